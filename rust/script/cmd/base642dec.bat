@@ -1,2 +0,0 @@
-@convertbase -f 64 -t 10 %*
-
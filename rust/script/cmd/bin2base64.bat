@@ -1,2 +0,0 @@
-@convertbase -f 2 -t 64 %*
-
@@ -1,2 +0,0 @@
-@convertbase -f 10 -t 2 %*
-
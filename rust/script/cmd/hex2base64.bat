@@ -1,2 +0,0 @@
-@convertbase -f 16 -t 64 %*
-
@@ -0,0 +1,396 @@
+use crate::codec::{BlockCodec, Codec, DecodeError, DecodeErrorKind};
+use crate::decimal::{bytes_to_limbs, divide, limbs_to_bytes, multiply_add};
+
+const RFC4648_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+// RFC 4648 section 7, which keeps the sort order of the encoded data
+const EXTENDED_HEX_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+// https://philzimmermann.com/docs/human-oriented-base-32-encoding.txt
+const Z_BASE_32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+// https://www.crockford.com/base32.html
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+// The check symbol is the value modulo 37, so needs five more symbols than the digits
+const CROCKFORD_CHECK_SYMBOLS: &[u8; 5] = b"*~$=U";
+const CROCKFORD_CHECK_MODULUS: u32 = 37;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Rfc4648,
+    ExtendedHex,
+    ZBase32,
+}
+
+// The variants which split the input into groups of five bits, with every five bytes encoded as
+// eight characters
+#[derive(Clone, Debug)]
+pub struct Base32 {
+    pub variant: Variant,
+    // Whether the output is padded with '=' to a multiple of eight characters. Unpadded input is
+    // only accepted when this is false, but padded input is always accepted.
+    pub padding: bool,
+    // Whether whitespace and hyphens in the input are ignored, as in "JBSW Y3DP EHPK 3PXP"
+    pub lenient: bool,
+    pub lowercase: bool,
+}
+
+impl Default for Base32 {
+    fn default() -> Base32 {
+        Base32::new(Variant::Rfc4648)
+    }
+}
+
+impl Base32 {
+    pub fn new(variant: Variant) -> Base32 {
+        match variant {
+            // z-base-32 is meant to be read by people, so is lowercase and never padded
+            Variant::ZBase32 => Base32 { variant, padding: false, lenient: false, lowercase: true },
+            _ => Base32 { variant, padding: true, lenient: false, lowercase: false },
+        }
+    }
+
+    pub fn hex() -> Base32 {
+        Base32::new(Variant::ExtendedHex)
+    }
+
+    pub fn z_base_32() -> Base32 {
+        Base32::new(Variant::ZBase32)
+    }
+
+    fn alphabet(&self) -> &'static [u8; 32] {
+        match self.variant {
+            Variant::Rfc4648 => RFC4648_ALPHABET,
+            Variant::ExtendedHex => EXTENDED_HEX_ALPHABET,
+            Variant::ZBase32 => Z_BASE_32_ALPHABET,
+        }
+    }
+
+    // None of the alphabets have both cases of a letter, so either case is accepted
+    fn char_value(&self, c: u8) -> Option<u8> {
+        self.alphabet()
+            .iter()
+            .position(|digit| digit.eq_ignore_ascii_case(&c))
+            .map(|value| value as u8)
+    }
+
+    fn is_separator(&self, c: u8) -> bool {
+        self.lenient && (c.is_ascii_whitespace() || (c == b'-'))
+    }
+}
+
+impl Codec for Base32 {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Variant::Rfc4648 => "base32",
+            Variant::ExtendedHex => "base32hex",
+            Variant::ZBase32 => "zbase32",
+        }
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match self.variant {
+            Variant::Rfc4648 => &["32", "b32"],
+            Variant::ExtendedHex => &["32hex", "b32hex"],
+            Variant::ZBase32 => &["z-base-32", "z32"],
+        }
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("padding", "pad the output with '=' (the default except for zbase32)"),
+            ("no-padding", "don't pad the output, and accept input without padding"),
+            ("lenient", "ignore whitespace and hyphens in the input"),
+            ("lowercase", "output lowercase letters (the default for zbase32)"),
+            ("uppercase", "output uppercase letters"),
+        ]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "padding" => self.padding = true,
+            "no-padding" => self.padding = false,
+            "lenient" => self.lenient = true,
+            "lowercase" => self.lowercase = true,
+            "uppercase" => self.lowercase = false,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let alphabet = self.alphabet();
+        let mut output = Vec::with_capacity(input.len().div_ceil(5) * 8);
+        for chunk in input.chunks(5) {
+            let mut group = [0u8; 8];
+            group[3..(3 + chunk.len())].copy_from_slice(chunk);
+            let current = u64::from_be_bytes(group);
+
+            // n bytes need enough characters to hold all of their 8n bits
+            let char_count = (chunk.len() * 8).div_ceil(5);
+            for i in 0..char_count {
+                let index = (current >> (35 - (5 * i))) & 0x1F;
+                output.push(alphabet[index as usize]);
+            }
+            if self.padding {
+                output.resize(output.len() + 8 - char_count, b'=');
+            }
+        }
+
+        if self.lowercase {
+            output.make_ascii_lowercase();
+        } else {
+            output.make_ascii_uppercase();
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        // The offset of each character that's part of the encoding, so that errors can point at
+        // the right place in the original input
+        let bytes = input.as_bytes();
+        let significant: Vec<usize> = (0..bytes.len()).filter(|&i| !self.is_separator(bytes[i])).collect();
+
+        let padding_count = significant.iter().rev().take_while(|&&i| bytes[i] == b'=').count();
+        let padding_start = significant.len() - padding_count;
+        if (self.padding || (padding_count > 0)) && !significant.len().is_multiple_of(8) {
+            return Err(DecodeError::end(DecodeErrorKind::BadLength, input));
+        }
+        // Only these numbers of characters in the final group hold a whole number of bytes
+        if ![0, 2, 4, 5, 7].contains(&(padding_start % 8)) {
+            return match padding_count {
+                0 => Err(DecodeError::end(DecodeErrorKind::BadLength, input)),
+                _ => Err(DecodeError::at(DecodeErrorKind::BadPadding, input, significant[padding_start])),
+            };
+        }
+
+        let mut result = Vec::with_capacity(significant.len() / 8 * 5);
+        let mut accumulated_bits = 0;
+        let mut accumulated: u32 = 0;
+        for &i in &significant[..padding_start] {
+            match self.char_value(bytes[i]) {
+                Some(value) => {
+                    accumulated = (accumulated << 5) | (value as u32);
+                    accumulated_bits += 5;
+                },
+                None if bytes[i] == b'=' => {
+                    return Err(DecodeError::at(DecodeErrorKind::BadPadding, input, i));
+                },
+                None => {
+                    return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i));
+                }
+            }
+
+            if accumulated_bits >= 8 {
+                accumulated_bits -= 8;
+                result.push((accumulated >> accumulated_bits) as u8);
+                accumulated &= (1 << accumulated_bits) - 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl BlockCodec for Base32 {
+    fn block_sizes(&self) -> (usize, usize) {
+        (5, 8)
+    }
+
+    fn is_ignorable(&self, c: u8) -> bool {
+        c.is_ascii_whitespace() || self.is_separator(c)
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        output.extend_from_slice(self.encode(input).as_bytes());
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        // The stream decoder has already rejected anything that isn't ASCII
+        let block = std::str::from_utf8(input).unwrap();
+        match self.decode(block) {
+            Ok(decoded) => output.extend_from_slice(&decoded),
+            Err(error) => return Err((error.offset, error.kind)),
+        }
+        Ok(())
+    }
+
+    fn is_padded(&self, block: &[u8]) -> bool {
+        block.last() == Some(&b'=')
+    }
+}
+
+// Crockford's base 32 writes a number rather than a stream of bits, so it works like decimal but
+// with 32 digits. Decoding ignores case and hyphens, and reads the easily confused I, L and O as
+// 1, 1 and 0.
+#[derive(Clone, Debug, Default)]
+pub struct Crockford {
+    // Whether a check symbol follows the digits, to catch mistyped values
+    pub check: bool,
+}
+
+impl Crockford {
+    fn char_value(c: u8) -> Option<u32> {
+        let c = match c.to_ascii_uppercase() {
+            b'O' => b'0',
+            b'I' | b'L' => b'1',
+            c => c,
+        };
+        CROCKFORD_ALPHABET.iter().position(|&digit| digit == c).map(|value| value as u32)
+    }
+
+    fn check_symbol_value(c: u8) -> Option<u32> {
+        match CROCKFORD_CHECK_SYMBOLS.iter().position(|symbol| symbol.eq_ignore_ascii_case(&c)) {
+            Some(position) => Some(32 + position as u32),
+            None => Crockford::char_value(c),
+        }
+    }
+
+    fn check_value(value: &[u32]) -> u32 {
+        divide(&mut value.to_vec(), CROCKFORD_CHECK_MODULUS)
+    }
+}
+
+impl Codec for Crockford {
+    fn name(&self) -> &'static str {
+        "crockford"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["base32crockford", "c32"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("check", "append a check symbol to the output, and require one on the input")]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "check" => self.check = true,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let mut value = bytes_to_limbs(input);
+        let check_value = Crockford::check_value(&value) as usize;
+        let mut output = Vec::new();
+        while !value.is_empty() {
+            output.push(CROCKFORD_ALPHABET[divide(&mut value, 32) as usize]);
+        }
+        if output.is_empty() {
+            output.push(CROCKFORD_ALPHABET[0]);
+        }
+        output.reverse();
+
+        if self.check {
+            output.push(match check_value {
+                0..=31 => CROCKFORD_ALPHABET[check_value],
+                _ => CROCKFORD_CHECK_SYMBOLS[check_value - 32],
+            });
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        let bytes = input.as_bytes();
+        let significant: Vec<usize> = (0..bytes.len()).filter(|&i| bytes[i] != b'-').collect();
+        let (digits, check) = match (self.check, significant.split_last()) {
+            (true, Some((&check, digits))) => (digits, Some(check)),
+            (true, None) => return Err(DecodeError::end(DecodeErrorKind::BadLength, input)),
+            (false, _) => (&significant[..], None),
+        };
+
+        let mut value = Vec::with_capacity(digits.len() / 6 + 1);
+        for &i in digits {
+            match Crockford::char_value(bytes[i]) {
+                Some(digit) => multiply_add(&mut value, 32, digit),
+                None => return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i)),
+            }
+        }
+
+        if let Some(i) = check {
+            match Crockford::check_symbol_value(bytes[i]) {
+                Some(symbol) if symbol == Crockford::check_value(&value) => {},
+                Some(_) => return Err(DecodeError::at(DecodeErrorKind::BadChecksum, input, i)),
+                None => return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i)),
+            }
+        }
+
+        Ok(limbs_to_bytes(&value))
+    }
+}
+
+#[cfg(test)]
+mod base32_tests {
+    use super::*;
+
+    // RFC 4648 section 10
+    #[test]
+    fn rfc4648_test_vectors() {
+        let vectors = [
+            ("", "", ""),
+            ("f", "MY======", "CO======"),
+            ("fo", "MZXQ====", "CPNG===="),
+            ("foo", "MZXW6===", "CPNMU==="),
+            ("foob", "MZXW6YQ=", "CPNMUOG="),
+            ("fooba", "MZXW6YTB", "CPNMUOJ1"),
+            ("foobar", "MZXW6YTBOI======", "CPNMUOJ1E8======"),
+        ];
+        for (input, base32, base32hex) in vectors.iter() {
+            assert_eq!(*base32, Base32::default().encode(input.as_bytes()));
+            assert_eq!(*base32hex, Base32::hex().encode(input.as_bytes()));
+            assert_eq!(input.as_bytes().to_vec(), Base32::default().decode(base32).unwrap());
+            assert_eq!(input.as_bytes().to_vec(), Base32::hex().decode(base32hex).unwrap());
+        }
+    }
+
+    #[test]
+    fn unpadded_and_lenient() {
+        let mut codec = Base32::default();
+        codec.set_option("no-padding").unwrap();
+        codec.set_option("lenient").unwrap();
+        assert_eq!("MZXW6YQ", codec.encode(b"foob"));
+        assert_eq!(b"Hello!\xde\xad\xbe\xef".to_vec(), codec.decode("jbsw y3dp eHPK-3PXP").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadLength, offset: 3, character: None },
+            codec.decode("MZX").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadLength, offset: 7, character: None },
+            Base32::default().decode("MZXW6YQ").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 2, character: Some('1') },
+            Base32::default().decode("MZ1W6YQ=").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn z_base_32() {
+        let codec = Base32::z_base_32();
+        assert_eq!("ca", codec.encode(&[0x66]));
+        assert_eq!("pb1sa5dxfoo8q551pt1y", codec.encode(b"hello, world"));
+        assert_eq!(b"hello, world".to_vec(), codec.decode("PB1SA5DXFOO8Q551PT1Y").unwrap());
+    }
+
+    #[test]
+    fn crockford_with_check_symbol() {
+        let mut codec = Crockford::default();
+        assert_eq!("3FAVFQF", codec.encode(&[0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(vec![0xDE, 0xAD, 0xBE, 0xEF], codec.decode("3fav-fqf").unwrap());
+        assert_eq!(vec![0x04, 0xD2], codec.decode("i6J").unwrap());
+        assert_eq!("0", codec.encode(&[]));
+
+        codec.set_option("check").unwrap();
+        // 1234 % 37 = 13
+        assert_eq!("16JD", codec.encode(&[0x04, 0xD2]));
+        assert_eq!(vec![0x04, 0xD2], codec.decode("16-jd").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadChecksum, offset: 3, character: Some('E') },
+            codec.decode("16JE").unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::codec::{Codec, DecodeError, DecodeErrorKind};
+use crate::radix::Radix;
+use sha2::{Digest, Sha256};
+
+// Base 62 without 0, O, I and l, which are easily confused
+const BITCOIN_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+// The same digits with the cases swapped, as used in Flickr's short URLs
+const FLICKR_ALPHABET: &[u8] = b"123456789abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ";
+
+// Base58Check appends the first four bytes of the double SHA-256 of the payload
+const CHECKSUM_LENGTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alphabet {
+    Bitcoin,
+    Flickr,
+}
+
+// A number in base 58, except that each leading zero byte is kept as a leading zero digit ('1'),
+// so that every byte string has its own encoding
+#[derive(Clone, Debug)]
+pub struct Base58 {
+    pub alphabet: Alphabet,
+    // Whether this is Base58Check, with a checksum after the payload
+    pub check: bool,
+}
+
+impl Default for Base58 {
+    fn default() -> Base58 {
+        Base58 { alphabet: Alphabet::Bitcoin, check: false }
+    }
+}
+
+impl Base58 {
+    pub fn flickr() -> Base58 {
+        Base58 { alphabet: Alphabet::Flickr, ..Base58::default() }
+    }
+
+    pub fn check() -> Base58 {
+        Base58 { check: true, ..Base58::default() }
+    }
+
+    fn radix(&self) -> Radix {
+        match self.alphabet {
+            Alphabet::Bitcoin => Radix::with_alphabet(self.name(), BITCOIN_ALPHABET),
+            Alphabet::Flickr => Radix::with_alphabet(self.name(), FLICKR_ALPHABET),
+        }
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let hash = Sha256::digest(Sha256::digest(payload));
+    let mut checksum = [0; CHECKSUM_LENGTH];
+    checksum.copy_from_slice(&hash[..CHECKSUM_LENGTH]);
+    checksum
+}
+
+impl Codec for Base58 {
+    fn name(&self) -> &'static str {
+        match (self.alphabet, self.check) {
+            (_, true) => "base58check",
+            (Alphabet::Bitcoin, false) => "base58",
+            (Alphabet::Flickr, false) => "base58flickr",
+        }
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match (self.alphabet, self.check) {
+            (_, true) => &["58check", "b58check"],
+            (Alphabet::Bitcoin, false) => &["58", "b58"],
+            (Alphabet::Flickr, false) => &["58flickr", "b58flickr"],
+        }
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("flickr", "use Flickr's alphabet, with lowercase letters before uppercase"),
+            ("check", "append a double SHA-256 checksum as Base58Check does, and verify it on the input"),
+        ]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "flickr" => self.alphabet = Alphabet::Flickr,
+            "check" => self.check = true,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let mut data = input.to_vec();
+        if self.check {
+            data.extend_from_slice(&checksum(input));
+        }
+
+        let radix = self.radix();
+        let leading_zeros = data.iter().take_while(|&&b| b == 0).count();
+        let mut output = String::from_utf8(vec![radix.alphabet()[0]; leading_zeros]).unwrap();
+        if leading_zeros < data.len() {
+            output.push_str(&radix.encode(&data[leading_zeros..]));
+        }
+        output
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        let radix = self.radix();
+        let leading_zeros = input.bytes().take_while(|&c| c == radix.alphabet()[0]).count();
+        let mut result = vec![0; leading_zeros];
+        match radix.decode(&input[leading_zeros..]) {
+            Ok(value) => result.extend_from_slice(&value),
+            Err(error) => return Err(DecodeError::at(error.kind, input, leading_zeros + error.offset)),
+        }
+
+        if self.check {
+            if result.len() < CHECKSUM_LENGTH {
+                return Err(DecodeError::end(DecodeErrorKind::BadLength, input));
+            }
+            let expected = result.split_off(result.len() - CHECKSUM_LENGTH);
+            if expected != checksum(&result) {
+                return Err(DecodeError::end(DecodeErrorKind::BadChecksum, input));
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod base58_tests {
+    use super::*;
+
+    #[test]
+    fn leading_zeros_are_kept() {
+        let codec = Base58::default();
+        assert_eq!("2NEpo7TZRRrLZSi2U", codec.encode(b"Hello World!"));
+        assert_eq!("111233QC4", codec.encode(&[0x00, 0x00, 0x00, 0x28, 0x7F, 0xB4, 0xCD]));
+        assert_eq!(vec![0x00, 0x00, 0x00, 0x28, 0x7F, 0xB4, 0xCD], codec.decode("111233QC4").unwrap());
+        assert_eq!("", codec.encode(&[]));
+        assert_eq!(vec![0x00], codec.decode("1").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 4, character: Some('0') },
+            codec.decode("1123033").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn flickr_alphabet() {
+        let codec = Base58::flickr();
+        assert_eq!("2nePN7syqqRkyrH2t", codec.encode(b"Hello World!"));
+        assert_eq!(b"Hello World!".to_vec(), codec.decode("2nePN7syqqRkyrH2t").unwrap());
+    }
+
+    #[test]
+    fn base58check() {
+        let codec = Base58::check();
+        // The version byte and public key hash of a bitcoin address
+        let payload = [
+            0x00, 0x01, 0x09, 0x66, 0x77, 0x60, 0x06, 0x95, 0x3D, 0x55, 0x67,
+            0x43, 0x9E, 0x5E, 0x39, 0xF8, 0x6A, 0x0D, 0x27, 0x3B, 0xEE,
+        ];
+        assert_eq!("16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvM", codec.encode(&payload));
+        assert_eq!(payload.to_vec(), codec.decode("16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvM").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadChecksum, offset: 33, character: None },
+            codec.decode("16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvN").unwrap_err()
+        );
+    }
+}
@@ -1,108 +1,242 @@
+use crate::codec::{BlockCodec, Codec, DecodeError, DecodeErrorKind};
+
+const STANDARD_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// RFC 4648 section 5, for use in URLs and filenames
+const URL_SAFE_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// RFC 2045 section 6.8: Encoded lines must be no more than 76 characters long
+pub const MIME_LINE_LENGTH: usize = 76;
+
 enum Base64CharParseResult {
     Value(u8),
     Padding,
     Unknown
 }
 
-fn parse_base64_char(c: u8) -> Base64CharParseResult {
-    return match c {
-        b'A'..=b'Z' => Base64CharParseResult::Value(c - b'A'),
-        b'a'..=b'z' => Base64CharParseResult::Value(c - b'a' + 26),
-        b'0'..=b'9' => Base64CharParseResult::Value(c - b'0' + 52),
-        b'+' => Base64CharParseResult::Value(62),
-        b'/' => Base64CharParseResult::Value(63),
-        b'=' => Base64CharParseResult::Padding,
+fn parse_base64_char(c: u8, url_safe: bool) -> Base64CharParseResult {
+    return match (c, url_safe) {
+        (b'A'..=b'Z', _) => Base64CharParseResult::Value(c - b'A'),
+        (b'a'..=b'z', _) => Base64CharParseResult::Value(c - b'a' + 26),
+        (b'0'..=b'9', _) => Base64CharParseResult::Value(c - b'0' + 52),
+        (b'+', false) | (b'-', true) => Base64CharParseResult::Value(62),
+        (b'/', false) | (b'_', true) => Base64CharParseResult::Value(63),
+        (b'=', _) => Base64CharParseResult::Padding,
         _ => Base64CharParseResult::Unknown
     }
 }
 
-pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
-    if input.len() == 0 {
-        return Some(vec![]);
+pub fn to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
+    Base64::default().decode(input)
+}
+
+pub fn from_bytes(input: &[u8]) -> String {
+    Base64::default().encode(input)
+}
+
+#[derive(Clone, Debug)]
+pub struct Base64 {
+    pub url_safe: bool,
+    // Whether the output is padded with '=' to a multiple of four characters. Unpadded input is
+    // only accepted when this is false, but padded input is always accepted.
+    pub padding: bool,
+    // Whether whitespace (including line breaks) in the input is ignored
+    pub lenient: bool,
+    // Output is split into lines of this many characters, if set, separated by CRLF as in MIME
+    pub line_length: Option<usize>,
+}
+
+impl Default for Base64 {
+    fn default() -> Base64 {
+        Base64 { url_safe: false, padding: true, lenient: false, line_length: None }
     }
+}
 
-    if input.len() % 4 != 0 {
-        return None;
+impl Base64 {
+    // The variant usually used in URLs (e.g in JWTs), which is unpadded
+    pub fn url() -> Base64 {
+        Base64 { url_safe: true, padding: false, ..Base64::default() }
     }
 
-    let bytes = input.as_bytes();
-    let mut result = Vec::with_capacity(bytes.len()); // TODO: This could be *(3/4)
-    let mut accumulated_bits = 0;
-    let mut accumulated = 0;
-    for i in 0..bytes.len() {
-        let parse_result = parse_base64_char(bytes[i]);
-        match parse_result {
-            Base64CharParseResult::Value(val) => {
-                accumulated = (accumulated << 6) | (val as u32);
-                accumulated_bits += 6;
-            },
-            Base64CharParseResult::Padding => (),
-            Base64CharParseResult::Unknown => panic!("Unrecognized base64 char")
+    pub fn mime() -> Base64 {
+        Base64 { lenient: true, line_length: Some(MIME_LINE_LENGTH), ..Base64::default() }
+    }
+
+    fn alphabet(&self) -> &'static [u8; 64] {
+        match self.url_safe {
+            true => URL_SAFE_ALPHABET,
+            false => STANDARD_ALPHABET
         }
+    }
+}
 
-        while accumulated_bits >= 8 {
-            let out = (accumulated >> (accumulated_bits-8) & 0xFF) as u8;
-            result.push(out);
-            accumulated_bits -= 8;
-            accumulated &= !((0xFF as u32) << accumulated_bits);
+impl Codec for Base64 {
+    fn name(&self) -> &'static str {
+        match self.url_safe {
+            true => "base64url",
+            false => "base64"
         }
     }
 
-    Some(result)
-}
+    fn aliases(&self) -> &'static [&'static str] {
+        match self.url_safe {
+            true => &["64url", "b64url"],
+            false => &["64", "b64"]
+        }
+    }
 
-pub fn from_bytes(input: &[u8]) -> String {
-    const HEX: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let mut output = Vec::with_capacity(input.len()); // TODO: This should be * 4/3
-    let mut bits_available = 0;
-    let mut current: u32 = 0;
-
-    for b in input {
-        current = (current << 8) | (*b as u32);
-        bits_available += 8;
-        if bits_available == 24 {
-            while bits_available >= 6 {
-                bits_available -= 6;
-                let mask = 0x3F << bits_available;
-                let new_out = ((current & mask) >> bits_available) as usize;
-                current = current & !mask;
-                output.push(HEX[new_out]);
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("url", "use the URL-safe alphabet, with - and _ in place of + and /"),
+            ("padding", "pad the output with '=' (the default except for base64url)"),
+            ("no-padding", "don't pad the output, and accept input without padding"),
+            ("lenient", "ignore whitespace and line breaks in the input"),
+            ("mime", "wrap the output at 76 columns as MIME does (implies lenient)"),
+        ]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "url" => self.url_safe = true,
+            "padding" => self.padding = true,
+            "no-padding" => self.padding = false,
+            "lenient" => self.lenient = true,
+            "mime" => {
+                self.lenient = true;
+                self.line_length = Some(MIME_LINE_LENGTH);
+            },
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let alphabet = self.alphabet();
+        let mut output = Vec::with_capacity(input.len().div_ceil(3) * 4);
+        for chunk in input.chunks(3) {
+            let mut group = [0u8; 3];
+            group[..chunk.len()].copy_from_slice(chunk);
+            let current = ((group[0] as u32) << 16) | ((group[1] as u32) << 8) | (group[2] as u32);
+
+            // n bytes need n+1 characters to hold all of their bits
+            for i in 0..(chunk.len() + 1) {
+                let index = (current >> (18 - (6 * i))) & 0x3F;
+                output.push(alphabet[index as usize]);
+            }
+            if self.padding {
+                output.resize(output.len() + 3 - chunk.len(), b'=');
             }
         }
+
+        if let Some(line_length) = self.line_length {
+            if line_length > 0 {
+                output = output.chunks(line_length).collect::<Vec<_>>().join(&b"\r\n"[..]);
+            }
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        output
     }
 
-    if bits_available == 16 {
-        current <<= 8;
-        bits_available += 8;
-        for _ in 0..3 {
-            bits_available -= 6;
-            let mask = 0x3F << bits_available;
-            let new_out = ((current & mask) >> bits_available) as usize;
-            current = current & !mask;
-            output.push(HEX[new_out]);
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        // The offset of each character that's part of the encoding, so that errors can point at
+        // the right place in the original input
+        let bytes = input.as_bytes();
+        let significant: Vec<usize> = (0..bytes.len())
+            .filter(|&i| !(self.lenient && bytes[i].is_ascii_whitespace()))
+            .collect();
+
+        // Only the final group of four can be padded, and it always has at least two real characters
+        let padding_count = significant.iter().rev().take_while(|&&i| bytes[i] == b'=').count();
+        let padding_start = significant.len() - padding_count;
+        if padding_count > 2 {
+            return Err(DecodeError::at(DecodeErrorKind::BadPadding, input, significant[padding_start]));
         }
-        output.push(b'=');
-        bits_available = 0;
-
-    } else if bits_available == 8 {
-        current <<= 16;
-        bits_available += 16;
-        for _ in 0..2 {
-            bits_available -= 6;
-            let mask = 0x3F << bits_available;
-            let new_out = ((current & mask) >> bits_available) as usize;
-            current = current & !mask;
-            output.push(HEX[new_out]);
+        let length_ok = match (self.padding, padding_count) {
+            (true, _) | (false, 1..=2) => significant.len().is_multiple_of(4),
+            // A single character in the final group isn't enough for even one byte
+            (false, _) => significant.len() % 4 != 1,
+        };
+        if !length_ok {
+            return Err(DecodeError::end(DecodeErrorKind::BadLength, input));
         }
-        output.push(b'=');
-        output.push(b'=');
-        bits_available = 0;
+
+        let mut result = Vec::with_capacity(significant.len() / 4 * 3);
+        let mut accumulated_bits = 0;
+        let mut accumulated = 0;
+        for &i in &significant[..padding_start] {
+            let parse_result = parse_base64_char(bytes[i], self.url_safe);
+            match parse_result {
+                Base64CharParseResult::Value(val) => {
+                    accumulated = (accumulated << 6) | (val as u32);
+                    accumulated_bits += 6;
+                },
+                Base64CharParseResult::Padding => {
+                    return Err(DecodeError::at(DecodeErrorKind::BadPadding, input, i));
+                },
+                Base64CharParseResult::Unknown => {
+                    return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i));
+                }
+            }
+
+            while accumulated_bits >= 8 {
+                let out = (accumulated >> (accumulated_bits-8) & 0xFF) as u8;
+                result.push(out);
+                accumulated_bits -= 8;
+                accumulated &= !((0xFF as u32) << accumulated_bits);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl BlockCodec for Base64 {
+    fn block_sizes(&self) -> (usize, usize) {
+        (3, 4)
     }
 
-    assert!(bits_available == 0, "Invalid number of bits: {}", bits_available);
+    fn line_length(&self) -> Option<usize> {
+        self.line_length
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        let unwrapped = Base64 { line_length: None, ..self.clone() };
+        output.extend_from_slice(unwrapped.encode(input).as_bytes());
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        let padding_count = input.iter().rev().take_while(|&&c| c == b'=').count();
+        let padding_start = input.len() - padding_count;
+        if (padding_count > 2) || ((padding_count > 0) && (input.len() != 4)) {
+            return Err((padding_start, DecodeErrorKind::BadPadding));
+        }
+        if (input.len() < 4) && (self.padding || (input.len() == 1)) {
+            return Err((input.len(), DecodeErrorKind::BadLength));
+        }
 
-    let output = String::from_utf8(output).unwrap();
-    output
+        let mut accumulated: u32 = 0;
+        for (i, c) in input[..padding_start].iter().enumerate() {
+            match parse_base64_char(*c, self.url_safe) {
+                Base64CharParseResult::Value(val) => accumulated |= (val as u32) << (18 - (6 * i)),
+                Base64CharParseResult::Padding => return Err((i, DecodeErrorKind::BadPadding)),
+                Base64CharParseResult::Unknown => return Err((i, DecodeErrorKind::InvalidDigit))
+            }
+        }
+
+        // n characters hold the bits of n-1 bytes
+        for i in 0..(padding_start - 1) {
+            output.push((accumulated >> (16 - (8 * i))) as u8);
+        }
+        Ok(())
+    }
+
+    fn is_padded(&self, block: &[u8]) -> bool {
+        block.last() == Some(&b'=')
+    }
 }
 
 #[cfg(test)]
@@ -139,6 +273,78 @@ mod base64_tests {
         );
     }
 
+    #[test]
+    fn base642bytes_errors() {
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadLength, offset: 7, character: None },
+            to_bytes("3q2+7w=").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadPadding, offset: 2, character: Some('=') },
+            to_bytes("3q=+7w==").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadPadding, offset: 5, character: Some('=') },
+            to_bytes("3q2+7===").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 4, character: Some('é') },
+            to_bytes("3q2+é==").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn base642bytes_url_safe_unpadded() {
+        let input = "3q2-7_8";
+        let output = Base64::url().decode(input).unwrap();
+        assert_eq!(
+            vec![0xDE, 0xAD, 0xBE, 0xEF, 0xFF],
+            output
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 3, character: Some('+') },
+            Base64::url().decode("3q2+7w").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadLength, offset: 5, character: None },
+            Base64::url().decode("3q2+7").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn base642bytes_lenient() {
+        let input = " 3q2+\r\n7w ==\n";
+        let output = Base64::mime().decode(input).unwrap();
+        assert_eq!(
+            vec![0xDE, 0xAD, 0xBE, 0xEF],
+            output
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 0, character: Some(' ') },
+            to_bytes(" 3q2").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn bytes2base64_url_safe_unpadded() {
+        let input = vec![0xDE, 0xAD, 0xBE, 0xEF, 0xFF];
+        let output = Base64::url().encode(&input);
+        assert_eq!(
+            "3q2-7_8",
+            output
+        );
+    }
+
+    #[test]
+    fn bytes2base64_mime_wrapped() {
+        let input = vec![0; 60];
+        let output = Base64::mime().encode(&input);
+        assert_eq!(
+            format!("{}\r\n{}", "A".repeat(76), "A".repeat(4)),
+            output
+        );
+    }
+
     #[test]
     fn bytes2base64_two_padding_bytes() {
         let input = vec![0xDE, 0xAD, 0xBE, 0xEF];
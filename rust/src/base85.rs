@@ -0,0 +1,271 @@
+use crate::codec::{BlockCodec, Codec, DecodeError, DecodeErrorKind};
+
+// https://rfc.zeromq.org/spec/32/
+const Z85_ALPHABET: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+// RFC 1924, which git also uses for binary patches
+const RFC1924_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+// Ascii85 uses the characters from '!' onwards, and writes a group of four zero bytes as 'z'
+const ASCII85_FIRST_DIGIT: u8 = b'!';
+const ASCII85_ZERO_GROUP: u8 = b'z';
+const ASCII85_START: &str = "<~";
+const ASCII85_END: &str = "~>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Ascii85,
+    Z85,
+    Rfc1924,
+}
+
+// Every four bytes are read as a big-endian number and written as five base 85 digits. A final
+// group of n bytes is padded with zeros and only the first n+1 digits are written, which Z85
+// itself doesn't allow but is accepted (and produced) here so that any input can be encoded.
+#[derive(Clone, Debug)]
+pub struct Base85 {
+    pub variant: Variant,
+    // Whether Ascii85 output is wrapped in <~ and ~> as Adobe does. They're optional on input.
+    pub delimiters: bool,
+}
+
+impl Default for Base85 {
+    fn default() -> Base85 {
+        Base85::new(Variant::Ascii85)
+    }
+}
+
+impl Base85 {
+    pub fn new(variant: Variant) -> Base85 {
+        Base85 { variant, delimiters: variant == Variant::Ascii85 }
+    }
+
+    pub fn z85() -> Base85 {
+        Base85::new(Variant::Z85)
+    }
+
+    pub fn rfc1924() -> Base85 {
+        Base85::new(Variant::Rfc1924)
+    }
+
+    fn digit(&self, value: u32) -> u8 {
+        match self.variant {
+            Variant::Ascii85 => ASCII85_FIRST_DIGIT + (value as u8),
+            Variant::Z85 => Z85_ALPHABET[value as usize],
+            Variant::Rfc1924 => RFC1924_ALPHABET[value as usize],
+        }
+    }
+
+    fn digit_value(&self, c: u8) -> Option<u32> {
+        let alphabet = match self.variant {
+            Variant::Ascii85 if (ASCII85_FIRST_DIGIT..(ASCII85_FIRST_DIGIT + 85)).contains(&c) => {
+                return Some((c - ASCII85_FIRST_DIGIT) as u32);
+            },
+            Variant::Ascii85 => return None,
+            Variant::Z85 => Z85_ALPHABET,
+            Variant::Rfc1924 => RFC1924_ALPHABET,
+        };
+        alphabet.iter().position(|&digit| digit == c).map(|value| value as u32)
+    }
+
+    fn encode_group(&self, chunk: &[u8], output: &mut Vec<u8>) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        if (self.variant == Variant::Ascii85) && (chunk.len() == 4) && (value == 0) {
+            output.push(ASCII85_ZERO_GROUP);
+            return;
+        }
+
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = self.digit(value % 85);
+            value /= 85;
+        }
+        output.extend_from_slice(&digits[..(chunk.len() + 1)]);
+    }
+
+    // Decodes the characters at the given offsets of the input, which for Ascii85 have already had
+    // whitespace and delimiters removed
+    fn decode_significant(&self, input: &str, significant: &[usize]) -> Result<Vec<u8>, DecodeError> {
+        let bytes = input.as_bytes();
+        let mut result = Vec::with_capacity(significant.len() / 5 * 4);
+        let mut group = Vec::with_capacity(5);
+        for (n, &i) in significant.iter().enumerate() {
+            if (self.variant == Variant::Ascii85) && (bytes[i] == ASCII85_ZERO_GROUP) && group.is_empty() {
+                result.extend_from_slice(&[0; 4]);
+                continue;
+            }
+            match self.digit_value(bytes[i]) {
+                Some(value) => group.push(value),
+                None => return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i)),
+            }
+            if group.len() == 5 || (n == significant.len() - 1) {
+                let group_start = significant[n + 1 - group.len()];
+                decode_group(&group, &mut result).map_err(|kind| match kind {
+                    DecodeErrorKind::BadLength => DecodeError::end(kind, input),
+                    _ => DecodeError::at(kind, input, group_start),
+                })?;
+                group.clear();
+            }
+        }
+        Ok(result)
+    }
+}
+
+// A final group of n digits is padded with the highest digit and gives n-1 bytes
+fn decode_group(group: &[u32], output: &mut Vec<u8>) -> Result<(), DecodeErrorKind> {
+    if group.len() == 1 {
+        return Err(DecodeErrorKind::BadLength);
+    }
+    let value = (0..5).fold(0u64, |value, i| (value * 85) + (*group.get(i).unwrap_or(&84) as u64));
+    if value > (u32::MAX as u64) {
+        return Err(DecodeErrorKind::Overflow);
+    }
+    output.extend_from_slice(&(value as u32).to_be_bytes()[..(group.len() - 1)]);
+    Ok(())
+}
+
+impl Codec for Base85 {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Variant::Ascii85 => "ascii85",
+            Variant::Z85 => "z85",
+            Variant::Rfc1924 => "base85",
+        }
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match self.variant {
+            Variant::Ascii85 => &["a85", "adobe85"],
+            Variant::Z85 => &["zeromq85"],
+            Variant::Rfc1924 => &["85", "b85", "rfc1924", "git85"],
+        }
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        match self.variant {
+            Variant::Ascii85 => &[("no-delimiters", "don't wrap the output in <~ and ~>")],
+            _ => &[],
+        }
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match (self.variant, option) {
+            (Variant::Ascii85, "no-delimiters") => self.delimiters = false,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let mut output = Vec::with_capacity(input.len().div_ceil(4) * 5 + 4);
+        if self.delimiters {
+            output.extend_from_slice(ASCII85_START.as_bytes());
+        }
+        for chunk in input.chunks(4) {
+            self.encode_group(chunk, &mut output);
+        }
+        if self.delimiters {
+            output.extend_from_slice(ASCII85_END.as_bytes());
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        let mut start = 0;
+        let mut end = input.len();
+        if self.variant == Variant::Ascii85 {
+            // Whitespace is ignored anywhere, including around the delimiters
+            let trimmed = input.trim();
+            start = input.len() - input.trim_start().len();
+            end = start + trimmed.len();
+            if trimmed.starts_with(ASCII85_START) {
+                start += ASCII85_START.len();
+            }
+            if input[start..end].ends_with(ASCII85_END) {
+                end -= ASCII85_END.len();
+            }
+        }
+
+        let bytes = input.as_bytes();
+        let significant: Vec<usize> = (start..end)
+            .filter(|&i| (self.variant != Variant::Ascii85) || !bytes[i].is_ascii_whitespace())
+            .collect();
+        self.decode_significant(input, &significant)
+    }
+
+    // Ascii85 groups aren't all the same length because of 'z', and it has an end delimiter
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        match self.variant {
+            Variant::Ascii85 => None,
+            _ => Some(Box::new(self.clone())),
+        }
+    }
+}
+
+impl BlockCodec for Base85 {
+    fn block_sizes(&self) -> (usize, usize) {
+        (4, 5)
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        self.encode_group(input, output);
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        let mut group = Vec::with_capacity(5);
+        for (i, c) in input.iter().enumerate() {
+            match self.digit_value(*c) {
+                Some(value) => group.push(value),
+                None => return Err((i, DecodeErrorKind::InvalidDigit)),
+            }
+        }
+        decode_group(&group, output).map_err(|kind| match kind {
+            DecodeErrorKind::BadLength => (input.len(), kind),
+            _ => (0, kind),
+        })
+    }
+}
+
+#[cfg(test)]
+mod base85_tests {
+    use super::*;
+
+    #[test]
+    fn ascii85_with_zero_groups() {
+        let codec = Base85::default();
+        assert_eq!("<~9jqo^zBla~>", codec.encode(b"Man \0\0\0\0is"));
+        assert_eq!(b"Man \0\0\0\0is".to_vec(), codec.decode(" <~9jqo^ zB\nla~>\n").unwrap());
+        assert_eq!(b"Man \0\0\0\0is".to_vec(), codec.decode("9jqo^zBla").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::Overflow, offset: 2, character: Some('s') },
+            codec.decode("<~s8W-\"~>").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 4, character: Some('z') },
+            codec.decode("<~9jzo^~>").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn z85_test_vector() {
+        let codec = Base85::z85();
+        let input = vec![0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B];
+        assert_eq!("HelloWorld", codec.encode(&input));
+        assert_eq!(input, codec.decode("HelloWorld").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::BadLength, offset: 6, character: None },
+            codec.decode("Hello0").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn rfc1924_partial_group() {
+        let codec = Base85::rfc1924();
+        assert_eq!("NM&qnZ!92pZ*pv8Ap", codec.encode(b"Hello, world!"));
+        assert_eq!(b"Hello, world!".to_vec(), codec.decode("NM&qnZ!92pZ*pv8Ap").unwrap());
+        assert_eq!(vec![0xFF; 4], codec.decode("|NsC0").unwrap());
+    }
+}
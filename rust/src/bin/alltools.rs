@@ -0,0 +1,235 @@
+use getopts::Options;
+use std::path::Path;
+use std::{env, fs, io, process};
+
+// Every tool is also compiled into this one executable so that it can be deployed on its own and
+// then run via symlinks named after each tool, the same way busybox works. The main() functions in
+// these modules are only used by the standalone executables.
+#[allow(dead_code)]
+#[path = "ascii.rs"]
+mod ascii;
+#[allow(dead_code)]
+#[path = "convertbase.rs"]
+mod convertbase;
+#[allow(dead_code)]
+#[path = "dns.rs"]
+mod dns;
+#[allow(dead_code)]
+#[path = "hex2utf8.rs"]
+mod hex2utf8;
+#[allow(dead_code)]
+#[path = "rainbow.rs"]
+mod rainbow;
+#[allow(dead_code)]
+#[path = "unix2utc.rs"]
+mod unix2utc;
+#[allow(dead_code)]
+#[path = "utc2unix.rs"]
+mod utc2unix;
+#[allow(dead_code)]
+#[path = "utf82hex.rs"]
+mod utf82hex;
+#[allow(dead_code)]
+#[path = "yinclude.rs"]
+mod yinclude;
+
+const PROGRAM_NAME: &str = "alltools";
+
+// Each tool's entry point, taking the full argument list including the program name
+type ToolMain = fn(Vec<String>);
+
+const TOOLS: &[(&str, ToolMain)] = &[
+    ("ascii", ascii::run),
+    ("convertbase", convertbase::run),
+    ("dns", dns::run),
+    ("hex2utf8", hex2utf8::run),
+    ("rainbow", rainbow::run),
+    ("unix2utc", unix2utc::run),
+    ("utc2unix", utc2unix::run),
+    ("utf82hex", utf82hex::run),
+    ("yinclude", yinclude::run),
+];
+
+// Shortcuts for common invocations of the tools above, which run the tool with the given
+// arguments followed by whatever arguments the alias itself was given.
+const ALIASES: &[(&str, &str, &[&str])] = &[
+    ("base642bin", "convertbase", &["-f", "64", "-t", "2"]),
+    ("base642dec", "convertbase", &["-f", "64", "-t", "10"]),
+    ("base642hex", "convertbase", &["-f", "64", "-t", "16"]),
+    ("bin2base64", "convertbase", &["-f", "2", "-t", "64"]),
+    ("bin2dec", "convertbase", &["-f", "2", "-t", "10"]),
+    ("bin2hex", "convertbase", &["-f", "2", "-t", "16"]),
+    ("dec2base64", "convertbase", &["-f", "10", "-t", "64"]),
+    ("dec2bin", "convertbase", &["-f", "10", "-t", "2"]),
+    ("dec2hex", "convertbase", &["-f", "10", "-t", "16"]),
+    ("hex2base64", "convertbase", &["-f", "16", "-t", "64"]),
+    ("hex2bin", "convertbase", &["-f", "16", "-t", "2"]),
+    ("hex2dec", "convertbase", &["-f", "16", "-t", "10"]),
+];
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!(
+        "Usage: {} COMMAND [ARGS]...\n       {} [OPTIONS]",
+        program, program
+    );
+    print!("{}", opts.usage(&brief));
+
+    println!();
+    println!("Commands can also be run by invoking this executable through a link with the name of the command (see --install)");
+}
+
+fn command_names() -> Vec<&'static str> {
+    TOOLS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(ALIASES.iter().map(|(name, _, _)| *name))
+        .collect()
+}
+
+// Runs the named tool or alias with the given arguments (the first of which is the program name
+// to show in usage messages), returning false if there is no command with that name
+fn run_command(name: &str, args: Vec<String>) -> bool {
+    if let Some((_, run)) = TOOLS.iter().find(|(tool, _)| *tool == name) {
+        run(args);
+        return true;
+    }
+
+    if let Some((_, tool, preset_args)) = ALIASES.iter().find(|(alias, _, _)| *alias == name) {
+        let mut tool_args = vec![args[0].clone()];
+        tool_args.extend(preset_args.iter().map(|arg| String::from(*arg)));
+        tool_args.extend(args.into_iter().skip(1));
+        return run_command(tool, tool_args);
+    }
+    false
+}
+
+fn install_link(target: &Path, link: &Path, force: bool) -> io::Result<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        if !force {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file already exists (use --force to replace it)",
+            ));
+        }
+        fs::remove_file(link)?;
+    }
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    // Creating symlinks on Windows needs special privileges, but hard links don't
+    #[cfg(not(unix))]
+    {
+        fs::hard_link(target, link)
+    }
+}
+
+fn install(dir: &str, force: bool) {
+    let target = match env::current_exe() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Failed to find the path of this executable: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+    let mut installed = 0;
+    for name in command_names() {
+        let link = Path::new(dir).join(format!("{}{}", name, env::consts::EXE_SUFFIX));
+        match install_link(&target, &link, force) {
+            Ok(()) => installed += 1,
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", link.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    println!(
+        "Installed {} commands into {} (linked to {})",
+        installed,
+        dir,
+        target.display()
+    );
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // When run through a link the name of the link is the command, otherwise it's the first argument
+    let invoked_name = Path::new(&args[0])
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if (invoked_name != PROGRAM_NAME) && run_command(&invoked_name, args.clone()) {
+        return;
+    }
+    if let Some(command) = args.get(1) {
+        if !command.starts_with('-') {
+            let mut command_args = vec![format!("{} {}", PROGRAM_NAME, command)];
+            command_args.extend(args.iter().skip(2).cloned());
+            if !run_command(command, command_args) {
+                eprintln!("Unknown command: {}", command);
+                eprintln!("Try '{} --list' for a list of commands", PROGRAM_NAME);
+                process::exit(1);
+            }
+            return;
+        }
+    }
+
+    let mut opts_spec = Options::new();
+    opts_spec.optflag("h", "help", "print this help menu");
+    opts_spec.optflag("l", "list", "list the available commands");
+    opts_spec.optopt(
+        "",
+        "install",
+        "create a link to this executable in DIR for every command",
+        "DIR",
+    );
+    opts_spec.optflag(
+        "",
+        "force",
+        "when installing, replace any files that already exist",
+    );
+    let opts = match opts_spec.parse(&args[1..]) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Try '{} --help' for more information", PROGRAM_NAME);
+            process::exit(1);
+        }
+    };
+
+    if opts.opt_present("h") {
+        println!("Run any of the tools in this collection from a single executable");
+        println!();
+
+        print_usage(PROGRAM_NAME, opts_spec);
+        return;
+    }
+
+    if opts.opt_present("l") {
+        for (name, _) in TOOLS {
+            println!("{}", name);
+        }
+        for (name, tool, preset_args) in ALIASES {
+            println!("{}  (alias for: {} {})", name, tool, preset_args.join(" "));
+        }
+        return;
+    }
+
+    match opts.opt_str("install") {
+        Some(dir) => install(&dir, opts.opt_present("force")),
+        None => {
+            eprintln!("No command provided");
+            eprintln!();
+            print_usage(PROGRAM_NAME, opts_spec);
+            process::exit(1);
+        }
+    }
+}
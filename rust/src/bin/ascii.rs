@@ -152,7 +152,10 @@ fn codepoint_name(codepoint: usize) -> Option<&'static str>
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
@@ -1,8 +1,13 @@
 use std::env;
-use std::io;
+use std::fs::File;
+use std::io::{self, Read, Write};
 use std::process;
 use std::string::String;
-use atty::Stream;
+use alltools::cli::{self, InputOptions};
+use alltools::codec::{self, Codec, DecodeError};
+use alltools::endian;
+use alltools::radix::{self, Radix};
+use alltools::stream::{Decoder, Encoder};
 use getopts::Options;
 
 fn print_usage(program: &str, opts: Options) {
@@ -11,13 +16,22 @@ fn print_usage(program: &str, opts: Options) {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
-    opts_spec.optflag("i", "interactive", "interactively read from standard input, converting each line");
+    cli::add_input_options(&mut opts_spec, "i", "F");
     opts_spec.optopt("f", "from", "the base from which to convert the input string", "FROM-BASE");
     opts_spec.optopt("t", "to", "the base to which to convert the output string", "TO-BASE");
+    opts_spec.optflag("s", "stream", "convert all of standard input (or each --file) as one stream, rather than line by line. This works for inputs of any size but only with bases that can be converted a piece at a time");
+    opts_spec.optmulti("o", "option", "an option for whichever of the input or output base has it (see below). Can be given more than once", "OPTION");
+    opts_spec.optmulti("", "from-option", "an option for the input base only, e.g. when both bases have it", "OPTION");
+    opts_spec.optmulti("", "to-option", "an option for the output base only, e.g. when both bases have it", "OPTION");
+    opts_spec.optopt("", "swap", "reverse the order of the bytes in each 16, 32 or 64-bit word before converting to the output base", "BITS");
+    opts_spec.optflag("b", "both-endian", "show the output for the bytes as they are (big-endian) and reversed (little-endian) side by side. With --swap, only the bytes within each word are reversed");
     let opts = match opts_spec.parse(&args[1..]) {
         Ok(o) => o,
         Err(e) => {
@@ -33,35 +47,29 @@ fn main() {
 
         print_usage(program, opts_spec);
 
+        println!();
+        print_codecs();
         println!("");
-        println!("If stdin has been redirected then each line of stdin will be separately decoded and printed");
+        cli::print_input_help();
         return;
     }
 
-    let is_interactive = opts.opt_present("i");
-    let is_stdin_tty = atty::is(Stream::Stdin);
-    if is_interactive && !is_stdin_tty {
-        println!("Bad argument: Interactive mode is not available with a non-interactive standard input stream");
-        process::exit(1);
-    }
-
-    if is_stdin_tty && !is_interactive && opts.free.is_empty() {
+    let inputs = match InputOptions::from_matches(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("Bad argument: {}", e);
+            process::exit(1);
+        }
+    };
+    if inputs.is_empty() {
         eprintln!("No input values provided");
         eprintln!("");
         print_usage(program, opts_spec);
         process::exit(1);
     }
 
-    let input_base = match opts.opt_str("f") {
-        Some(input_str) => {
-            match input_str.parse::<u8>() {
-                Ok(strval) => strval,
-                Err(err) => {
-                    eprintln!("{} is not valid as a numeric base: {}", input_str, err);
-                    process::exit(1);
-                }
-            }
-        },
+    let input_name = match opts.opt_str("f") {
+        Some(name) => name,
         None => {
             eprintln!("A from-base is required but none was specified");
             eprintln!("");
@@ -69,16 +77,8 @@ fn main() {
             process::exit(1);
         }
     };
-    let output_base = match opts.opt_str("t") {
-        Some(output_str) => {
-            match output_str.parse::<u8>() {
-                Ok(strval) => strval,
-                Err(err) => {
-                    eprintln!("{} is not valid as a numeric base: {}", output_str, err);
-                    process::exit(1);
-                }
-            }
-        },
+    let output_name = match opts.opt_str("t") {
+        Some(name) => name,
         None => {
             eprintln!("A to-base is required by none was specified");
             eprintln!("");
@@ -87,58 +87,213 @@ fn main() {
         }
     };
 
-    for arg in opts.free {
-        process_input(&arg, input_base, output_base);
+    // Options that aren't given for a particular side go to whichever of the codecs has them
+    let mut input_options = opts.opt_strs("from-option");
+    let mut output_options = opts.opt_strs("to-option");
+    let names = (find_codec(&input_name).name().to_string(), find_codec(&output_name).name().to_string());
+    for option in opts.opt_strs("o") {
+        match (try_option(&input_name, &input_options, &option), try_option(&output_name, &output_options, &option)) {
+            (Ok(true), Ok(true)) => {
+                eprintln!("Bad argument: Both {} and {} have an option called {}, use --from-option or --to-option to say which it's for", names.0, names.1, option);
+                process::exit(1);
+            },
+            (Ok(true), _) => input_options.push(option),
+            (_, Ok(true)) => output_options.push(option),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Bad argument: {}", e);
+                process::exit(1);
+            },
+            (Ok(false), Ok(false)) => {
+                eprintln!("Bad argument: Neither {} nor {} has an option called {}", names.0, names.1, option);
+                process::exit(1);
+            },
+        }
     }
+    let input_codec = build_codec(&input_name, &input_options);
+    let output_codec = build_codec(&output_name, &output_options);
 
-    if !is_interactive && is_stdin_tty {
-        return
-    }
+    let reordering = Reordering {
+        swap_bits: match opts.opt_str("swap").map(|bits| bits.parse::<usize>()) {
+            Some(Ok(bits)) if endian::WORD_SIZES.contains(&bits) => Some(bits),
+            Some(_) => {
+                eprintln!("Bad argument: --swap must be one of {:?}", endian::WORD_SIZES);
+                process::exit(1);
+            },
+            None => None,
+        },
+        both: opts.opt_present("b"),
+    };
 
-    let mut input = String::new();
-    loop {
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            eprintln!("Error: {}", e.to_string());
-            break;
+    if opts.opt_present("s") {
+        if reordering.swap_bits.is_some() || reordering.both {
+            eprintln!("Bad argument: --swap and --both-endian can't be used with --stream");
+            process::exit(1);
+        }
+        if !inputs.args.is_empty() {
+            eprintln!("Bad argument: --stream converts files or standard input, not arguments");
+            process::exit(1);
         }
+        check_block_codec(input_codec.as_ref());
+        check_block_codec(output_codec.as_ref());
+        if !stream_files(&inputs.files, input_codec.as_ref(), output_codec.as_ref()) {
+            process::exit(1);
+        }
+        return;
+    }
 
-        let trimmed = input.trim();
-        if trimmed.len() == 0 {
-            break;
+    if !inputs.process(|input| process_input(input, input_codec.as_ref(), output_codec.as_ref(), reordering)) {
+        process::exit(1);
+    }
+}
+
+fn build_codec(name: &str, options: &[String]) -> Box<dyn Codec> {
+    let mut codec = find_codec(name);
+    for option in options {
+        match codec.set_option(option) {
+            Ok(true) => {},
+            Ok(false) => {
+                eprintln!("Bad argument: {} has no option called {}", codec.name(), option);
+                process::exit(1);
+            },
+            Err(e) => {
+                eprintln!("Bad argument: {}", e);
+                process::exit(1);
+            }
         }
-        process_input(trimmed, input_base, output_base);
-        input.clear();
     }
+    codec
+}
+
+// Whether the codec would accept the option on top of the ones it already has
+fn try_option(name: &str, options: &[String], option: &str) -> Result<bool, String> {
+    let mut codec = build_codec(name, options);
+    codec.set_option(option)
+}
+
+fn check_block_codec(codec: &dyn Codec) {
+    if codec.block_codec().is_none() {
+        eprintln!("Bad argument: {} can't be converted as a stream", codec.name());
+        process::exit(1);
+    }
+}
+
+// Converts each file (or stdin, if there are none) in turn, returning false if any of them failed
+fn stream_files(files: &[String], input_codec: &dyn Codec, output_codec: &dyn Codec) -> bool {
+    if files.is_empty() {
+        return stream_file("-", input_codec, output_codec);
+    }
+
+    let mut success = true;
+    for file in files {
+        success &= stream_file(file, input_codec, output_codec);
+    }
+    success
 }
 
-fn process_input(input: &str, input_base: u8, output_base: u8) {
-    let input_bytes = match input_base {
-        2 => alltools::binary::to_bytes(input),
-        10 => alltools::decimal::to_bytes(input),
-        16 => alltools::hex::to_bytes(input),
-        64 => alltools::base64::to_bytes(input),
-        _ => {
-            eprintln!("{} is not a supported input base", input_base);
-            return;
+fn stream_file(path: &str, input_codec: &dyn Codec, output_codec: &dyn Codec) -> bool {
+    let (name, reader): (&str, Box<dyn Read>) = if path == "-" {
+        ("standard input", Box::new(io::stdin()))
+    } else {
+        match File::open(path) {
+            Ok(file) => (path, Box::new(file)),
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                return false;
+            }
         }
     };
 
-    match input_bytes {
-        Some(input_bytes) => {
-            let output = match output_base {
-                2 => alltools::binary::from_bytes(&input_bytes, true),
-                10 => alltools::decimal::from_bytes(&input_bytes),
-                16 => alltools::hex::from_bytes(&input_bytes),
-                64 => alltools::base64::from_bytes(&input_bytes),
-                _ => {
-                    eprintln!("{} is not a supported output base", output_base);
-                    return;
-                }
-            };
-            println!("{}", output);
+    let stdout = io::stdout();
+    let mut decoder = Decoder::new(reader, input_codec.block_codec().unwrap());
+    let output_block_codec = output_codec.block_codec().unwrap();
+    let is_text = output_block_codec.is_ascii_only();
+    let mut encoder = Encoder::new(stdout.lock(), output_block_codec);
+    let result = io::copy(&mut decoder, &mut encoder).and_then(|_| encoder.finish());
+    match result {
+        // Finish the line, unless the output is binary data that shouldn't be changed
+        Ok(mut stdout) => {
+            if is_text {
+                let _ = writeln!(stdout);
+            }
+            true
+        }
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            println!();
+            eprintln!("Bad input ({}): {}", name, e);
+            false
+        }
+        Err(e) => {
+            println!();
+            eprintln!("Error converting {}: {}", name, e);
+            false
         }
+    }
+}
+
+fn find_codec(name: &str) -> Box<dyn Codec> {
+    match codec::find_codec(name) {
+        Some(codec) => codec,
         None => {
-            eprintln!("Bad input: \"{}\" is not a valid base-{} literal", input, input_base)
+            eprintln!("{} is not a supported base", name);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_codecs() {
+    println!("Supported bases (with their aliases) and options:");
+    for codec in codec::all_codecs() {
+        println!("  {} ({})", codec.name(), codec.aliases().join(", "));
+        for (name, description) in codec.options() {
+            println!("      -o {:<16}{}", name, description);
         }
     }
+    println!("  base{}-base{} (or just the number)", radix::MIN_RADIX, radix::MAX_RADIX);
+    for (name, description) in Radix::new(radix::MAX_RADIX).unwrap().options() {
+        println!("      -o {:<16}{}", name, description);
+    }
+    println!();
+    println!("Negative numbers must come after -- so that they aren't read as options, e.g:");
+    println!("  convertbase -f dec -t hex -o signed -o width=16 -- -1 -32768");
+}
+
+// Points out where decoding failed, e.g:
+//   invalid digit 'z' at offset 3
+//     0xfz
+//        ^
+fn describe_decode_error(input: &str, error: &DecodeError) -> String {
+    let column = input[..error.offset].chars().count();
+    format!("{}\n    {}\n    {}^", error, input, " ".repeat(column))
+}
+
+// How the bytes are rearranged between decoding the input and encoding the output
+#[derive(Clone, Copy)]
+struct Reordering {
+    // Reverse the bytes within each word of this many bits
+    swap_bits: Option<usize>,
+    // Show the output for the bytes as they are and reordered, side by side
+    both: bool,
+}
+
+// Swaps the bytes within each word, or reverses all of them if there's no word size
+fn reorder(bytes: &[u8], swap_bits: Option<usize>) -> Result<Vec<u8>, String> {
+    let mut bytes = bytes.to_vec();
+    match swap_bits {
+        Some(bits) => endian::swap_words(&mut bytes, bits)?,
+        None => bytes.reverse(),
+    }
+    Ok(bytes)
+}
+
+fn process_input(input: &str, input_codec: &dyn Codec, output_codec: &dyn Codec, reordering: Reordering) -> Result<(), String> {
+    let input_bytes = input_codec.decode(input).map_err(|e| describe_decode_error(input, &e))?;
+    if reordering.both {
+        let reordered = reorder(&input_bytes, reordering.swap_bits)?;
+        println!("big-endian: {}\tlittle-endian: {}", output_codec.encode(&input_bytes), output_codec.encode(&reordered));
+    } else if reordering.swap_bits.is_some() {
+        println!("{}", output_codec.encode(&reorder(&input_bytes, reordering.swap_bits)?));
+    } else {
+        println!("{}", output_codec.encode(&input_bytes));
+    }
+    Ok(())
 }
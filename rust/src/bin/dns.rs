@@ -1,12 +1,25 @@
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use alltools::dns::{
+    self, diff_records, find_tag, parse_address_range, parse_tag_list, reverse_domain_name,
+    rsa_key_bits, DnsQuestion, DnsResourceRecord, DnsResponse, DomainClass, QueryType, Record,
+    RecordChange, RecordData, ResponseCode, Severity,
+};
+use alltools::time::{self, TtlFormat};
+use atty::Stream;
+use chrono::offset::TimeZone;
+use chrono::{Local, Utc};
+use colored::*;
 use getopts::Options;
+#[cfg(windows)]
 use ipconfig;
-use rand::Rng;
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::net::{IpAddr, Ipv4Addr};
 use std::option::Option;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, Instant};
-use std::{convert::TryInto, env, io, net, process};
+use std::{cmp, env, io, net, process, thread};
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [OPTIONS] [INPUT]", program);
@@ -24,6 +37,15 @@ fn get_server_ip(requested_server: Option<String>, verbose: bool) -> IpAddr {
         }
     }
 
+    if let Some(server_ip) = system_dns_server(verbose) {
+        return server_ip;
+    }
+
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))
+}
+
+#[cfg(windows)]
+fn system_dns_server(verbose: bool) -> Option<IpAddr> {
     match ipconfig::get_adapters() {
         Ok(adapters) => {
             for adapter in adapters {
@@ -43,7 +65,7 @@ fn get_server_ip(requested_server: Option<String>, verbose: bool) -> IpAddr {
                                 adapter.friendly_name()
                             );
                         }
-                        return *server_addr;
+                        return Some(*server_addr);
                     }
                 }
             }
@@ -53,19 +75,61 @@ fn get_server_ip(requested_server: Option<String>, verbose: bool) -> IpAddr {
             eprintln!("Falling back to the default server IP...");
         }
     };
+    None
+}
 
-    return IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+#[cfg(not(windows))]
+fn system_dns_server(verbose: bool) -> Option<IpAddr> {
+    match std::fs::read_to_string(dns::RESOLV_CONF_PATH) {
+        Ok(contents) => {
+            let server_ip = dns::parse_resolv_conf(&contents).into_iter().next();
+            if verbose {
+                if let Some(server_ip) = server_ip {
+                    println!(
+                        "Detected system DNS server {} in {}",
+                        server_ip,
+                        dns::RESOLV_CONF_PATH
+                    );
+                }
+            }
+            server_ip
+        }
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", dns::RESOLV_CONF_PATH, e);
+            eprintln!("Falling back to the default server IP...");
+            None
+        }
+    }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
+    if args.get(1).map(|arg| arg.as_str()) == Some("update") {
+        update_main(program, &args[2..]);
+        return;
+    }
+
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
     opts_spec.optflag(
         "r",
         "reverse",
-        "do a reverse lookup (find the domain name given an IP)",
+        "do a reverse lookup (find the domain name given an IP). Also accepts CIDR blocks (e.g 192.0.2.0/24) and ranges (e.g 192.0.2.10-192.0.2.20)",
+    );
+    opts_spec.optflag(
+        "F",
+        "fcrdns",
+        "for reverse lookups, check that each name found resolves back to the same address (forward-confirmed reverse DNS)",
+    );
+    opts_spec.optopt(
+        "j",
+        "jobs",
+        "the number of reverse lookups to run at the same time when looking up a range of addresses (default 16)",
+        "COUNT",
     );
     opts_spec.optflag("v", "verbose", "print additional data");
     opts_spec.optopt(
@@ -80,6 +144,106 @@ fn main() {
         "the type of record to request. A (default), CNAME, MX, etc. Ignored for reverse lookups",
         "TYPE",
     );
+    opts_spec.optopt(
+        "w",
+        "watch",
+        "repeat the query every INTERVAL (e.g 30, 30s, 5m) and highlight records that change",
+        "INTERVAL",
+    );
+    opts_spec.optflag(
+        "S",
+        "split-txt",
+        "show each of the strings that make up a TXT record separately, rather than joined together",
+    );
+    opts_spec.optflag(
+        "m",
+        "mail",
+        "audit the mail configuration (MX, SPF, DMARC, DKIM and MTA-STS) of the given domain",
+    );
+    opts_spec.optmulti(
+        "k",
+        "dkim",
+        "a DKIM selector to check as part of a mail audit. Can be given multiple times",
+        "SELECTOR",
+    );
+    opts_spec.optopt(
+        "e",
+        "expect",
+        "when watching, stop once an answer with the given value is received",
+        "VALUE",
+    );
+    opts_spec.optflag(
+        "M",
+        "mdns",
+        "send the query to the local network using multicast DNS and show every response",
+    );
+    opts_spec.optflagopt(
+        "B",
+        "browse",
+        "browse the local network for DNS-SD services, either of the given type (e.g _ipp._tcp) or of every advertised type",
+        "SERVICE",
+    );
+    opts_spec.optopt(
+        "W",
+        "window",
+        "how long to wait for multicast DNS responses (default 2s)",
+        "DURATION",
+    );
+    opts_spec.optflag(
+        "6",
+        "ipv6",
+        "send multicast DNS queries to the IPv6 group (ff02::fb) instead of the IPv4 one",
+    );
+    opts_spec.optflag(
+        "H",
+        "hosts",
+        "check the system hosts file before querying DNS (like the \"files dns\" order of the system resolver) and report which one answered",
+    );
+    opts_spec.optopt(
+        "",
+        "hosts-file",
+        "an extra hosts file to check before the system one. Implies --hosts",
+        "FILE",
+    );
+    opts_spec.optopt(
+        "",
+        "subnet",
+        "send an EDNS Client Subnet option so the server answers as if the query came from the given network (e.g 203.0.113.0/24)",
+        "PREFIX",
+    );
+    opts_spec.optflagopt(
+        "",
+        "cookie",
+        "send an EDNS DNS Cookie, either a new random one or the given hex client cookie (optionally followed by the server cookie)",
+        "COOKIE",
+    );
+    opts_spec.optflag(
+        "",
+        "nsid",
+        "ask the server to identify itself (EDNS NSID), which shows which instance of an anycast service answered",
+    );
+    opts_spec.optflag(
+        "",
+        "padding",
+        "pad the query to a multiple of 128 bytes with the EDNS padding option",
+    );
+    opts_spec.optflag(
+        "",
+        "0x20",
+        "randomise the case of the query name and reject responses that don't echo it back exactly (DNS 0x20), to make spoofing harder",
+    );
+    opts_spec.optopt(
+        "T",
+        "ttl-format",
+        "how to show TTLs: human (default), compact (e.g 1h3m), seconds, or the time at which they expire in utc or local time",
+        "FORMAT",
+    );
+    opts_spec.optopt(
+        "P",
+        "pcap",
+        "read the DNS traffic (port 53) from a pcap or pcapng capture file and print each query along with its response",
+        "FILE",
+    );
     let opts = match opts_spec.parse(&args[1..]) {
         Ok(o) => o,
         Err(e) => {
@@ -91,6 +255,10 @@ fn main() {
 
     if opts.opt_present("h") {
         println!("Send a DNS query for the given domain(s)");
+        println!(
+            "Use '{} update --help' for details on sending dynamic updates to a zone",
+            program
+        );
         println!("");
 
         print_usage(program, opts_spec);
@@ -101,24 +269,63 @@ fn main() {
     let reverse = opts.opt_present("r");
     let server = opts.opt_str("s");
     let query_type = match opts.opt_str("t") {
-        Some(mut query_type_str) => {
-            query_type_str.make_ascii_uppercase();
-            match query_type_str.as_str() {
-                "A" => QueryType::A,
-                "CNAME" => QueryType::CNAME,
-                "MX" => QueryType::MX,
-                "NS" => QueryType::NS,
-                "TXT" => QueryType::TXT,
-                "PTR" => QueryType::PTR,
-                _ => {
-                    eprintln!("Unsupported query type: {} Supported options are A, CNAME, MX, NS, TXT, PTR", &query_type_str);
-                    process::exit(1);
-                }
+        Some(query_type_str) => match parse_query_type(&query_type_str) {
+            Some(query_type) => query_type,
+            None => {
+                eprintln!("Unsupported query type: {} Supported options are A, AAAA, CNAME, MX, NS, TXT, PTR, SRV", &query_type_str);
+                process::exit(1);
             }
-        }
+        },
         None => QueryType::A, // TODO: Default to running a query for *all* types
     };
 
+    let ttl_format = match opts.opt_str("T") {
+        Some(format_str) => match TtlFormat::from_name(&format_str) {
+            Some(ttl_format) => ttl_format,
+            None => {
+                eprintln!(
+                    "Unsupported TTL format: {} Supported options are {}",
+                    format_str,
+                    TtlFormat::NAMES.join(", ")
+                );
+                process::exit(1);
+            }
+        },
+        None => TtlFormat::Human,
+    };
+
+    let mdns = opts.opt_present("M");
+    let ipv6 = opts.opt_present("6");
+    let window = match opts.opt_str("W") {
+        Some(window_str) => match parse_interval(&window_str) {
+            Some(window) => window,
+            None => {
+                eprintln!("Invalid response window: {}", window_str);
+                process::exit(1);
+            }
+        },
+        None => dns::DEFAULT_MDNS_WINDOW,
+    };
+    if (ipv6 || opts.opt_present("W")) && !(mdns || opts.opt_present("B")) {
+        eprintln!("Bad argument: The IPv6 and window options only apply to multicast DNS (--mdns or --browse)");
+        process::exit(1);
+    }
+    if opts.opt_present("B") {
+        let mut service_types: Vec<String> = opts.opt_str("B").into_iter().collect();
+        service_types.extend(opts.free.iter().cloned());
+        browse_services(&service_types, ipv6, window, verbose);
+        return;
+    }
+
+    if let Some(capture_path) = opts.opt_str("P") {
+        if !opts.free.is_empty() {
+            eprintln!("Bad argument: A query cannot be given when reading a capture file (--pcap)");
+            process::exit(1);
+        }
+        pcap_input(&capture_path, verbose, ttl_format);
+        return;
+    }
+
     if opts.free.is_empty() {
         eprintln!("No input provided");
         eprintln!("");
@@ -136,991 +343,2217 @@ fn main() {
         eprintln!();
     }
 
-    let query = &opts.free[0];
-    let server_ip = get_server_ip(server, verbose);
-
-    process_input(server_ip, query, query_type, reverse, verbose);
-}
+    let watch_interval = match opts.opt_str("w") {
+        Some(interval_str) => match parse_interval(&interval_str) {
+            Some(interval) => Some(interval),
+            None => {
+                eprintln!("Invalid watch interval: {}", interval_str);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let expected = opts.opt_str("e");
+    if expected.is_some() && watch_interval.is_none() {
+        eprintln!("Bad argument: An expected value can only be given when watching (--watch)");
+        process::exit(1);
+    }
 
-#[derive(Clone, Copy, Debug)]
-enum DomainClass {
-    Reserved = 0,
-    Internet = 1,
-    Chaos = 3,
-    Hesiod = 4,
-}
+    let split_txt = opts.opt_present("S");
+    let override_hosts_file = opts.opt_str("hosts-file");
+    let use_hosts = opts.opt_present("H") || override_hosts_file.is_some();
+    if use_hosts && (watch_interval.is_some() || opts.opt_present("m") || opts.opt_present("M")) {
+        eprintln!("Bad argument: Hosts files can only be checked for single lookups, not when watching, auditing mail or using multicast DNS");
+        process::exit(1);
+    }
+    let mail_audit = opts.opt_present("m");
+    let dkim_selectors = opts.opt_strs("k");
+    if !dkim_selectors.is_empty() && !mail_audit {
+        eprintln!("Bad argument: DKIM selectors can only be given for a mail audit (--mail)");
+        process::exit(1);
+    }
 
-impl DomainClass {
-    fn from_int(i: u16) -> Option<DomainClass> {
-        match i {
-            0 => Some(DomainClass::Reserved),
-            1 => Some(DomainClass::Internet),
-            3 => Some(DomainClass::Chaos),
-            4 => Some(DomainClass::Hesiod),
-            _ => None,
-        }
+    let fcrdns = opts.opt_present("F");
+    if fcrdns && !reverse {
+        eprintln!(
+            "Bad argument: Forward-confirmation can only be done for reverse lookups (--reverse)"
+        );
+        process::exit(1);
     }
-}
+    let jobs = match opts.opt_str("j") {
+        Some(jobs_str) => match jobs_str.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => {
+                eprintln!("Invalid number of jobs: {}", jobs_str);
+                process::exit(1);
+            }
+        },
+        None => 16,
+    };
 
-#[derive(Clone, Copy, Debug)]
-enum QueryType {
-    Unknown = 0,
-    A = 1,
-    NS = 2,
-    MD = 3,
-    MF = 4,
-    CNAME = 5,
-    SOA = 6,
-    MB = 7,
-    MG = 8,
-    MR = 9,
-    NULL = 10,
-    WKS = 11,
-    PTR = 12,
-    HINFO = 13,
-    MINFO = 14,
-    MX = 15,
-    TXT = 16,
-    RP = 17,
-    AFSDB = 18,
-    X25 = 19,
-    ISDN = 20,
-    RT = 21,
-    NSAP = 22,
-    NSAPPTR = 23,
-    SIG = 24,
-    KEY = 25,
-    PX = 26,
-    GPOS = 27,
-    AAAA = 28,
-    LOC = 29,
-    NXT = 30,
-    EID = 31,
-    NIMLOC = 32,
-}
+    let query_options = dns::QueryOptions {
+        edns: match edns_from_options(&opts) {
+            Ok(edns) => edns,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        randomise_case: opts.opt_present("0x20"),
+    };
+    let has_query_options = query_options.edns.is_some() || query_options.randomise_case;
+    if has_query_options && (mdns || mail_audit || (reverse && fcrdns) || use_hosts) {
+        eprintln!("Bad argument: EDNS options and case randomisation can only be used with single lookups or when watching");
+        process::exit(1);
+    }
 
-impl QueryType {
-    fn from_int(i: u16) -> Option<QueryType> {
-        match i {
-            0 => Some(QueryType::Unknown),
-            1 => Some(QueryType::A),
-            2 => Some(QueryType::NS),
-            3 => Some(QueryType::MD),
-            4 => Some(QueryType::MF),
-            5 => Some(QueryType::CNAME),
-            6 => Some(QueryType::SOA),
-            7 => Some(QueryType::MB),
-            8 => Some(QueryType::MG),
-            9 => Some(QueryType::MR),
-            10 => Some(QueryType::NULL),
-            11 => Some(QueryType::WKS),
-            12 => Some(QueryType::PTR),
-            13 => Some(QueryType::HINFO),
-            14 => Some(QueryType::MINFO),
-            15 => Some(QueryType::MX),
-            16 => Some(QueryType::TXT),
-            17 => Some(QueryType::RP),
-            18 => Some(QueryType::AFSDB),
-            19 => Some(QueryType::X25),
-            20 => Some(QueryType::ISDN),
-            21 => Some(QueryType::RT),
-            22 => Some(QueryType::NSAP),
-            23 => Some(QueryType::NSAPPTR),
-            24 => Some(QueryType::SIG),
-            25 => Some(QueryType::KEY),
-            26 => Some(QueryType::PX),
-            27 => Some(QueryType::GPOS),
-            28 => Some(QueryType::AAAA),
-            29 => Some(QueryType::LOC),
-            30 => Some(QueryType::NXT),
-            31 => Some(QueryType::EID),
-            32 => Some(QueryType::NIMLOC),
-            _ => None,
+    let query = &opts.free[0];
+    let is_range = query.contains('/') || query.contains('-');
+    if reverse && (is_range || fcrdns) && watch_interval.is_some() {
+        eprintln!("Bad argument: Address ranges and forward-confirmation cannot be watched");
+        process::exit(1);
+    }
+    if mdns {
+        if watch_interval.is_some() {
+            eprintln!("Bad argument: Multicast DNS queries cannot be watched");
+            process::exit(1);
         }
+        mdns_input(
+            query, query_type, reverse, ipv6, window, verbose, ttl_format,
+        );
+        return;
     }
-}
 
-#[derive(Clone, Copy, Debug)]
-enum OpCode {
-    Standard = 0,
-    Inverse = 1,
-    Status = 2,
-}
+    let server_ip = get_server_ip(server, verbose);
 
-impl OpCode {
-    fn from_int(i: u16) -> Option<OpCode> {
-        match i {
-            0 => Some(OpCode::Standard),
-            1 => Some(OpCode::Inverse),
-            2 => Some(OpCode::Status),
-            _ => None,
+    if reverse && (is_range || fcrdns) {
+        match parse_address_range(query) {
+            Ok(addresses) => bulk_reverse_lookup(server_ip, addresses, jobs, fcrdns, verbose),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
         }
+        return;
     }
-}
 
-#[derive(Clone, Copy, Debug)]
-enum ResponseCode {
-    NoError = 0,
-    FormatError = 1,
-    ServerFailure = 2,
-    NameError = 3,
-    NotImplemented = 4,
-    Refused = 5,
-}
+    if mail_audit {
+        audit_mail(server_ip, query, &dkim_selectors, verbose);
+        return;
+    }
 
-impl ResponseCode {
-    fn from_int(i: u16) -> Option<ResponseCode> {
-        match i {
-            0 => Some(ResponseCode::NoError),
-            1 => Some(ResponseCode::FormatError),
-            2 => Some(ResponseCode::ServerFailure),
-            3 => Some(ResponseCode::NameError),
-            4 => Some(ResponseCode::NotImplemented),
-            5 => Some(ResponseCode::Refused),
-            _ => None,
+    if use_hosts {
+        let hosts_answered = hosts_input(
+            server_ip,
+            query,
+            query_type,
+            reverse,
+            override_hosts_file.as_deref(),
+            verbose,
+            ttl_format,
+        );
+        if hosts_answered {
+            return;
         }
     }
+
+    match watch_interval {
+        Some(interval) => watch_input(
+            server_ip,
+            build_question(query, query_type, reverse),
+            verbose,
+            interval,
+            expected.as_deref(),
+            &query_options,
+            ttl_format,
+        ),
+        None => process_input(
+            server_ip,
+            build_question(query, query_type, reverse),
+            verbose,
+            split_txt,
+            &query_options,
+            ttl_format,
+        ),
+    }
 }
 
-#[derive(Debug)]
-struct DnsHeader {
-    request_id: u16,
-    is_response: bool,
-    opcode: OpCode,
-    authoritative_answer: bool,
-    message_truncated: bool,
-    recursion_desired: bool,
-    recursion_available: bool,
-    rcode: ResponseCode,
-    query_count: u16,
-    answer_count: u16,
-    nameserver_count: u16,
-    additional_count: u16,
+fn format_ttl(ttl: u32, ttl_format: TtlFormat) -> String {
+    let value = ttl_format.format_ttl(ttl, Utc::now());
+    if ttl_format.is_expiry() {
+        format!("Expires: {}", value)
+    } else {
+        format!("TTL: {}", value)
+    }
 }
 
-impl Default for DnsHeader {
-    fn default() -> DnsHeader {
-        DnsHeader {
-            request_id: 0,
-            is_response: false,
-            opcode: OpCode::Standard,
-            authoritative_answer: false,
-            message_truncated: false,
-            recursion_desired: false,
-            recursion_available: false,
-            rcode: ResponseCode::NoError,
-            query_count: 0,
-            answer_count: 0,
-            nameserver_count: 0,
-            additional_count: 0,
-        }
+fn describe(data: &RecordData, ttl: u32, ttl_format: TtlFormat) -> String {
+    match data {
+        RecordData::MX {
+            preference,
+            exchange,
+        } => format!(
+            "  {}  (Priority: {}, {})",
+            exchange,
+            preference,
+            format_ttl(ttl, ttl_format)
+        ),
+        RecordData::SRV {
+            priority,
+            weight,
+            port,
+            target,
+        } => format!(
+            "  {}:{}  (Priority: {}, Weight: {}, {})",
+            target,
+            port,
+            priority,
+            weight,
+            format_ttl(ttl, ttl_format)
+        ),
+        RecordData::SOA {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        } => format!(
+            "  {} - {} (Serial: {}, Refresh: {}, Retry: {}, Expire: {}, Minimum: {}, {})",
+            mname,
+            rname,
+            serial,
+            ttl_format.format_duration(*refresh),
+            ttl_format.format_duration(*retry),
+            ttl_format.format_duration(*expire),
+            ttl_format.format_duration(*minimum),
+            format_ttl(ttl, ttl_format)
+        ),
+        _ => format!("  {}  ({})", data, format_ttl(ttl, ttl_format)),
     }
 }
 
-impl DnsHeader {
-    fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<usize> {
-        let bitflags: u16 = (u16::from(self.is_response) << 15)
-            | ((self.opcode as u16) << 11)
-            | (u16::from(self.authoritative_answer) << 10)
-            | (u16::from(self.message_truncated) << 9)
-            | (u16::from(self.recursion_desired) << 8)
-            | (u16::from(self.recursion_available) << 7)
-            // 4 0 bits << 4
-            | (self.rcode as u16);
-
-        let start_len = buffer.len();
-        buffer.write_u16::<BigEndian>(self.request_id)?;
-        buffer.write_u16::<BigEndian>(bitflags)?;
-        buffer.write_u16::<BigEndian>(self.query_count)?;
-        buffer.write_u16::<BigEndian>(self.answer_count)?;
-        buffer.write_u16::<BigEndian>(self.nameserver_count)?;
-        buffer.write_u16::<BigEndian>(self.additional_count)?;
-        let end_len = buffer.len();
-        Ok(end_len - start_len)
-    }
-
-    fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>) -> io::Result<()> {
-        self.request_id = cursor.read_u16::<BigEndian>()?;
-        let bitflags = cursor.read_u16::<BigEndian>()?;
-        match OpCode::from_int((bitflags >> 11) & 0b1111) {
-            Some(oc) => {
-                self.opcode = oc;
-            }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid query operation code",
-                ));
-            }
-        }
-        self.is_response = ((bitflags >> 15) & 0b1) == 1;
-        self.authoritative_answer = ((bitflags >> 10) & 0b1) == 1;
-        self.message_truncated = ((bitflags >> 9) & 0b1) == 1;
-        self.recursion_desired = ((bitflags >> 8) & 0b1) == 1;
-        self.recursion_available = ((bitflags >> 7) & 0b1) == 1;
-        match ResponseCode::from_int(bitflags & 0b1111) {
-            Some(rc) => {
-                self.rcode = rc;
-            }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid response code",
-                ));
-            }
+fn records_from_answers(response: &DnsResponse) -> Vec<Record> {
+    let mut result = Vec::new();
+    for answer in &response.packet.answers {
+        match Record::deserialize_from(answer, &response.data) {
+            Ok(record) => result.push(record),
+            Err(e) => eprintln!("ERROR: {}", e),
         }
-        self.query_count = cursor.read_u16::<BigEndian>()?;
-        self.answer_count = cursor.read_u16::<BigEndian>()?;
-        self.nameserver_count = cursor.read_u16::<BigEndian>()?;
-        self.additional_count = cursor.read_u16::<BigEndian>()?;
-        Ok(())
     }
+    result
+}
 
-    fn deserialize_from(cursor: &mut io::Cursor<&[u8]>) -> io::Result<DnsHeader> {
-        let mut result = DnsHeader::default();
-        result.deserialize(cursor)?;
-        Ok(result)
+fn matches_value(record: &Record, expected: &str) -> bool {
+    let value = record.data.to_string();
+    match record.data {
+        RecordData::TXT(ref strings) => strings.concat() == expected.as_bytes(),
+        _ => value
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(expected.trim_end_matches('.')),
     }
 }
 
-#[derive(Debug)]
-struct DnsQuestion {
-    domain_name: String,
-    query_type: QueryType,
-    query_class: DomainClass,
+fn format_record(record: &Record, marker: &str, ttl_format: TtlFormat) -> String {
+    format!(
+        "{} {} ({:?}):{}",
+        marker,
+        record.domain_name,
+        record.data_type,
+        describe(&record.data, record.ttl, ttl_format)
+    )
 }
 
-impl Default for DnsQuestion {
-    fn default() -> DnsQuestion {
-        DnsQuestion {
-            domain_name: String::new(),
-            query_type: QueryType::Unknown,
-            query_class: DomainClass::Reserved,
-        }
-    }
+fn roundtrip_ms(response: &DnsResponse) -> f64 {
+    (response.roundtrip.as_micros() as f64) / 1000.0
 }
 
-impl DnsQuestion {
-    fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
-        for label in self.domain_name.split('.') {
-            buffer.write_u8(
-                label
-                    .len()
-                    .try_into()
-                    .expect("label length greater than 255"),
-            )?;
-            for ch in label.bytes() {
-                buffer.write_u8(ch)?;
-            }
-        }
-        buffer.write_u8(0)?;
-        buffer.write_u16::<BigEndian>(self.query_type as u16)?;
-        buffer.write_u16::<BigEndian>(self.query_class as u16)?;
-        return Ok(());
-    }
-
-    fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>) -> io::Result<()> {
-        loop {
-            let label_len = cursor.read_u8()?;
-            if label_len == 0 {
-                break;
-            }
-
-            if self.domain_name.len() != 0 {
-                self.domain_name.push('.');
-            }
-            for _ in 0..label_len {
-                let ch = cursor.read_u8()?;
-                self.domain_name.push(char::from(ch));
-            }
-        }
-
-        let query_type_int = cursor.read_u16::<BigEndian>()?;
-        match QueryType::from_int(query_type_int) {
-            Some(t) => {
-                self.query_type = t;
-            }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid query type: {}", query_type_int),
-                ));
-            }
-        }
-        match DomainClass::from_int(cursor.read_u16::<BigEndian>()?) {
-            Some(class) => {
-                self.query_class = class;
+fn build_question(domain: &str, qtype: QueryType, reverse: bool) -> DnsQuestion {
+    let mut question = DnsQuestion::default();
+    if reverse {
+        match IpAddr::from_str(domain) {
+            Ok(addr) => {
+                question.domain_name = reverse_domain_name(addr);
+                question.query_type = QueryType::PTR;
             }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid domain class",
-                ));
+            Err(err) => {
+                eprintln!(
+                    "Failed to parse request string as IP address {}: {}",
+                    domain, err
+                );
+                process::exit(1);
             }
-        }
-        Ok(())
+        };
+    } else {
+        question.domain_name = String::from(domain);
+        question.query_type = qtype;
     }
 
-    fn deserialize_from(cursor: &mut io::Cursor<&[u8]>) -> io::Result<DnsQuestion> {
-        let mut result = DnsQuestion::default();
-        result.deserialize(cursor)?;
-        Ok(result)
-    }
+    question.query_class = DomainClass::Internet;
+    question
 }
 
-#[derive(Debug)]
-struct DnsResourceRecord {
-    domain_name: String,
-    data_type: QueryType,
-    data_class: DomainClass,
-    ttl: u32,
-    data_length: u16,
-    data: Vec<u8>, // TODO: Maybe we want this to be a &[u8]? To save on copying? Depends on the deserialize
-}
+fn send_query(
+    server_ip: IpAddr,
+    question: DnsQuestion,
+    options: &dns::QueryOptions,
+    verbose: bool,
+) -> io::Result<DnsResponse> {
+    let response = dns::send_query_with_options(
+        net::SocketAddr::from((server_ip, dns::DNS_PORT)),
+        question,
+        options,
+        dns::DEFAULT_TIMEOUT,
+    )?;
+
+    for reason in &response.rejected {
+        eprintln!(
+            "{}",
+            format!(
+                "WARNING: Ignored a packet that did not match the request: {}",
+                reason
+            )
+            .yellow()
+        );
+    }
 
-impl Default for DnsResourceRecord {
-    fn default() -> DnsResourceRecord {
-        DnsResourceRecord {
-            domain_name: String::new(),
-            data_type: QueryType::Unknown,
-            data_class: DomainClass::Reserved,
-            ttl: 0,
-            data_length: 0,
-            data: Vec::new(),
+    if verbose {
+        print!(
+            "Received {} byte response from {} after {:.1}ms - ",
+            response.data.len(),
+            response.source,
+            roundtrip_ms(&response)
+        );
+        for b in &response.data {
+            print!("{:x}", b);
         }
+        println!();
     }
+
+    Ok(response)
 }
 
-fn deserialize_name(cursor: &mut io::Cursor<&[u8]>, all_data: &[u8]) -> io::Result<Vec<u8>> {
-    let mut result = Vec::new();
-    loop {
-        const POINTER_MASK: u8 = 0b11 << 6;
-        let len = cursor.read_u8()?;
-        if len == 0 {
-            break;
-        }
+fn lookup_records(
+    server_ip: IpAddr,
+    name: &str,
+    qtype: QueryType,
+    verbose: bool,
+) -> io::Result<(ResponseCode, Vec<Record>)> {
+    let question = DnsQuestion {
+        domain_name: String::from(name),
+        query_type: qtype,
+        query_class: DomainClass::Internet,
+    };
+    let response = send_query(server_ip, question, &dns::QueryOptions::default(), verbose)?;
+    Ok((
+        response.packet.header.rcode,
+        records_from_answers(&response),
+    ))
+}
 
-        if result.len() != 0 {
-            result.push('.' as u8);
-        }
+fn format_character_strings(strings: &[Vec<u8>]) -> String {
+    let quoted: Vec<String> = strings
+        .iter()
+        .map(|s| format!("\"{}\"", dns::escape_character_string(s)))
+        .collect();
+    quoted.join(" ")
+}
 
-        if len & POINTER_MASK == POINTER_MASK {
-            let offset_hi = len & !POINTER_MASK;
-            let offset_lo = cursor.read_u8()?;
-            let offset = ((offset_hi as u64) << 8) | (offset_lo as u64);
-            let mut ptr_cursor = io::Cursor::new(all_data);
-            ptr_cursor.set_position(offset);
+fn print_record_data(
+    record: &DnsResourceRecord,
+    data: &RecordData,
+    split_txt: bool,
+    ttl_format: TtlFormat,
+) {
+    match data {
+        RecordData::TXT(strings) if split_txt => println!(
+            "  {}  ({})",
+            format_character_strings(strings),
+            format_ttl(record.ttl, ttl_format)
+        ),
+        _ => println!("{}", describe(data, record.ttl, ttl_format)),
+    }
+}
 
-            let mut pointed_result = deserialize_name(&mut ptr_cursor, all_data)?;
-            result.append(&mut pointed_result);
-            break;
-        } else {
-            for _ in 0..len {
-                result.push(cursor.read_u8()?);
-            }
-        }
+fn parse_client_subnet(input: &str) -> Result<dns::EdnsOption, String> {
+    let (address_str, prefix_str) = match input.find('/') {
+        Some(index) => (&input[..index], Some(&input[index + 1..])),
+        None => (input, None),
+    };
+    let address = IpAddr::from_str(address_str)
+        .map_err(|_| format!("Invalid client subnet address: {}", address_str))?;
+    // RFC 7871 section 11.1 recommends not revealing more than a /24 or /56 by default
+    let prefix = match prefix_str {
+        Some(prefix_str) => prefix_str
+            .parse::<u8>()
+            .map_err(|_| format!("Invalid client subnet prefix length: {}", prefix_str))?,
+        None if address.is_ipv4() => 24,
+        None => 56,
+    };
+    dns::EdnsOption::client_subnet(address, prefix).map_err(|e| e.to_string())
+}
+
+fn parse_cookie(input: Option<&str>) -> Result<dns::EdnsOption, String> {
+    let bytes = match input {
+        Some(hex_str) => match alltools::hex::to_bytes(hex_str) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Invalid cookie (expected hex): {}: {}", hex_str, e)),
+        },
+        None => rand::random::<[u8; 8]>().to_vec(),
+    };
+    let server_len = bytes.len().saturating_sub(8);
+    if (bytes.len() < 8) || ((server_len != 0) && !(8..=32).contains(&server_len)) {
+        return Err(String::from(
+            "Invalid cookie: The client cookie must be 8 bytes, optionally followed by an 8 to 32 byte server cookie",
+        ));
     }
-    Ok(result)
+    let mut client = [0; 8];
+    client.copy_from_slice(&bytes[..8]);
+    Ok(dns::EdnsOption::Cookie {
+        client,
+        server: bytes[8..].to_vec(),
+    })
 }
 
-fn deserialize_bytes(cursor: &mut io::Cursor<&[u8]>) -> io::Result<Vec<u8>> {
-    let len = cursor.read_u8()?;
-    if len == 0 {
-        return Ok(Vec::new());
+fn edns_from_options(opts: &getopts::Matches) -> Result<Option<dns::Edns>, String> {
+    let mut edns = dns::Edns::default();
+    if opts.opt_present("nsid") {
+        edns.options.push(dns::EdnsOption::Nsid(Vec::new()));
+    }
+    if let Some(subnet) = opts.opt_str("subnet") {
+        edns.options.push(parse_client_subnet(&subnet)?);
+    }
+    if opts.opt_present("cookie") {
+        edns.options
+            .push(parse_cookie(opts.opt_str("cookie").as_deref())?);
+    }
+    if opts.opt_present("padding") {
+        edns.options.push(dns::EdnsOption::Padding(0));
     }
 
-    let mut result = Vec::new();
-    for _ in 0..len {
-        result.push(cursor.read_u8()?);
+    if edns.options.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(edns))
     }
-    Ok(result)
 }
 
-impl DnsResourceRecord {
-    fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>, all_data: &[u8]) -> io::Result<()> {
-        let name_bytes = deserialize_name(cursor, all_data)?;
-        match String::from_utf8(name_bytes) {
-            Ok(name) => {
-                self.domain_name = name;
-            }
-            Err(e) => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
-            }
+fn print_edns(edns: &dns::Edns, verbose: bool) {
+    if verbose {
+        print!(
+            "  EDNS version {}, UDP payload size {}",
+            edns.version, edns.udp_payload_size
+        );
+        if edns.dnssec_ok {
+            print!(", DNSSEC OK");
         }
+        println!();
+    }
 
-        let query_type_int = cursor.read_u16::<BigEndian>()?;
-        match QueryType::from_int(query_type_int) {
-            Some(t) => {
-                self.data_type = t;
+    for option in &edns.options {
+        match option {
+            dns::EdnsOption::Nsid(id) => {
+                let is_text =
+                    !id.is_empty() && id.iter().all(|b| b.is_ascii_graphic() || *b == b' ');
+                if is_text {
+                    println!(
+                        "  Server ID (NSID): \"{}\" ({})",
+                        String::from_utf8_lossy(id),
+                        alltools::hex::from_bytes(id)
+                    );
+                } else {
+                    println!("  Server ID (NSID): {}", alltools::hex::from_bytes(id));
+                }
             }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid query type: {}", query_type_int),
-                ));
+            dns::EdnsOption::ClientSubnet {
+                address,
+                source_prefix,
+                scope_prefix,
+            } => println!(
+                "  Client subnet: {}/{} (answer applies to /{})",
+                address, source_prefix, scope_prefix
+            ),
+            dns::EdnsOption::Cookie { client, server } => {
+                if server.is_empty() {
+                    println!(
+                        "  Cookie: {} (no server cookie)",
+                        alltools::hex::from_bytes(client)
+                    );
+                } else {
+                    // Printed as one value so it can be passed straight back to --cookie
+                    let mut cookie = client.to_vec();
+                    cookie.extend_from_slice(server);
+                    println!("  Cookie: {}", alltools::hex::from_bytes(&cookie));
+                }
             }
-        };
-        match DomainClass::from_int(cursor.read_u16::<BigEndian>()?) {
-            Some(class) => {
-                self.data_class = class;
+            dns::EdnsOption::Padding(len) => {
+                if verbose {
+                    println!("  Padding: {} bytes", len);
+                }
             }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid domain class",
-                ));
+            dns::EdnsOption::Unknown { code, data } => {
+                println!(
+                    "  EDNS option {}: {}",
+                    code,
+                    alltools::hex::from_bytes(data)
+                )
             }
-        };
-        self.ttl = cursor.read_u32::<BigEndian>()?;
-        self.data_length = cursor.read_u16::<BigEndian>()?;
-        self.data.reserve_exact(self.data_length as usize);
-        for _ in 0..self.data_length {
-            let b = cursor.read_u8()?;
-            self.data.push(b);
         }
-        Ok(())
     }
-
-    fn deserialize_from(
-        cursor: &mut io::Cursor<&[u8]>,
-        all_data: &[u8],
-    ) -> io::Result<DnsResourceRecord> {
-        let mut result = DnsResourceRecord::default();
-        result.deserialize(cursor, all_data)?;
-        Ok(result)
-    }
-}
-
-#[derive(Debug)]
-struct DnsPacket {
-    header: DnsHeader,
-    questions: Vec<DnsQuestion>,
-    answers: Vec<DnsResourceRecord>,
-    authorities: Vec<DnsResourceRecord>,
-    additionals: Vec<DnsResourceRecord>,
 }
 
-impl Default for DnsPacket {
-    fn default() -> DnsPacket {
-        DnsPacket {
-            header: DnsHeader::default(),
-            questions: Vec::new(),
-            answers: Vec::new(),
-            authorities: Vec::new(),
-            additionals: Vec::new(),
-        }
+fn print_response(response: &DnsResponse, verbose: bool, split_txt: bool, ttl_format: TtlFormat) {
+    let resp_bytes = &response.data[..];
+    let response_packet = &response.packet;
+    if !verbose {
+        println!(
+            "Received response from {} after {:.1}ms",
+            response.source,
+            roundtrip_ms(response)
+        );
     }
-}
 
-impl DnsPacket {
-    fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
-        self.header.serialize(buffer)?;
+    if verbose {
+        println!(
+            "  Request operation type: {:?}",
+            response_packet.header.opcode
+        );
 
-        for question in &self.questions {
-            question.serialize(buffer)?;
+        if response_packet.header.authoritative_answer {
+            println!("  Response is authoritative");
+        } else {
+            println!("  Response is non-authoritative");
         }
-        return Ok(());
-    }
 
-    fn deserialize(
-        &mut self,
-        header: DnsHeader,
-        cursor: &mut io::Cursor<&[u8]>,
-        all_data: &[u8],
-    ) -> io::Result<()> {
-        self.header = header;
-        for _ in 0..self.header.query_count {
-            self.questions.push(DnsQuestion::deserialize_from(cursor)?);
+        if response_packet.header.recursion_available {
+            println!("  Recursive query resolution is available");
+        } else {
+            println!("  Recursive query resolution is not available");
+        }
+    }
+
+    let extended_rcode = response_packet
+        .edns
+        .as_ref()
+        .map(|edns| edns.full_rcode(response_packet.header.rcode as u16))
+        .filter(|rcode| *rcode > 0x0F);
+    match (response_packet.header.rcode, extended_rcode) {
+        (_, Some(rcode)) => {
+            eprintln!(
+                "  Response code: ERROR: {} ({})",
+                dns::extended_rcode_name(rcode).unwrap_or("Unknown"),
+                rcode
+            );
+        }
+        (ResponseCode::NoError, None) => {
+            if verbose {
+                println!("  Response code: {:?}", response_packet.header.rcode);
+            }
+        }
+        _ => {
+            eprintln!("  Response code: ERROR: {:?}", response_packet.header.rcode);
+        }
+    }
+
+    if let Some(edns) = &response_packet.edns {
+        print_edns(edns, verbose);
+    }
+
+    if response_packet.header.message_truncated {
+        println!("WARNING: Packet header indicates that the data received has been truncated!");
+    }
+    println!();
+
+    if verbose || response_packet.answers.is_empty() {
+        println!("Received {} answers", response_packet.answers.len());
+    }
+
+    for answer in &response_packet.answers {
+        print!(
+            "{} ({:?}, {:?}): ",
+            answer.domain_name, answer.data_class, answer.data_type
+        );
+
+        match RecordData::deserialize_from(answer, resp_bytes) {
+            Ok(data) => print_record_data(answer, &data, split_txt, ttl_format),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                eprintln!(" Unsupported answer data type: {:?}\n", answer.data_type);
+            }
+            Err(e) => eprintln!("ERROR: {}", e),
+        }
+    }
+
+    if verbose || !response_packet.authorities.is_empty() {
+        // TODO: Name server RRs
+        println!(
+            "Received {} authority server records",
+            response_packet.authorities.len()
+        );
+    }
+
+    for authority in &response_packet.authorities {
+        print!(
+            "{} ({:?}, {:?}): ",
+            authority.domain_name, authority.data_class, authority.data_type
+        );
+
+        match RecordData::deserialize_from(authority, resp_bytes) {
+            Ok(data) => print_record_data(authority, &data, split_txt, ttl_format),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                eprintln!(
+                    " Unsupported authority data type: {:?}\n",
+                    authority.data_type
+                );
+            }
+            Err(e) => eprintln!("ERROR: {}", e),
         }
-        for _ in 0..self.header.answer_count {
-            self.answers
-                .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+    }
+
+    if verbose || !response_packet.additionals.is_empty() {
+        // TODO: Name server RRs
+        println!(
+            "Received {} additional records",
+            response_packet.additionals.len()
+        );
+    }
+
+    if verbose {
+        println!("\n Response packet debug: {:?}", response_packet);
+    }
+}
+
+fn print_cname_chain(chain: &dns::CnameChain, question: &DnsQuestion, ttl_format: TtlFormat) {
+    println!();
+    println!("CNAME chain ({} hops):", chain.hops.len());
+    for (index, hop) in chain.hops.iter().enumerate() {
+        if index == 0 {
+            println!("  {}", hop.name);
         }
-        for _ in 0..self.header.nameserver_count {
-            self.authorities
-                .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+        println!("  -> {}  ({})", hop.target, format_ttl(hop.ttl, ttl_format));
+    }
+
+    if chain.records.is_empty() {
+        println!(
+            "  => No {:?} records found for {}",
+            question.query_type,
+            chain.hops.last().map(|hop| &hop.target[..]).unwrap_or("")
+        );
+    }
+    for record in &chain.records {
+        println!("  =>{}", describe(&record.data, record.ttl, ttl_format));
+    }
+}
+
+fn process_input(
+    server_ip: IpAddr,
+    question: DnsQuestion,
+    verbose: bool,
+    split_txt: bool,
+    options: &dns::QueryOptions,
+    ttl_format: TtlFormat,
+) {
+    let response = match send_query(server_ip, question.clone(), options, verbose) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    print_response(&response, verbose, split_txt, ttl_format);
+
+    let has_cname = response
+        .packet
+        .answers
+        .iter()
+        .any(|answer| answer.data_type == QueryType::CNAME);
+    if has_cname && (question.query_type != QueryType::CNAME) {
+        let requery = |next_question| {
+            send_query(server_ip, next_question, options, verbose)
+                .map(|response| records_from_answers(&response))
+        };
+        match dns::follow_cname_chain(&question, records_from_answers(&response), requery) {
+            Ok(chain) if !chain.hops.is_empty() => print_cname_chain(&chain, &question, ttl_format),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                process::exit(1);
+            }
         }
-        for _ in 0..self.header.additional_count {
-            self.additionals
-                .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+    }
+}
+
+fn parse_interval(input: &str) -> Option<Duration> {
+    let (number, multiplier) = match input.chars().last()? {
+        's' => (&input[..input.len() - 1], 1),
+        'm' => (&input[..input.len() - 1], 60),
+        'h' => (&input[..input.len() - 1], 60 * 60),
+        _ => (input, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(n) => n.checked_mul(multiplier).map(Duration::from_secs),
+    }
+}
+
+// Unlike an interval, a TTL can be zero (RFC 1035 section 3.2.1) but has to fit in 32 bits
+fn parse_ttl(input: &str) -> Option<u32> {
+    let (number, multiplier) = match input.chars().last()? {
+        's' => (&input[..input.len() - 1], 1),
+        'm' => (&input[..input.len() - 1], 60),
+        'h' => (&input[..input.len() - 1], 60 * 60),
+        'd' => (&input[..input.len() - 1], 24 * 60 * 60),
+        _ => (input, 1),
+    };
+    number.parse::<u32>().ok()?.checked_mul(multiplier)
+}
+
+fn print_changes(previous: Option<&[Record]>, current: &[Record], ttl_format: TtlFormat) {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => {
+            for record in current {
+                println!("{}", format_record(record, " ", ttl_format));
+            }
+            return;
+        }
+    };
+
+    for change in diff_records(previous, current) {
+        match change {
+            RecordChange::Unchanged(record) => {
+                println!("{}", format_record(record, " ", ttl_format))
+            }
+            RecordChange::Added(record) => {
+                println!("{}", format_record(record, "+", ttl_format).green())
+            }
+            RecordChange::Removed(record) => {
+                println!("{}", format_record(record, "-", ttl_format).red())
+            }
+            RecordChange::Changed { old, new } => {
+                let line = format!(
+                    "~ {} ({:?}): {} ->{}",
+                    new.domain_name,
+                    new.data_type,
+                    old.data,
+                    describe(&new.data, new.ttl, ttl_format)
+                );
+                println!("{}", line.yellow());
+            }
+        }
+    }
+}
+
+fn wait_for_next_query(interval: Duration, records: &[Record]) {
+    if !atty::is(Stream::Stdout) {
+        thread::sleep(interval);
+        return;
+    }
+
+    let shortest_ttl = records.iter().map(|r| r.ttl).min();
+    let start = Instant::now();
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= interval {
+            break;
+        }
+        let remaining = interval - elapsed;
+
+        let mut status = format!(
+            "Next query in {}",
+            time::humanize_duration(remaining.as_secs_f64().ceil() as u64)
+        );
+        if let Some(ttl) = shortest_ttl {
+            let ttl_remaining = ttl.saturating_sub(elapsed.as_secs() as u32);
+            status.push_str(&format!(
+                ", shortest TTL expires in {}",
+                time::humanize_duration(ttl_remaining as u64)
+            ));
+        }
+        print!("\r{}\x1b[K", status);
+        io::stdout().flush().ok();
+
+        thread::sleep(cmp::min(remaining, Duration::from_secs(1)));
+    }
+    print!("\r\x1b[K");
+    io::stdout().flush().ok();
+}
+
+fn watch_input(
+    server_ip: IpAddr,
+    question: DnsQuestion,
+    verbose: bool,
+    interval: Duration,
+    expected: Option<&str>,
+    options: &dns::QueryOptions,
+    ttl_format: TtlFormat,
+) {
+    let mut previous: Option<Vec<Record>> = None;
+    loop {
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        match send_query(server_ip, question.clone(), options, verbose) {
+            Ok(response) => {
+                let current = records_from_answers(&response);
+
+                print!(
+                    "[{}] Received {} answers from {} after {:.1}ms",
+                    timestamp,
+                    current.len(),
+                    response.source,
+                    roundtrip_ms(&response)
+                );
+                match response.packet.header.rcode {
+                    ResponseCode::NoError => println!(),
+                    rcode => println!(" (Response code: ERROR: {:?})", rcode),
+                }
+                print_changes(previous.as_deref(), &current, ttl_format);
+
+                if let Some(expected) = expected {
+                    if current.iter().any(|r| matches_value(r, expected)) {
+                        println!("Received the expected value {}", expected);
+                        return;
+                    }
+                }
+                previous = Some(current);
+            }
+            Err(e) => {
+                eprintln!("[{}] {}", timestamp, e);
+            }
+        }
+
+        let records = match &previous {
+            Some(records) => &records[..],
+            None => &[],
+        };
+        wait_for_next_query(interval, records);
+    }
+}
+
+struct MailAudit {
+    server_ip: IpAddr,
+    verbose: bool,
+    problems: Vec<(Severity, String)>,
+}
+
+impl MailAudit {
+    fn report(&mut self, severity: Severity, message: String) {
+        self.problems.push((severity, message));
+    }
+
+    fn lookup(&mut self, name: &str, qtype: QueryType) -> Vec<Record> {
+        match lookup_records(self.server_ip, name, qtype, self.verbose) {
+            Ok((rcode, records)) => {
+                match rcode {
+                    ResponseCode::NoError | ResponseCode::NameError => {}
+                    rcode => self.report(
+                        Severity::Error,
+                        format!("{:?} query for {} failed: {:?}", qtype, name, rcode),
+                    ),
+                }
+                records
+            }
+            Err(e) => {
+                self.report(
+                    Severity::Error,
+                    format!("{:?} query for {} failed: {}", qtype, name, e),
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    fn lookup_all_txt(&mut self, name: &str) -> Vec<String> {
+        self.lookup(name, QueryType::TXT)
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::TXT(strings) => {
+                    Some(String::from_utf8_lossy(&strings.concat()).into_owned())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn lookup_txt(&mut self, name: &str, prefix: &str) -> Vec<String> {
+        self.lookup_all_txt(name)
+            .into_iter()
+            .filter(|txt| {
+                let lower = txt.to_ascii_lowercase();
+                lower.starts_with(prefix)
+                    && matches!(
+                        lower[prefix.len()..].chars().next(),
+                        None | Some(' ') | Some(';')
+                    )
+            })
+            .collect()
+    }
+
+    fn lookup_addresses(&mut self, name: &str) -> (Vec<IpAddr>, bool) {
+        let mut addresses = Vec::new();
+        let mut is_alias = false;
+        for qtype in &[QueryType::A, QueryType::AAAA] {
+            for record in self.lookup(name, *qtype) {
+                match record.data {
+                    RecordData::A(addr) => addresses.push(IpAddr::V4(addr)),
+                    RecordData::AAAA(addr) => addresses.push(IpAddr::V6(addr)),
+                    RecordData::CNAME(_) => is_alias = true,
+                    _ => {}
+                }
+            }
+        }
+        (addresses, is_alias)
+    }
+
+    fn audit_mx(&mut self, domain: &str) {
+        println!("MX records:");
+        let mut exchanges: Vec<(u16, String)> = self
+            .lookup(domain, QueryType::MX)
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::MX {
+                    preference,
+                    exchange,
+                } => Some((preference, exchange)),
+                _ => None,
+            })
+            .collect();
+        exchanges.sort();
+
+        if exchanges.is_empty() {
+            println!("  None");
+            self.report(
+                Severity::Warning,
+                format!(
+                    "{} has no MX records, mail will be delivered to its A/AAAA address instead",
+                    domain
+                ),
+            );
+            return;
+        }
+
+        for (preference, exchange) in exchanges {
+            if exchange.is_empty() || (exchange == ".") {
+                println!(
+                    "  {} .  (Null MX, the domain does not accept mail)",
+                    preference
+                );
+                continue;
+            }
+            if IpAddr::from_str(&exchange).is_ok() {
+                println!("  {} {}", preference, exchange);
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "MX record points to an IP address ({}) instead of a host name",
+                        exchange
+                    ),
+                );
+                continue;
+            }
+
+            let (addresses, is_alias) = self.lookup_addresses(&exchange);
+            let address_list: Vec<String> = addresses.iter().map(|a| a.to_string()).collect();
+            println!(
+                "  {} {}  -> {}",
+                preference,
+                exchange,
+                if address_list.is_empty() {
+                    String::from("(no addresses)")
+                } else {
+                    address_list.join(", ")
+                }
+            );
+            if is_alias {
+                self.report(Severity::Warning, format!("MX host {} is an alias (CNAME), which is not permitted by RFC 2181 section 10.3", exchange));
+            }
+            if addresses.is_empty() {
+                self.report(
+                    Severity::Error,
+                    format!("MX host {} does not resolve to any address", exchange),
+                );
+            }
+        }
+    }
+
+    fn audit_spf(&mut self, domain: &str) {
+        println!("SPF:");
+        let mut spf = dns::evaluate_spf(domain, self);
+        self.problems.append(&mut spf.problems);
+        match &spf.record {
+            Some(record) => println!("  {}", record),
+            None => {
+                println!("  None");
+                return;
+            }
+        }
+        if self.verbose {
+            for (term, record) in &spf.followed {
+                println!("  {} -> {}", term, record);
+            }
+        }
+
+        println!(
+            "  DNS lookups: {}/{}",
+            spf.lookups,
+            dns::SPF_MAX_DNS_LOOKUPS
+        );
+        println!("  Flattened: {}", spf.flattened());
+    }
+
+    fn audit_dmarc(&mut self, domain: &str) {
+        println!("DMARC:");
+        let dmarc_domain = format!("_dmarc.{}", domain);
+        let records = self.lookup_txt(&dmarc_domain, "v=dmarc1");
+        if records.is_empty() {
+            println!("  None");
+            self.report(
+                Severity::Error,
+                format!("{} has no DMARC record", dmarc_domain),
+            );
+            return;
+        }
+        if records.len() > 1 {
+            self.report(
+                Severity::Error,
+                format!(
+                    "{} has {} DMARC records, receivers will ignore all of them",
+                    dmarc_domain,
+                    records.len()
+                ),
+            );
+        }
+
+        let record = &records[0];
+        println!("  {}", record);
+        let tags = parse_tag_list(record);
+        if tags[0].1 != "DMARC1" {
+            self.report(
+                Severity::Error,
+                format!(
+                    "DMARC version tag should be exactly \"v=DMARC1\", not \"v={}\"",
+                    tags[0].1
+                ),
+            );
+        }
+
+        for policy_tag in &["p", "sp"] {
+            match find_tag(&tags, policy_tag) {
+                Some(policy) => match policy.to_ascii_lowercase().as_str() {
+                    "reject" | "quarantine" => {}
+                    "none" => self.report(
+                        Severity::Warning,
+                        format!(
+                            "DMARC {}=none only monitors, failing mail is still delivered",
+                            policy_tag
+                        ),
+                    ),
+                    _ => self.report(
+                        Severity::Error,
+                        format!("Invalid DMARC {} policy: {}", policy_tag, policy),
+                    ),
+                },
+                None if *policy_tag == "p" => self.report(
+                    Severity::Error,
+                    String::from("DMARC record has no policy (p) tag"),
+                ),
+                None => {}
+            }
+        }
+        if let Some(pct) = find_tag(&tags, "pct") {
+            match pct.parse::<u8>() {
+                Ok(100) => {}
+                Ok(pct) if pct < 100 => self.report(
+                    Severity::Warning,
+                    format!("DMARC policy only applies to {}% of failing mail", pct),
+                ),
+                _ => self.report(Severity::Error, format!("Invalid DMARC pct value: {}", pct)),
+            }
+        }
+        for alignment_tag in &["adkim", "aspf"] {
+            if let Some(alignment) = find_tag(&tags, alignment_tag) {
+                if (alignment != "r") && (alignment != "s") {
+                    self.report(
+                        Severity::Error,
+                        format!("Invalid DMARC {} value: {}", alignment_tag, alignment),
+                    );
+                }
+            }
+        }
+        match find_tag(&tags, "rua") {
+            Some(_) => {}
+            None => self.report(
+                Severity::Warning,
+                String::from("DMARC record has no rua tag, so no aggregate reports will be sent"),
+            ),
+        }
+        for report_tag in &["rua", "ruf"] {
+            if let Some(uris) = find_tag(&tags, report_tag) {
+                for uri in uris.split(',') {
+                    if !uri.trim().to_ascii_lowercase().starts_with("mailto:") {
+                        self.report(
+                            Severity::Warning,
+                            format!(
+                                "DMARC {} destination is not a mailto: URI: {}",
+                                report_tag, uri
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        const KNOWN_TAGS: &[&str] = &[
+            "v", "p", "sp", "pct", "adkim", "aspf", "rua", "ruf", "fo", "rf", "ri", "np", "psd",
+            "t",
+        ];
+        for (tag, _) in &tags {
+            if !KNOWN_TAGS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(tag))
+            {
+                self.report(Severity::Warning, format!("Unknown DMARC tag: {}", tag));
+            }
+        }
+    }
+
+    fn audit_dkim(&mut self, domain: &str, selector: &str) {
+        println!("DKIM ({}):", selector);
+        let dkim_domain = format!("{}._domainkey.{}", selector, domain);
+        let records: Vec<String> = self
+            .lookup(&dkim_domain, QueryType::TXT)
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::TXT(strings) => {
+                    Some(String::from_utf8_lossy(&strings.concat()).into_owned())
+                }
+                _ => None,
+            })
+            .collect();
+        if records.is_empty() {
+            println!("  None");
+            self.report(
+                Severity::Error,
+                format!("{} has no DKIM record", dkim_domain),
+            );
+            return;
+        }
+        if records.len() > 1 {
+            self.report(
+                Severity::Error,
+                format!(
+                    "{} has {} TXT records, verifiers will not know which to use",
+                    dkim_domain,
+                    records.len()
+                ),
+            );
+        }
+
+        let record = &records[0];
+        println!("  {}", record);
+        let tags = parse_tag_list(record);
+        if let Some(index) = tags.iter().position(|(tag, _)| tag == "v") {
+            if (index != 0) || (tags[index].1 != "DKIM1") {
+                self.report(Severity::Error, format!("DKIM record for selector {} has an invalid version tag, it must be \"v=DKIM1\" at the start of the record", selector));
+            }
+        }
+        let flags = find_tag(&tags, "t").unwrap_or("");
+        if flags.split(':').any(|f| f.trim() == "y") {
+            self.report(
+                Severity::Warning,
+                format!("DKIM selector {} is in testing mode (t=y)", selector),
+            );
+        }
+
+        let key_type = find_tag(&tags, "k").unwrap_or("rsa").to_ascii_lowercase();
+        let key = match find_tag(&tags, "p") {
+            Some(key) => key.split_whitespace().collect::<String>(),
+            None => {
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "DKIM record for selector {} has no public key (p) tag",
+                        selector
+                    ),
+                );
+                return;
+            }
+        };
+        if key.is_empty() {
+            println!("  Key has been revoked");
+            self.report(
+                Severity::Warning,
+                format!("DKIM key for selector {} has been revoked", selector),
+            );
+            return;
+        }
+
+        let key_bytes = match alltools::base64::to_bytes(&key) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "DKIM public key for selector {} is not valid base64 ({})",
+                        selector, e
+                    ),
+                );
+                return;
+            }
+        };
+        match key_type.as_str() {
+            "rsa" => match rsa_key_bits(&key_bytes) {
+                Some(bits) => {
+                    println!("  RSA key, {} bits", bits);
+                    if bits < 1024 {
+                        self.report(Severity::Error, format!("DKIM key for selector {} is only {} bits, verifiers will reject it", selector, bits));
+                    } else if bits < 2048 {
+                        self.report(
+                            Severity::Warning,
+                            format!(
+                                "DKIM key for selector {} is only {} bits, 2048 is recommended",
+                                selector, bits
+                            ),
+                        );
+                    }
+                }
+                None => self.report(
+                    Severity::Error,
+                    format!(
+                        "DKIM public key for selector {} is not a valid RSA key",
+                        selector
+                    ),
+                ),
+            },
+            "ed25519" => {
+                println!("  Ed25519 key");
+                if key_bytes.len() != 32 {
+                    self.report(
+                        Severity::Error,
+                        format!(
+                            "DKIM Ed25519 key for selector {} should be 32 bytes but is {} bytes",
+                            selector,
+                            key_bytes.len()
+                        ),
+                    );
+                }
+            }
+            _ => self.report(
+                Severity::Error,
+                format!(
+                    "DKIM record for selector {} has an unknown key type: {}",
+                    selector, key_type
+                ),
+            ),
+        }
+    }
+
+    fn audit_mta_sts(&mut self, domain: &str) {
+        println!("MTA-STS:");
+        let sts_domain = format!("_mta-sts.{}", domain);
+        let records = self.lookup_txt(&sts_domain, "v=stsv1");
+        if records.is_empty() {
+            println!("  None");
+            return;
+        }
+        if records.len() > 1 {
+            self.report(
+                Severity::Error,
+                format!(
+                    "{} has {} MTA-STS records, senders will ignore all of them",
+                    sts_domain,
+                    records.len()
+                ),
+            );
+        }
+
+        let record = &records[0];
+        println!("  {}", record);
+        let tags = parse_tag_list(record);
+        match find_tag(&tags, "id") {
+            Some(id)
+                if !id.is_empty()
+                    && (id.len() <= 32)
+                    && id.chars().all(|c| c.is_ascii_alphanumeric()) => {}
+            Some(id) => self.report(
+                Severity::Error,
+                format!(
+                    "Invalid MTA-STS id (it should be 1 to 32 letters or digits): {}",
+                    id
+                ),
+            ),
+            None => self.report(
+                Severity::Error,
+                String::from("MTA-STS record has no id tag"),
+            ),
+        }
+
+        // NOTE: We don't fetch the policy itself (it is only available over HTTPS) but we can at
+        //       least check that the host that serves it exists.
+        let policy_host = format!("mta-sts.{}", domain);
+        if self.lookup_addresses(&policy_host).0.is_empty() {
+            self.report(
+                Severity::Error,
+                format!(
+                    "MTA-STS policy host {} does not resolve to any address",
+                    policy_host
+                ),
+            );
+        } else {
+            println!("  Policy: https://{}/.well-known/mta-sts.txt", policy_host);
+        }
+    }
+}
+
+impl dns::SpfLookup for MailAudit {
+    fn txt(&mut self, name: &str) -> Vec<String> {
+        self.lookup_all_txt(name)
+    }
+
+    fn mx(&mut self, name: &str) -> Vec<String> {
+        self.lookup(name, QueryType::MX)
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::MX { exchange, .. } => Some(exchange),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn addresses(&mut self, name: &str) -> Vec<IpAddr> {
+        self.lookup_addresses(name).0
+    }
+}
+
+fn audit_mail(server_ip: IpAddr, domain: &str, dkim_selectors: &[String], verbose: bool) {
+    let mut audit = MailAudit {
+        server_ip,
+        verbose,
+        problems: Vec::new(),
+    };
+
+    println!("Mail audit for {}", domain);
+    println!();
+    audit.audit_mx(domain);
+    audit.audit_spf(domain);
+    audit.audit_dmarc(domain);
+    for selector in dkim_selectors {
+        audit.audit_dkim(domain, selector);
+    }
+    audit.audit_mta_sts(domain);
+    println!();
+
+    if audit.problems.is_empty() {
+        println!("{}", "No problems found".green());
+        return;
+    }
+
+    println!("Problems:");
+    for (severity, problem) in &audit.problems {
+        match severity {
+            Severity::Error => println!("  {} {}", "ERROR:".red(), problem),
+            Severity::Warning => println!("  {} {}", "WARNING:".yellow(), problem),
+        }
+    }
+    if audit
+        .problems
+        .iter()
+        .any(|(severity, _)| *severity == Severity::Error)
+    {
+        process::exit(1);
+    }
+}
+
+struct ReverseLookup {
+    address: IpAddr,
+    names: io::Result<Vec<String>>,
+    forward_addresses: Vec<io::Result<Vec<IpAddr>>>,
+}
+
+fn reverse_lookup(
+    server_ip: IpAddr,
+    address: IpAddr,
+    fcrdns: bool,
+    verbose: bool,
+) -> ReverseLookup {
+    let reverse_name = reverse_domain_name(address);
+    let names =
+        lookup_records(server_ip, &reverse_name, QueryType::PTR, verbose).map(|(_, records)| {
+            records
+                .into_iter()
+                .filter_map(|r| match r.data {
+                    RecordData::PTR(name) => Some(name),
+                    _ => None,
+                })
+                .collect::<Vec<String>>()
+        });
+
+    let mut forward_addresses = Vec::new();
+    if fcrdns {
+        let forward_type = match address {
+            IpAddr::V4(_) => QueryType::A,
+            IpAddr::V6(_) => QueryType::AAAA,
+        };
+        for name in names.iter().flatten() {
+            let addresses =
+                lookup_records(server_ip, name, forward_type, verbose).map(|(_, records)| {
+                    records
+                        .into_iter()
+                        .filter_map(|r| match r.data {
+                            RecordData::A(addr) => Some(IpAddr::V4(addr)),
+                            RecordData::AAAA(addr) => Some(IpAddr::V6(addr)),
+                            _ => None,
+                        })
+                        .collect::<Vec<IpAddr>>()
+                });
+            forward_addresses.push(addresses);
+        }
+    }
+
+    ReverseLookup {
+        address,
+        names,
+        forward_addresses,
+    }
+}
+
+fn print_reverse_lookup(lookup: &ReverseLookup, address_width: usize) {
+    let address = format!(
+        "{:width$}",
+        lookup.address.to_string(),
+        width = address_width
+    );
+    let names = match &lookup.names {
+        Ok(names) => names,
+        Err(e) => {
+            println!("{}  {} {}", address, "ERROR:".red(), e);
+            return;
+        }
+    };
+    if names.is_empty() {
+        println!("{}  -", address);
+        return;
+    }
+
+    for (index, name) in names.iter().enumerate() {
+        match lookup.forward_addresses.get(index) {
+            None => println!("{}  {}", address, name),
+            Some(Ok(forward)) if forward.contains(&lookup.address) => {
+                println!("{}  {}  {}", address, name, "(forward-confirmed)".green())
+            }
+            Some(Ok(forward)) if forward.is_empty() => println!(
+                "{}  {}  {}",
+                address,
+                name,
+                "(NOT forward-confirmed, the name does not resolve)".red()
+            ),
+            Some(Ok(forward)) => {
+                let forward: Vec<String> = forward.iter().map(|a| a.to_string()).collect();
+                let status = format!(
+                    "(NOT forward-confirmed, the name resolves to {})",
+                    forward.join(", ")
+                );
+                println!("{}  {}  {}", address, name, status.red());
+            }
+            Some(Err(e)) => println!(
+                "{}  {}  {} Failed to resolve the name: {}",
+                address,
+                name,
+                "ERROR:".red(),
+                e
+            ),
+        }
+    }
+}
+
+fn bulk_reverse_lookup(
+    server_ip: IpAddr,
+    addresses: Vec<IpAddr>,
+    jobs: usize,
+    fcrdns: bool,
+    verbose: bool,
+) {
+    let address_count = addresses.len();
+    let address_width = addresses
+        .iter()
+        .map(|a| a.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let addresses = Arc::new(addresses);
+    let next_index = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let mut workers = Vec::new();
+    for _ in 0..cmp::min(jobs, address_count) {
+        let addresses = Arc::clone(&addresses);
+        let next_index = Arc::clone(&next_index);
+        let sender = sender.clone();
+        workers.push(thread::spawn(move || loop {
+            let index = next_index.fetch_add(1, Ordering::SeqCst);
+            if index >= addresses.len() {
+                break;
+            }
+            let lookup = reverse_lookup(server_ip, addresses[index], fcrdns, verbose);
+            if sender.send((index, lookup)).is_err() {
+                break;
+            }
+        }));
+    }
+    drop(sender);
+
+    // NOTE: Lookups complete in whatever order the server answers them, so we hold on to results
+    //       until everything before them has been printed to keep the output in address order.
+    let mut pending = BTreeMap::new();
+    let mut next_to_print = 0;
+    let mut named_count = 0;
+    let mut confirmed_count = 0;
+    let mut failed_count = 0;
+    for (index, lookup) in receiver {
+        pending.insert(index, lookup);
+        while let Some(lookup) = pending.remove(&next_to_print) {
+            print_reverse_lookup(&lookup, address_width);
+            match &lookup.names {
+                Ok(names) if !names.is_empty() => named_count += 1,
+                Ok(_) => {}
+                Err(_) => failed_count += 1,
+            }
+            let confirmed = lookup
+                .forward_addresses
+                .iter()
+                .any(|forward| match forward {
+                    Ok(forward) => forward.contains(&lookup.address),
+                    Err(_) => false,
+                });
+            if confirmed {
+                confirmed_count += 1;
+            }
+            next_to_print += 1;
+        }
+    }
+    for worker in workers {
+        worker
+            .join()
+            .expect("reverse lookup worker thread panicked");
+    }
+
+    println!();
+    print!(
+        "Looked up {} addresses: {} with PTR records",
+        address_count, named_count
+    );
+    if fcrdns {
+        print!(", {} forward-confirmed", confirmed_count);
+    }
+    println!(", {} failed", failed_count);
+    if failed_count != 0 {
+        process::exit(1);
+    }
+}
+
+fn parse_query_type(input: &str) -> Option<QueryType> {
+    match input.to_ascii_uppercase().as_str() {
+        "A" => Some(QueryType::A),
+        "CNAME" => Some(QueryType::CNAME),
+        "MX" => Some(QueryType::MX),
+        "NS" => Some(QueryType::NS),
+        "TXT" => Some(QueryType::TXT),
+        "PTR" => Some(QueryType::PTR),
+        "AAAA" => Some(QueryType::AAAA),
+        "SRV" => Some(QueryType::SRV),
+        _ => None,
+    }
+}
+
+// Splits off the first `count` whitespace-separated fields and returns them along with whatever
+// remains, so that record data containing spaces (e.g TXT) is kept intact.
+fn split_fields(input: &str, count: usize) -> (Vec<&str>, &str) {
+    let mut fields = Vec::with_capacity(count);
+    let mut remaining = input.trim();
+    while fields.len() < count && !remaining.is_empty() {
+        let end = remaining
+            .find(char::is_whitespace)
+            .unwrap_or(remaining.len());
+        fields.push(&remaining[..end]);
+        remaining = remaining[end..].trim_start();
+    }
+    (fields, remaining)
+}
+
+fn parse_record(name: &str, type_str: &str, data: &str, ttl: u32) -> Result<Record, String> {
+    let data_type = match parse_query_type(type_str) {
+        Some(data_type) => data_type,
+        None => return Err(format!("Unsupported record type: {}", type_str)),
+    };
+    match RecordData::parse(data_type, data) {
+        Ok(data) => Ok(Record::new(name.trim_end_matches('.'), data, ttl)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn add_update_command(message: &mut dns::UpdateMessage, command: &str) -> Result<(), String> {
+    let (fields, data) = split_fields(command, 4);
+    let name = fields
+        .get(1)
+        .map(|name| String::from(name.trim_end_matches('.')));
+    match (fields.first().copied(), name) {
+        (Some("add"), Some(name)) if (fields.len() == 4) && !data.is_empty() => {
+            let ttl = match parse_ttl(fields[2]) {
+                Some(ttl) => ttl,
+                None => return Err(format!("Invalid TTL: {}", fields[2])),
+            };
+            let record = parse_record(&name, fields[3], data, ttl)?;
+            message.updates.push(dns::Update::Add(record));
+        }
+        (Some(verb @ "delete"), Some(name)) | (Some(verb @ "require"), Some(name)) => {
+            let data = [fields.get(3).copied().unwrap_or(""), data].join(" ");
+            let is_delete = verb == "delete";
+            match fields.get(2) {
+                None if is_delete => message.updates.push(dns::Update::DeleteName(name)),
+                None => message
+                    .prerequisites
+                    .push(dns::Prerequisite::NameInUse(name)),
+                Some(type_str) if data.trim().is_empty() => {
+                    let data_type = match parse_query_type(type_str) {
+                        Some(data_type) => data_type,
+                        None => return Err(format!("Unsupported record type: {}", type_str)),
+                    };
+                    if is_delete {
+                        message
+                            .updates
+                            .push(dns::Update::DeleteRecordSet(name, data_type));
+                    } else {
+                        message
+                            .prerequisites
+                            .push(dns::Prerequisite::RecordSetExists(name, data_type));
+                    }
+                }
+                Some(type_str) => {
+                    let record = parse_record(&name, type_str, &data, 0)?;
+                    if is_delete {
+                        message.updates.push(dns::Update::DeleteRecord(record));
+                    } else {
+                        message
+                            .prerequisites
+                            .push(dns::Prerequisite::RecordExists(record));
+                    }
+                }
+            }
+        }
+        (Some("prohibit"), Some(name)) if fields.len() <= 3 && data.is_empty() => {
+            match fields.get(2) {
+                None => message
+                    .prerequisites
+                    .push(dns::Prerequisite::NameNotInUse(name)),
+                Some(type_str) => match parse_query_type(type_str) {
+                    Some(data_type) => message
+                        .prerequisites
+                        .push(dns::Prerequisite::RecordSetDoesNotExist(name, data_type)),
+                    None => return Err(format!("Unsupported record type: {}", type_str)),
+                },
+            }
+        }
+        _ => return Err(format!("Invalid update command: {}", command)),
+    }
+    Ok(())
+}
+
+fn parse_tsig_key(input: &str) -> Result<dns::TsigKey, String> {
+    let parts: Vec<&str> = input.split(':').collect();
+    let (name, secret) = match parts.as_slice() {
+        [name, secret] => (name, secret),
+        [algorithm, name, secret] if algorithm.eq_ignore_ascii_case(dns::HMAC_SHA256) => {
+            (name, secret)
+        }
+        [algorithm, _, _] => {
+            return Err(format!(
+                "Unsupported TSIG algorithm {}, only {} is supported",
+                algorithm,
+                dns::HMAC_SHA256
+            ))
+        }
+        _ => return Err(format!("Invalid TSIG key: {}", input)),
+    };
+    match dns::decode_secret(secret) {
+        Ok(secret) => Ok(dns::TsigKey::new(name, &secret)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Updates have to go to the primary server for the zone, which is named in the zone's SOA record
+fn find_primary_server(zone: &str, verbose: bool) -> Result<IpAddr, String> {
+    let server_ip = get_server_ip(None, verbose);
+    let mname = match lookup_records(server_ip, zone, QueryType::SOA, verbose) {
+        Ok((_, records)) => records.into_iter().find_map(|record| match record.data {
+            RecordData::SOA { mname, .. } => Some(mname),
+            _ => None,
+        }),
+        Err(e) => return Err(format!("Failed to look up the SOA for {}: {}", zone, e)),
+    };
+    let mname = match mname {
+        Some(mname) => mname,
+        None => return Err(format!("No SOA record found for {}", zone)),
+    };
+    match lookup_records(server_ip, &mname, QueryType::A, verbose) {
+        Ok((_, records)) => match records.iter().find_map(|record| match record.data {
+            RecordData::A(addr) => Some(IpAddr::V4(addr)),
+            _ => None,
+        }) {
+            Some(addr) => {
+                if verbose {
+                    println!("Sending update to the primary server {} ({})", mname, addr);
+                }
+                Ok(addr)
+            }
+            None => Err(format!("No address found for the primary server {}", mname)),
+        },
+        Err(e) => Err(format!(
+            "Failed to look up the primary server {}: {}",
+            mname, e
+        )),
+    }
+}
+
+fn update_main(program: &str, args: &[String]) {
+    let mut opts_spec = Options::new();
+    opts_spec.optflag("h", "help", "print this help menu");
+    opts_spec.optflag("v", "verbose", "print additional data");
+    opts_spec.optopt(
+        "s",
+        "server",
+        "the address of the server to send the update to. Defaults to the primary server named in the zone's SOA record",
+        "IP-ADDR",
+    );
+    opts_spec.optopt("z", "zone", "the zone to update", "ZONE");
+    opts_spec.optopt(
+        "y",
+        "key",
+        "sign the update with the given TSIG key, formatted as [hmac-sha256:]NAME:SECRET with a base64 secret",
+        "KEY",
+    );
+    opts_spec.optopt(
+        "K",
+        "key-file",
+        "sign the update with the TSIG key in the given BIND key file (as written by tsig-keygen)",
+        "FILE",
+    );
+    let opts = match opts_spec.parse(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Try '{} update --help' for more information", program);
+            process::exit(1);
         }
-        Ok(())
-    }
+    };
 
-    fn deserialize_from(
-        header: DnsHeader,
-        cursor: &mut io::Cursor<&[u8]>,
-        all_data: &[u8],
-    ) -> io::Result<DnsPacket> {
-        let mut result = DnsPacket::default();
-        result.deserialize(header, cursor, all_data)?;
-        Ok(result)
+    if opts.opt_present("h") {
+        println!("Send a dynamic DNS update (RFC 2136), made up of the given commands, to a zone");
+        println!();
+        println!("Commands:");
+        println!("  add NAME TTL TYPE DATA        add a record");
+        println!("  delete NAME [TYPE [DATA]]     delete all records for a name, a record set or a single record");
+        println!("  require NAME [TYPE [DATA]]    only apply the update if the name, record set or record exists");
+        println!("  prohibit NAME [TYPE]          only apply the update if the name or record set does not exist");
+        println!();
+        println!(
+            "Example: {} update -z example.com -y ci-key:c2VjcmV0 \"delete www.example.com A\" \"add www.example.com 300 A 192.0.2.1\"",
+            program
+        );
+        println!();
+        print_usage(&format!("{} update", program), opts_spec);
+        return;
     }
-}
 
-fn format_time(total_sec: u32) -> String {
-    let mut result = String::new();
-    let mut time = total_sec;
-    let seconds = time % 60;
-    time /= 60;
-    let minutes = time % 60;
-    time /= 60;
-    let hours = time % 24;
-    let days = time / 24;
-
-    if days != 0 {
-        if days == 1 {
-            result.push_str("1 day");
-        } else {
-            result.push_str(&format!("{} days", days));
-        }
-    }
-    if hours != 0 {
-        if result.len() > 0 {
-            result.push_str(", ");
-        }
-        if hours == 1 {
-            result.push_str("1 hour");
-        } else {
-            result.push_str(&format!("{} hours", hours));
-        }
-    }
-    if minutes != 0 {
-        if result.len() > 0 {
-            result.push_str(", ");
-        }
-        if minutes == 1 {
-            result.push_str("1 minute");
-        } else {
-            result.push_str(&format!("{} minutes", minutes));
-        }
-    }
-    if (seconds != 0) || (result.len() == 0) {
-        if result.len() > 0 {
-            result.push_str(", ");
-        }
-        if seconds == 1 {
-            result.push_str("1 second");
-        } else {
-            result.push_str(&format!("{} seconds", seconds));
+    let verbose = opts.opt_present("v");
+    let zone = match opts.opt_str("z") {
+        Some(zone) => zone,
+        None => {
+            eprintln!("Bad argument: The zone to update must be given (--zone)");
+            process::exit(1);
         }
+    };
+    if opts.free.is_empty() {
+        eprintln!("No update commands provided");
+        eprintln!("Try '{} update --help' for more information", program);
+        process::exit(1);
     }
-    result
-}
-
-fn process_input(server_ip: IpAddr, domain: &str, qtype: QueryType, reverse: bool, verbose: bool) {
-    let mut rng = rand::thread_rng();
 
-    let mut request = DnsPacket::default();
-    request.header.request_id = rng.gen::<u16>();
-    request.header.recursion_desired = true; // TODO: Maybe we want to be able to ask for no recursion?
-    request.header.query_count = 1;
-
-    let mut question = DnsQuestion::default();
-    if reverse {
-        match IpAddr::from_str(domain) {
-            Ok(IpAddr::V4(v4addr)) => {
-                question.domain_name = format!(
-                    "{}.{}.{}.{}.in-addr.arpa",
-                    v4addr.octets()[3],
-                    v4addr.octets()[2],
-                    v4addr.octets()[1],
-                    v4addr.octets()[0]
-                );
-                question.query_type = QueryType::PTR;
-            }
-            Ok(IpAddr::V6(_)) => {
-                eprintln!("Reverse IPv6 lookups are not currently supported");
+    let key = match (opts.opt_str("y"), opts.opt_str("K")) {
+        (Some(_), Some(_)) => {
+            eprintln!("Bad argument: Only one of --key and --key-file can be given");
+            process::exit(1);
+        }
+        (Some(key_str), None) => match parse_tsig_key(&key_str) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("{}", e);
                 process::exit(1);
             }
-            Err(err) => {
-                eprintln!(
-                    "Failed to parse request string as IP address {}: {}",
-                    domain, err
-                );
-                process::exit(1);
+        },
+        (None, Some(key_file)) => {
+            match std::fs::read_to_string(&key_file)
+                .and_then(|config| dns::TsigKey::from_bind_config(&config))
+            {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    eprintln!("Failed to read TSIG key from {}: {}", key_file, e);
+                    process::exit(1);
+                }
             }
-        };
-    } else {
-        question.domain_name = String::from(domain);
-        question.query_type = qtype;
-    }
-
-    question.query_class = DomainClass::Internet;
-    request.questions.push(question);
+        }
+        (None, None) => None,
+    };
 
-    let mut request_data_buf: Vec<u8> = Vec::new();
-    match request.serialize(&mut request_data_buf) {
-        Ok(()) => {}
-        Err(e) => {
-            eprintln!("Failed to serialize request: {}", e);
+    let mut message = dns::UpdateMessage::new(&zone);
+    for command in &opts.free {
+        if let Err(e) = add_update_command(&mut message, command) {
+            eprintln!("{}", e);
             process::exit(1);
         }
     }
 
-    const MAX_PORT_SELECT_ATTEMPTS: usize = 15;
-    let mut port_select_attempts = 0;
-    let socket: net::UdpSocket;
-    let socket_addr = IpAddr::from_str("0.0.0.0").unwrap();
-    let port_distribution = rand::distributions::Uniform::<u16>::new(49152, 65535); // Taken from the recommendation at https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.xhtml
-    loop {
-        let port = rng.sample(port_distribution);
-        match net::UdpSocket::bind((socket_addr, port)) {
-            Ok(new_sock) => {
-                socket = new_sock;
-                break;
-            }
+    let server_ip = match opts.opt_str("s") {
+        Some(server) => get_server_ip(Some(server), verbose),
+        None => match find_primary_server(&zone, verbose) {
+            Ok(server_ip) => server_ip,
             Err(e) => {
-                if port_select_attempts >= MAX_PORT_SELECT_ATTEMPTS {
+                eprintln!("{}", e);
+                eprintln!("Use --server to give the server address explicitly");
+                process::exit(1);
+            }
+        },
+    };
+
+    let server = net::SocketAddr::from((server_ip, dns::DNS_PORT));
+    match dns::send_update(server, &message, key.as_ref(), dns::DEFAULT_TIMEOUT) {
+        Ok(response) => {
+            if verbose {
+                println!(
+                    "Received response from {} after {:.1}ms",
+                    response.source,
+                    roundtrip_ms(&response)
+                );
+            }
+            match response.packet.header.rcode {
+                ResponseCode::NoError => {
+                    print!(
+                        "Update of {} accepted by {} ({} prerequisites, {} changes)",
+                        message.zone,
+                        server_ip,
+                        message.prerequisites.len(),
+                        message.updates.len()
+                    );
+                    if key.is_some() {
+                        print!(", response signature verified");
+                    }
+                    println!();
+                }
+                rcode => {
                     eprintln!(
-                        "Failed to bind to a local port after {} retries. Terminating.",
-                        MAX_PORT_SELECT_ATTEMPTS
+                        "Update of {} was rejected by {}: {:?}",
+                        message.zone, server_ip, rcode
                     );
                     process::exit(1);
-                } else {
-                    if verbose {
-                        eprintln!("Failed to bind to local port {} ({}), retrying", port, e);
-                    }
-                    port_select_attempts += 1;
                 }
             }
         }
-    }
-
-    let dest_addr = net::SocketAddr::from((server_ip, 53));
-    socket
-        .set_read_timeout(Some(Duration::from_secs(5)))
-        .expect("failed to set socket read timeout");
-    socket
-        .set_write_timeout(Some(Duration::from_secs(5)))
-        .expect("failed to set socket write timeout");
-    let send_instant = Instant::now();
-    match socket.send_to(&request_data_buf, dest_addr) {
-        Ok(bytes_written) => {
-            if verbose {
-                println!("Successfully wrote {} bytes of request to the network, waiting for response...", bytes_written);
-            }
-        }
         Err(e) => {
-            eprintln!("Failed to send request to the network: {}", e);
+            eprintln!("Update of {} failed: {}", message.zone, e);
             process::exit(1);
         }
     }
+}
 
-    let mut resp_buffer = [0; 512];
-    let (bytes_read, src_addr) = match socket.recv_from(&mut resp_buffer) {
-        Ok((bytes_read, src_addr)) => (bytes_read, src_addr),
+fn mdns_input(
+    domain: &str,
+    qtype: QueryType,
+    reverse: bool,
+    ipv6: bool,
+    window: Duration,
+    verbose: bool,
+    ttl_format: TtlFormat,
+) {
+    let question = build_question(domain, qtype, reverse);
+    if verbose {
+        println!(
+            "Sending multicast query for {} ({:?}), waiting {} for responses...",
+            question.domain_name,
+            question.query_type,
+            time::humanize_duration(window.as_secs())
+        );
+    }
+    let responses = match dns::send_multicast_query(vec![question], ipv6, window) {
+        Ok(responses) => responses,
         Err(e) => {
-            eprintln!("Failed to read response from the network: {}", e);
+            eprintln!("{}", e);
             process::exit(1);
         }
     };
-    let roundtrip_ms = (send_instant.elapsed().as_micros() as f64) / 1000.0;
-    let resp_bytes = &resp_buffer[..bytes_read];
 
-    if src_addr != dest_addr {
-        eprintln!(
-            "Received answer from unexpected source address: {} Ignoring...",
-            src_addr
+    for response in &responses {
+        println!(
+            "Response from {} after {:.1}ms",
+            response.source,
+            roundtrip_ms(response)
         );
+        for (_, record) in dns::collect_records(std::slice::from_ref(response)) {
+            println!("{}", format_record(&record, " ", ttl_format));
+        }
+        println!();
+    }
+    println!("Received {} responses", responses.len());
+    if responses.is_empty() {
         process::exit(1);
     }
+}
 
+fn browse_services(service_types: &[String], ipv6: bool, window: Duration, verbose: bool) {
+    // Service types are always in the .local domain, so allow it to be left off
+    let service_types: Vec<String> = service_types
+        .iter()
+        .map(|t| {
+            let t = t.trim_end_matches('.');
+            if t.to_ascii_lowercase().ends_with(".local") {
+                String::from(t)
+            } else {
+                format!("{}.local", t)
+            }
+        })
+        .collect();
     if verbose {
-        print!(
-            "Received {} byte response from {} after {:.1}ms - ",
-            bytes_read, src_addr, roundtrip_ms
-        );
-        for b in resp_bytes {
-            print!("{:x}", b);
+        if service_types.is_empty() {
+            println!("Browsing for all services on the local network...");
+        } else {
+            println!(
+                "Browsing for {} on the local network...",
+                service_types.join(", ")
+            );
         }
-        println!();
-    } else {
-        println!(
-            "Received response from {} after {:.1}ms",
-            src_addr, roundtrip_ms
-        );
     }
 
-    let mut cursor = io::Cursor::new(resp_bytes);
-    let response_header = match DnsHeader::deserialize_from(&mut cursor) {
-        Ok(header) => header,
-        Err(e) => {
-            eprintln!("Failed to deserialise header from network: {}", e);
-            process::exit(1);
-        }
-    };
-    let response = match DnsPacket::deserialize_from(response_header, &mut cursor, resp_bytes) {
-        Ok(packet) => packet,
+    let instances = match dns::browse_services(&service_types, ipv6, window) {
+        Ok(instances) => instances,
         Err(e) => {
-            eprintln!("Failed to deserialise packet from network: {}", e);
+            eprintln!("{}", e);
             process::exit(1);
         }
     };
 
-    if response.header.request_id != request.header.request_id {
-        println!("WARNING: Received packet header contains a request ID that does not match our request!");
-    }
-
-    if verbose {
-        println!("  Request operation type: {:?}", response.header.opcode);
-
-        if response.header.authoritative_answer {
-            println!("  Response is authoritative");
-        } else {
-            println!("  Response is non-authoritative");
-        }
-
-        if response.header.recursion_available {
-            println!("  Recursive query resolution is available");
-        } else {
-            println!("  Recursive query resolution is not available");
+    let mut current_type = None;
+    for instance in &instances {
+        if current_type != Some(&instance.service_type) {
+            if current_type.is_some() {
+                println!();
+            }
+            println!("{}", instance.service_type.bold());
+            current_type = Some(&instance.service_type);
         }
-    }
-
-    match response.header.rcode {
-        ResponseCode::NoError => {
-            if verbose {
-                println!("  Response code: {:?}", response.header.rcode);
+        println!("  {}", instance.short_name());
+        match (&instance.host, instance.port) {
+            (Some(host), Some(port)) => {
+                let addresses: Vec<String> =
+                    instance.addresses.iter().map(|a| a.to_string()).collect();
+                if addresses.is_empty() {
+                    println!("    Host: {}:{}", host, port);
+                } else {
+                    println!("    Host: {}:{} ({})", host, port, addresses.join(", "));
+                }
             }
+            _ => println!("    Host: unknown (no SRV record received)"),
         }
-        _ => {
-            eprintln!("  Response code: ERROR: {:?}", response.header.rcode);
+        if !instance.txt.is_empty() {
+            println!("    TXT: {}", instance.txt.join(" "));
+        }
+        if verbose {
+            if let Some(responder) = instance.responder {
+                println!("    Responder: {}", responder);
+            }
         }
     }
 
-    if response.header.message_truncated {
-        println!("WARNING: Packet header indicates that the data received has been truncated!");
+    if instances.is_empty() {
+        println!("No services found");
+        process::exit(1);
     }
     println!();
+    println!("Found {} service instances", instances.len());
+}
 
-    if verbose || (response.answers.len() == 0) {
-        println!("Received {} answers", response.answers.len());
+fn load_hosts_files(override_file: Option<&str>) -> Vec<dns::HostsFile> {
+    let mut result = Vec::new();
+    if let Some(override_file) = override_file {
+        match dns::HostsFile::load(std::path::Path::new(override_file)) {
+            Ok(hosts) => result.push(hosts),
+            Err(e) => {
+                eprintln!("Failed to read hosts file {}: {}", override_file, e);
+                process::exit(1);
+            }
+        }
     }
 
-    for answer in &response.answers {
-        print!(
-            "{} ({:?}, {:?}): ",
-            answer.domain_name, answer.data_class, answer.data_type
-        );
+    let system_path = dns::default_hosts_path();
+    match dns::HostsFile::load(&system_path) {
+        Ok(hosts) => result.push(hosts),
+        Err(e) => eprintln!(
+            "WARNING: Failed to read hosts file {}: {}",
+            system_path.display(),
+            e
+        ),
+    }
+    result
+}
 
-        match answer.data_type {
-            QueryType::A => {
-                const EXPECTED_LEN: usize = 4;
-                if answer.data.len() != EXPECTED_LEN {
-                    eprintln!("Response for data type A is expected to contain exactly {} bytes and instead contained {} bytes. Ignoring...", EXPECTED_LEN, answer.data.len());
-                } else {
-                    println!(
-                        "  {}.{}.{}.{}  (TTL: {})",
-                        answer.data[0],
-                        answer.data[1],
-                        answer.data[2],
-                        answer.data[3],
-                        format_time(answer.ttl)
-                    );
-                }
+fn same_values(a: &[String], b: &[String]) -> bool {
+    let normalise = |values: &[String]| {
+        let mut result: Vec<String> = values
+            .iter()
+            .map(|v| v.trim_end_matches('.').to_ascii_lowercase())
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    };
+    normalise(a) == normalise(b)
+}
+
+// Emulates the "files dns" order of the system resolver: if a hosts file has an answer then DNS is
+// never asked. We still ask DNS afterwards so that stale hosts entries can be pointed out.
+// Returns false if the name wasn't in any hosts file, in which case it should be looked up normally.
+fn hosts_input(
+    server_ip: IpAddr,
+    query: &str,
+    qtype: QueryType,
+    reverse: bool,
+    override_file: Option<&str>,
+    verbose: bool,
+    ttl_format: TtlFormat,
+) -> bool {
+    if !reverse && (qtype != QueryType::A) && (qtype != QueryType::AAAA) {
+        if verbose {
+            println!(
+                "Hosts files only contain addresses, answering {:?} query from DNS",
+                qtype
+            );
+        }
+        return false;
+    }
+
+    let question = build_question(query, qtype, reverse);
+    let hosts_files = load_hosts_files(override_file);
+    for hosts in &hosts_files {
+        let mut answers: Vec<(String, usize)> = Vec::new();
+        if reverse {
+            // build_question has already checked that the query is a valid address
+            let address = IpAddr::from_str(query).unwrap();
+            for entry in hosts.lookup_address(address) {
+                answers.extend(entry.names.iter().map(|name| (name.clone(), entry.line)));
             }
-            QueryType::CNAME => {
-                let mut cursor = io::Cursor::new(&answer.data[..]);
-                let name_bytes = match deserialize_name(&mut cursor, resp_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse name data from CNAME record: {}", e);
-                        continue;
-                    }
-                };
-                let name = match String::from_utf8(name_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse canonical name as UTF8: {}", e);
-                        continue;
-                    }
+        } else {
+            for entry in hosts.lookup_name(query) {
+                let family_matches = match entry.address {
+                    IpAddr::V4(_) => qtype == QueryType::A,
+                    IpAddr::V6(_) => qtype == QueryType::AAAA,
                 };
-                println!("  {}  (TTL: {})", name, format_time(answer.ttl));
+                if family_matches {
+                    answers.push((entry.address.to_string(), entry.line));
+                }
             }
-            QueryType::MX => {
-                const MIN_LEN: usize = 3;
-                if answer.data.len() < MIN_LEN {
-                    eprintln!("Response for data type MX is expected to contain at least {} bytes and instead contained {} bytes. Ignoring...", MIN_LEN, answer.data.len());
+        }
+        if answers.is_empty() {
+            continue;
+        }
+
+        println!("Answered from hosts file {}", hosts.path.display());
+        println!();
+        for (value, line) in &answers {
+            println!(
+                "{} ({:?}):  {}  (line {})",
+                question.domain_name, question.query_type, value, line
+            );
+        }
+        println!();
+
+        match lookup_records(
+            server_ip,
+            &question.domain_name,
+            question.query_type,
+            verbose,
+        ) {
+            Ok((rcode, records)) => {
+                let dns_values: Vec<String> = records
+                    .iter()
+                    .filter(|r| r.data_type == question.query_type)
+                    .map(|r| r.data.to_string())
+                    .collect();
+                println!("For comparison, DNS server {} answered:", server_ip);
+                if dns_values.is_empty() {
+                    println!("  No {:?} records ({:?})", question.query_type, rcode);
+                }
+                for record in records
+                    .iter()
+                    .filter(|r| r.data_type == question.query_type)
+                {
+                    println!("{}", format_record(record, " ", ttl_format));
+                }
+                println!();
+                let values: Vec<String> = answers.into_iter().map(|(value, _)| value).collect();
+                if same_values(&values, &dns_values) {
+                    println!("The hosts file agrees with DNS");
                 } else {
-                    let mut mx_cursor = io::Cursor::new(&answer.data[..]);
-                    let preference = mx_cursor.read_u16::<BigEndian>().unwrap();
-                    let name_bytes = match deserialize_name(&mut mx_cursor, resp_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!(
-                                "ERROR: Failed to parse exchange name data from MX record: {}",
-                                e
-                            );
-                            continue;
-                        }
-                    };
-                    let name = match String::from_utf8(name_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!("ERROR: Failed to parse exchange name as UTF8: {}", e);
-                            continue;
-                        }
-                    };
                     println!(
-                        "  {}  (Priority: {}, TTL: {})",
-                        name,
-                        preference,
-                        format_time(answer.ttl)
+                        "{}",
+                        "WARNING: The hosts file entry overrides a different answer from DNS"
+                            .yellow()
                     );
                 }
             }
-            QueryType::NS => {
-                let mut cursor = io::Cursor::new(&answer.data[..]);
-                let name_bytes = match deserialize_name(&mut cursor, resp_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!(
-                            "ERROR: Failed to parse name-server data from NS record: {}",
-                            e
-                        );
-                        continue;
-                    }
-                };
-                let name = match String::from_utf8(name_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!(
-                            "ERROR: Failed to parse authoritative name-server as UTF8: {}",
-                            e
-                        );
-                        continue;
-                    }
-                };
-                println!("  {}  (TTL: {})", name, format_time(answer.ttl));
-            }
-            QueryType::TXT => {
-                // TODO: The RFC says "one or more character strings". A string can only be 256-chars long (due to the single-byte length prefix). Do we just concatenate
-                //       them if they're longer than that? Should we be looping until we get a length < 256?
-                let mut txt_cursor = io::Cursor::new(&answer.data[..]);
-                let txt_bytes = match deserialize_bytes(&mut txt_cursor) {
-                    Ok(bytes) => bytes,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse data from TXT record: {}", e);
-                        continue;
-                    }
-                };
-                let txt = match String::from_utf8(txt_bytes) {
-                    Ok(txt) => txt,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse TXT data as UTF8: {}", e);
-                        continue;
-                    }
-                };
-                println!("  {}  (TTL: {})", txt, format_time(answer.ttl));
-            }
-            QueryType::PTR => {
-                let mut cursor = io::Cursor::new(&answer.data[..]);
-                let name_bytes = match deserialize_name(&mut cursor, resp_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse domain name from PTR record: {}", e);
-                        continue;
-                    }
-                };
-                let name = match String::from_utf8(name_bytes) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to parse domain name as UTF8: {}", e);
-                        continue;
-                    }
-                };
-                println!("  {}  (TTL: {})", name, format_time(answer.ttl));
-            }
-            // TODO: QueryType::AAAA at least?  https://tools.ietf.org/html/rfc3596
-            _ => {
-                eprintln!(" Unsupported answer data type: {:?}\n", answer.data_type);
-            }
+            Err(e) => println!("For comparison, DNS server {} failed: {}", server_ip, e),
         }
+        return true;
     }
 
-    if verbose || (response.authorities.len() != 0) {
-        // TODO: Name server RRs
-        println!(
-            "Received {} authority server records",
-            response.authorities.len()
-        );
+    let paths: Vec<String> = hosts_files
+        .iter()
+        .map(|hosts| hosts.path.display().to_string())
+        .collect();
+    println!(
+        "Not found in hosts files ({}), answered from DNS server {}",
+        paths.join(", "),
+        server_ip
+    );
+    false
+}
+
+fn format_capture_time(timestamp: Duration) -> String {
+    match Utc
+        .timestamp_opt(timestamp.as_secs() as i64, timestamp.subsec_nanos())
+        .single()
+    {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+        None => format!("{:.6}", timestamp.as_secs_f64()),
     }
+}
 
-    for authority in &response.authorities {
-        print!(
-            "{} ({:?}, {:?}): ",
-            authority.domain_name, authority.data_class, authority.data_type
+fn describe_capture_question(packet: &dns::DnsPacket) -> String {
+    match packet.questions.first() {
+        Some(question) => format!("{} ({:?})", question.domain_name, question.query_type),
+        None => String::from("(no question)"),
+    }
+}
+
+fn pcap_input(path: &str, verbose: bool, ttl_format: TtlFormat) {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let analysis = match dns::analyse_capture(&data) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    let mut unanswered = 0;
+    let mut unsolicited = 0;
+    let mut latencies = Vec::new();
+    let mut rcodes: BTreeMap<String, usize> = BTreeMap::new();
+    for transaction in &analysis.transactions {
+        let (first, first_packet) = match (&transaction.query, &transaction.response) {
+            (Some(query), _) => query,
+            (None, Some(response)) => response,
+            (None, None) => continue,
+        };
+        let (client, server) = if transaction.query.is_some() {
+            (first.source, first.destination)
+        } else {
+            (first.destination, first.source)
+        };
+        let outcome = match (&transaction.response, transaction.latency()) {
+            (Some((_, response)), latency) => {
+                let rcode = format!("{:?}", response.header.rcode);
+                *rcodes.entry(rcode.clone()).or_insert(0) += 1;
+                let answers = format!("{} answers", response.answers.len());
+                match latency {
+                    Some(latency) => {
+                        latencies.push(latency);
+                        format!(
+                            "{}, {}, {:.3}ms",
+                            rcode,
+                            answers,
+                            (latency.as_micros() as f64) / 1000.0
+                        )
+                    }
+                    None => {
+                        unsolicited += 1;
+                        format!("{}, {}, {}", rcode, answers, "no query seen".yellow())
+                    }
+                }
+            }
+            (None, _) => {
+                unanswered += 1;
+                format!("{}", "NO RESPONSE".red())
+            }
+        };
+        println!(
+            "{}  {} -> {} {}  ID {:<5}  {}  {}",
+            format_capture_time(first.timestamp),
+            client,
+            server,
+            first.transport,
+            first_packet.header.request_id,
+            describe_capture_question(first_packet),
+            outcome
         );
 
-        match authority.data_type {
-            QueryType::SOA => {
-                const MIN_LEN: usize = 3;
-                if authority.data.len() < MIN_LEN {
-                    eprintln!("Response for data type SOA is expected to contain at least {} bytes and instead contained {} bytes. Ignoring...", MIN_LEN, authority.data.len());
-                } else {
-                    let mut soa_cursor = io::Cursor::new(&authority.data[..]);
-                    let mname_bytes = match deserialize_name(&mut soa_cursor, resp_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!(
-                                "ERROR: Failed to parse origin name data from SOA record: {}",
-                                e
-                            );
-                            continue;
+        if verbose {
+            if let Some((response, packet)) = &transaction.response {
+                for answer in &packet.answers {
+                    match Record::deserialize_from(answer, &response.data) {
+                        Ok(record) => {
+                            println!("    {}", format_record(&record, " ", ttl_format))
                         }
-                    };
-                    let rname_bytes = match deserialize_name(&mut soa_cursor, resp_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!(
-                                "ERROR: Failed to parse responsible mailbox from SOA record: {}",
-                                e
-                            );
-                            continue;
-                        }
-                    };
-                    let serial = soa_cursor.read_u32::<BigEndian>().unwrap();
-                    let refresh = soa_cursor.read_u32::<BigEndian>().unwrap();
-                    let retry = soa_cursor.read_u32::<BigEndian>().unwrap();
-                    let expire = soa_cursor.read_u32::<BigEndian>().unwrap();
-                    let minimum = soa_cursor.read_u32::<BigEndian>().unwrap();
-
-                    let mname = match String::from_utf8(mname_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!("ERROR: Failed to parse origin name as UTF8: {}", e);
-                            continue;
-                        }
-                    };
-                    let rname = match String::from_utf8(rname_bytes) {
-                        Ok(name) => name,
-                        Err(e) => {
-                            eprintln!("ERROR: Failed to parse responsible mailbox as UTF8: {}", e);
-                            continue;
-                        }
-                    };
-                    println!("  {} - {} (Serial: {}, Refresh: {}, Retry: {}, Expire: {}, Minimum: {}, TTL: {})",
-                        mname, rname, serial,
-                        format_time(refresh), format_time(retry), format_time(expire), format_time(minimum),
-                        format_time(authority.ttl));
+                        Err(e) => println!("    ERROR: {}", e),
+                    }
                 }
             }
-            _ => {
-                eprintln!(
-                    " Unsupported authority data type: {:?}\n",
-                    authority.data_type
-                );
-            }
         }
     }
 
-    if verbose || (response.additionals.len() != 0) {
-        // TODO: Name server RRs
-        println!("Received {} additional records", response.additionals.len());
+    for (message, e) in &analysis.malformed {
+        eprintln!(
+            "{}  {} -> {} {}  Malformed DNS message ({} bytes): {}",
+            format_capture_time(message.timestamp),
+            message.source,
+            message.destination,
+            message.transport,
+            message.data.len(),
+            e
+        );
     }
 
-    if verbose {
-        println!("\n Response packet debug: {:?}", response);
+    println!();
+    println!(
+        "{} transactions, {} without a response, {} responses without a query, {} malformed messages",
+        analysis.transactions.len() - unsolicited,
+        unanswered,
+        unsolicited,
+        analysis.malformed.len()
+    );
+    if !rcodes.is_empty() {
+        let counts: Vec<String> = rcodes
+            .iter()
+            .map(|(rcode, count)| format!("{}: {}", rcode, count))
+            .collect();
+        println!("Response codes: {}", counts.join(", "));
+    }
+    if !latencies.is_empty() {
+        let to_ms = |latency: Duration| (latency.as_micros() as f64) / 1000.0;
+        let total: Duration = latencies.iter().sum();
+        println!(
+            "Latency: min {:.3}ms, average {:.3}ms, max {:.3}ms",
+            to_ms(*latencies.iter().min().unwrap()),
+            to_ms(total / latencies.len() as u32),
+            to_ms(*latencies.iter().max().unwrap())
+        );
     }
 }
@@ -1,7 +1,6 @@
-use atty::Stream;
+use alltools::cli::{self, InputOptions};
 use getopts::Options;
 use std::env;
-use std::io;
 use std::process;
 use std::str;
 
@@ -10,19 +9,18 @@ fn print_usage(program: &str, opts: Options) {
     print!("{}", opts.usage(&brief));
 
     println!();
-    println!("If stdin has been redirected then each line of stdin will be separately decoded and printed");
+    cli::print_input_help();
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
-    opts_spec.optflag(
-        "i",
-        "interactive",
-        "interactively read from standard input, converting each line",
-    );
+    cli::add_input_options(&mut opts_spec, "i", "f");
     let opts = match opts_spec.parse(&args[1..]) {
         Ok(o) => o,
         Err(e) => {
@@ -40,53 +38,34 @@ fn main() {
         return;
     }
 
-    let is_interactive = opts.opt_present("i");
-    let is_stdin_tty = atty::is(Stream::Stdin);
-    if is_interactive && !is_stdin_tty {
-        println!("Bad argument: Interactive mode is not available with a non-interactive standard input stream");
-        process::exit(1);
-    }
-
-    if is_stdin_tty && !is_interactive && opts.free.is_empty() {
+    let inputs = match InputOptions::from_matches(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("Bad argument: {}", e);
+            process::exit(1);
+        }
+    };
+    if inputs.is_empty() {
         eprintln!("No input values provided");
         eprintln!();
         print_usage(program, opts_spec);
         process::exit(1);
     }
 
-    for arg in opts.free {
-        process_input(&arg);
-    }
-
-    if !is_interactive && is_stdin_tty {
-        return;
-    }
-
-    let mut input = String::new();
-    loop {
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            eprintln!("Error: {}", e);
-            break;
-        }
-
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            break;
-        }
-        process_input(trimmed);
-        input.clear();
+    if !inputs.process(process_input) {
+        process::exit(1);
     }
 }
 
-fn process_input(input: &str) {
+fn process_input(input: &str) -> Result<(), String> {
     match alltools::hex::to_bytes(input) {
-        Some(input_bytes) => match str::from_utf8(&input_bytes) {
-            Ok(utf8) => println!("{}", utf8),
-            Err(e) => eprintln!(
-                "Bad input: {} does not decode to valid UTF-8 ({})",
-                input, e
-            ),
+        Ok(input_bytes) => match str::from_utf8(&input_bytes) {
+            Ok(utf8) => {
+                println!("{}", utf8);
+                Ok(())
+            }
+            Err(e) => Err(format!("{} does not decode to valid UTF-8 ({})", input, e)),
         },
-        None => eprintln!("Bad input: {} is not valid hex", input),
+        Err(e) => Err(format!("{} is not valid hex: {}", input, e)),
     }
 }
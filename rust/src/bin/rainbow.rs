@@ -37,7 +37,10 @@ fn hsv_to_rgb(hsv: Vec3) -> Vec3 {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
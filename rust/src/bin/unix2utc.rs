@@ -1,15 +1,16 @@
-use atty::Stream;
+use alltools::cli::{self, InputOptions};
+use alltools::time;
 use chrono::offset::TimeZone;
 use chrono::{Datelike, Utc};
 use getopts::Options;
-use std::{env, io, process};
+use std::{env, process};
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [OPTIONS] [TIMESTAMP]...", program);
     print!("{}", opts.usage(&brief));
 
     println!();
-    println!("If stdin has been redirected then each line of stdin will be separately decoded and printed");
+    cli::print_input_help();
     println!();
     println!(
         "TIMESTAMP should be an integer representing seconds or milliseconds since the Unix epoch."
@@ -22,10 +23,15 @@ fn print_usage(program: &str, opts: Options) {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
+    // -i is already used for --iso
+    cli::add_input_options(&mut opts_spec, "", "f");
     opts_spec.optflag(
         "m",
         "millis",
@@ -37,6 +43,12 @@ fn main() {
         "interpret input as a number of seconds since the unix epoch",
     );
     opts_spec.optflag("i", "iso", "output in standard ISO-8601/RFC-3339 format");
+    opts_spec.optflag("l", "local", "output in the local time zone instead of UTC");
+    opts_spec.optflag(
+        "r",
+        "relative",
+        "also output how long ago (or how far in the future) the timestamp is",
+    );
     let opts = match opts_spec.parse(&args[1..]) {
         Ok(o) => o,
         Err(e) => {
@@ -55,45 +67,37 @@ fn main() {
     }
 
     let use_millis = opts.opt_present("m");
-    let use_iso_format = opts.opt_present("s");
-    let is_stdin_tty = atty::is(Stream::Stdin);
-
-    if is_stdin_tty && opts.free.is_empty() {
+    let format = OutputFormat {
+        iso: opts.opt_present("i"),
+        local: opts.opt_present("l"),
+        relative: opts.opt_present("r"),
+    };
+    let inputs = match InputOptions::from_matches(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("Bad argument: {}", e);
+            process::exit(1);
+        }
+    };
+    if inputs.is_empty() {
         eprintln!("No input values provided");
         eprintln!();
         print_usage(program, opts_spec);
         process::exit(1);
     }
 
-    for arg in opts.free {
-        process_input(&arg, use_millis, use_iso_format);
-    }
-
-    if is_stdin_tty {
-        // NOTE: We only read from stdin (by default, we could conceivably add a flag for it)
-        //       so that the user doesn't run the program and then sit confused as to whether the
-        //       program is stuck or just waiting for input.
-        return;
+    if !inputs.process(|input| process_input(input, use_millis, &format)) {
+        process::exit(1);
     }
+}
 
-    let mut input = String::new();
-    loop {
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            eprintln!("Error: {}", e);
-            break;
-        }
-
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            break;
-        }
-
-        process_input(trimmed, use_millis, use_iso_format);
-        input.clear();
-    }
+struct OutputFormat {
+    iso: bool,
+    local: bool,
+    relative: bool,
 }
 
-fn process_input(input: &str, use_millis: bool, use_iso_format: bool) {
+fn process_input(input: &str, use_millis: bool, format: &OutputFormat) -> Result<(), String> {
     let input_timestamp = match input.parse::<i64>() {
         Ok(n) => n,
         Err(e) => {
@@ -106,11 +110,10 @@ fn process_input(input: &str, use_millis: bool, use_iso_format: bool) {
                     false => Utc::now().timestamp(),
                 }
             } else {
-                eprintln!(
-                    "Bad input: \"{}\" is not a valid unix timestamp. Error: '{}'",
+                return Err(format!(
+                    "\"{}\" is not a valid unix timestamp. Error: '{}'",
                     input, e
-                );
-                return;
+                ));
             }
         }
     };
@@ -124,15 +127,26 @@ fn process_input(input: &str, use_millis: bool, use_iso_format: bool) {
         false => {
             let candidate = Utc.timestamp(input_timestamp, 0);
             if candidate.year() > 10000 {
-                return process_input(input, true, use_iso_format);
+                return process_input(input, true, format);
             }
             candidate
         }
     };
 
-    if use_iso_format {
-        println!("{}", output.to_rfc3339());
-    } else {
-        println!("{}", output);
+    let mut text = match (format.iso, format.local) {
+        (true, true) => output.with_timezone(&chrono::Local).to_rfc3339(),
+        (true, false) => output.to_rfc3339(),
+        (false, local) => time::format_timestamp(output, local),
+    };
+    if format.relative {
+        let offset = output.timestamp() - Utc::now().timestamp();
+        let distance = time::humanize_duration(offset.unsigned_abs());
+        if offset < 0 {
+            text.push_str(&format!(" ({} ago)", distance));
+        } else {
+            text.push_str(&format!(" (in {})", distance));
+        }
     }
+    println!("{}", text);
+    Ok(())
 }
@@ -1,15 +1,15 @@
-use atty::Stream;
+use alltools::cli::{self, InputOptions};
 use chrono::offset::TimeZone;
 use chrono::{DateTime, Utc};
 use getopts::Options;
-use std::{env, io, process};
+use std::{env, process};
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [OPTIONS] [DATETIME]...", program);
     print!("{}", opts.usage(&brief));
 
     println!("");
-    println!("If stdin has been redirected then each line of stdin will be separately decoded and printed");
+    cli::print_input_help();
     println!("");
     println!("DATETIME should be a string representing the current UTC time");
     println!(
@@ -18,10 +18,14 @@ fn print_usage(program: &str, opts: Options) {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
+    cli::add_input_options(&mut opts_spec, "i", "f");
     opts_spec.optflag("m", "millis", "use the input as the number of milliseconds since the unix epoch, rather than the number of seconds");
     opts_spec.optflag(
         "s",
@@ -46,53 +50,35 @@ fn main() {
     }
 
     let use_millis = opts.opt_present("m");
-    let is_stdin_tty = atty::is(Stream::Stdin);
-
-    if is_stdin_tty && opts.free.is_empty() {
+    let inputs = match InputOptions::from_matches(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("Bad argument: {}", e);
+            process::exit(1);
+        }
+    };
+    if inputs.is_empty() {
         eprintln!("No input values provided");
         eprintln!("");
         print_usage(program, opts_spec);
         process::exit(1);
     }
 
-    for arg in opts.free {
-        process_input(&arg, use_millis);
-    }
-
-    if is_stdin_tty {
-        // NOTE: We only read from stdin (by default, we could conceivably add a flag for it)
-        //       so that the user doesn't run the program and then sit confused as to whether the
-        //       program is stuck or just waiting for input.
-        return;
-    }
-
-    let mut input = String::new();
-    loop {
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            eprintln!("Error: {}", e.to_string());
-            break;
-        }
-
-        let trimmed = input.trim();
-        if trimmed.len() == 0 {
-            break;
-        }
-
-        process_input(trimmed, use_millis);
-        input.clear();
+    if !inputs.process(|input| process_input(input, use_millis)) {
+        process::exit(1);
     }
 }
 
-fn process_input(input: &str, use_millis: bool) {
+fn process_input(input: &str, use_millis: bool) -> Result<(), String> {
     if input.eq_ignore_ascii_case("now") {
         output_timestamp(Utc::now(), use_millis);
-        return;
+        return Ok(());
     }
 
     match DateTime::parse_from_rfc3339(input) {
         Ok(dt) => {
             output_timestamp(dt, use_millis);
-            return;
+            return Ok(());
         }
         Err(_e) => {}
     };
@@ -100,7 +86,7 @@ fn process_input(input: &str, use_millis: bool) {
     match DateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S %z") {
         Ok(dt) => {
             output_timestamp(dt, use_millis);
-            return;
+            return Ok(());
         }
         Err(_e) => {}
     };
@@ -108,15 +94,15 @@ fn process_input(input: &str, use_millis: bool) {
     match Utc.datetime_from_str(input, "%Y-%m-%d %H:%M:%S") {
         Ok(dt) => {
             output_timestamp(dt, use_millis);
-            return;
+            return Ok(());
         }
         Err(_e) => {}
     };
 
-    eprintln!(
-        "Bad input: {} is not a time string with a recognized format",
+    Err(format!(
+        "{} is not a time string with a recognized format",
         input
-    );
+    ))
 }
 
 fn output_timestamp(instant: DateTime<impl TimeZone>, use_millis: bool) {
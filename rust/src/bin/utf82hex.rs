@@ -1,8 +1,6 @@
 use std::env;
-use std::io;
 use std::process;
-use std::str;
-use atty::Stream;
+use alltools::cli::{self, InputOptions};
 use getopts::Options;
 
 fn print_usage(program: &str, opts: Options) {
@@ -11,11 +9,14 @@ fn print_usage(program: &str, opts: Options) {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    run(env::args().collect());
+}
+
+pub fn run(args: Vec<String>) {
     let program = &args[0];
     let mut opts_spec = Options::new();
     opts_spec.optflag("h", "help", "print this help menu");
-    opts_spec.optflag("i", "interactive", "interactively read from standard input, converting each line");
+    cli::add_input_options(&mut opts_spec, "i", "f");
     let opts = match opts_spec.parse(&args[1..]) {
         Ok(o) => o,
         Err(e) => {
@@ -32,50 +33,32 @@ fn main() {
         print_usage(program, opts_spec);
 
         println!("");
-        println!("If stdin has been redirected then each line of stdin will be separately decoded and printed");
+        cli::print_input_help();
         return;
     }
 
-    let is_interactive = opts.opt_present("i");
-    let is_stdin_tty = atty::is(Stream::Stdin);
-    if is_interactive && !is_stdin_tty {
-        println!("Bad argument: Interactive mode is not available with a non-interactive standard input stream");
-        process::exit(1);
-    }
-
-    if is_stdin_tty && !is_interactive && opts.free.is_empty() {
+    let inputs = match InputOptions::from_matches(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("Bad argument: {}", e);
+            process::exit(1);
+        }
+    };
+    if inputs.is_empty() {
         eprintln!("No input values provided");
         eprintln!("");
         print_usage(program, opts_spec);
         process::exit(1);
     }
 
-    for arg in opts.free {
-        process_input(&arg);
-    }
-
-    if !is_interactive && is_stdin_tty {
-        return
-    }
-
-    let mut input = String::new();
-    loop {
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            eprintln!("Error: {}", e.to_string());
-            break;
-        }
-
-        let trimmed = input.trim();
-        if trimmed.len() == 0 {
-            break;
-        }
-        process_input(trimmed);
-        input.clear();
+    if !inputs.process(process_input) {
+        process::exit(1);
     }
 }
 
-fn process_input(input: &str) {
+fn process_input(input: &str) -> Result<(), String> {
     let bytes = input.as_bytes();
     let hex = alltools::hex::from_bytes(bytes);
     println!("{}", hex);
+    Ok(())
 }
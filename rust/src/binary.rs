@@ -1,6 +1,8 @@
-pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
+use crate::codec::{BlockCodec, Codec, DecodeError, DecodeErrorKind};
+
+pub fn to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
     if input.len() == 0 {
-        return Some(vec![]);
+        return Ok(vec![]);
     }
 
     let bytes = input.as_bytes();
@@ -20,7 +22,7 @@ pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
         let bitval = match bytes[i] {
             b'0' => 0,
             b'1' => 1,
-            _ => return None
+            _ => return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i))
         };
 
         current_byte |= bitval << bit_count;
@@ -37,7 +39,7 @@ pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
     }
 
     result.reverse();
-    Some(result)
+    Ok(result)
 }
 
 pub fn from_bytes(input: &[u8], trim_leading_zeros: bool) -> String {
@@ -64,6 +66,85 @@ pub fn from_bytes(input: &[u8], trim_leading_zeros: bool) -> String {
     output
 }
 
+#[derive(Clone)]
+pub struct Binary {
+    pub trim_leading_zeros: bool,
+}
+
+impl Default for Binary {
+    fn default() -> Binary {
+        Binary { trim_leading_zeros: true }
+    }
+}
+
+impl Codec for Binary {
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["2", "bin"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("keep-zeros", "output every bit of each byte, including leading zeros")]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "keep-zeros" => self.trim_leading_zeros = false,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        from_bytes(input, self.trim_leading_zeros)
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        to_bytes(input)
+    }
+
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// Streams always include every bit, since leading zeros can only be trimmed from the whole value
+impl BlockCodec for Binary {
+    fn block_sizes(&self) -> (usize, usize) {
+        (1, 8)
+    }
+
+    fn prefix(&self) -> &'static [u8] {
+        b"0b"
+    }
+
+    fn is_ignorable(&self, c: u8) -> bool {
+        crate::is_char_ignorable(c) || c.is_ascii_whitespace()
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        output.extend_from_slice(&from_bytes(input, false).as_bytes()[2..]);
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        let mut value = 0;
+        for (i, c) in input.iter().enumerate() {
+            match c {
+                b'0' | b'1' => value = (value << 1) | (c - b'0'),
+                _ => return Err((i, DecodeErrorKind::InvalidDigit))
+            }
+        }
+        if input.len() != 8 {
+            return Err((input.len(), DecodeErrorKind::BadLength));
+        }
+        output.push(value);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod binary_tests {
     use super::*;
@@ -98,6 +179,16 @@ mod binary_tests {
         );
     }
 
+    #[test]
+    fn tobytes_invalid_digit() {
+        let input = "0b10 12";
+        let output = to_bytes(input).unwrap_err();
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 6, character: Some('2') },
+            output
+        );
+    }
+
     #[test]
     fn frombytes_leading_zeroes_included() {
         let input = vec![0x07, 0x01];
@@ -0,0 +1,271 @@
+use atty::Stream;
+use getopts::{Matches, Options};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+// The name used in error messages for inputs read from stdin, and the file name that means stdin
+const STDIN_NAME: &str = "standard input";
+const STDIN_PATH: &str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delimiter {
+    // Each line is a separate input, ignoring any surrounding whitespace
+    Line,
+    // Each NUL-terminated record is a separate input, taken exactly as-is
+    Nul,
+    // The whole stream is a single input (without any final line ending)
+    Whole,
+}
+
+// Adds the options understood by InputOptions::from_matches. Tools that already use one of the
+// usual short names (-i and -f) for something else can pass a different one, or "" for none.
+pub fn add_input_options(opts_spec: &mut Options, interactive_short: &str, file_short: &str) {
+    opts_spec.optflag(
+        interactive_short,
+        "interactive",
+        "interactively read from standard input, converting each line",
+    );
+    opts_spec.optmulti(
+        file_short,
+        "file",
+        "read inputs from FILE, one per line ('-' for standard input). Can be given more than once",
+        "FILE",
+    );
+    opts_spec.optflag(
+        "0",
+        "null",
+        "inputs read from files or standard input are separated by NUL characters rather than lines",
+    );
+    opts_spec.optflag(
+        "w",
+        "whole",
+        "treat the whole of standard input (or each file) as a single input",
+    );
+}
+
+pub fn print_input_help() {
+    println!("Each INPUT argument is converted separately, followed by each input from any --file.");
+    println!("If stdin has been redirected (or --interactive is passed) then each line of stdin is also converted.");
+    println!("Blank lines are skipped, and the exit status is non-zero if any input could not be converted.");
+}
+
+pub struct InputOptions {
+    pub args: Vec<String>,
+    pub files: Vec<String>,
+    pub delimiter: Delimiter,
+    pub interactive: bool,
+    read_stdin: bool,
+}
+
+impl InputOptions {
+    // Errors describe the bad combination of arguments, ready to be shown to the user
+    pub fn from_matches(opts: &Matches) -> Result<InputOptions, String> {
+        let interactive = opts.opt_present("interactive");
+        let is_stdin_tty = atty::is(Stream::Stdin);
+        if interactive && !is_stdin_tty {
+            return Err(String::from(
+                "Interactive mode is not available with a non-interactive standard input stream",
+            ));
+        }
+
+        let delimiter = match (opts.opt_present("null"), opts.opt_present("whole")) {
+            (true, true) => {
+                return Err(String::from("--null and --whole cannot be used together"));
+            }
+            (true, false) => Delimiter::Nul,
+            (false, true) => Delimiter::Whole,
+            (false, false) => Delimiter::Line,
+        };
+
+        let files = opts.opt_strs("file");
+        // NOTE: A terminal is only read from when asked so that the user doesn't run the program
+        //       and then sit confused as to whether it's stuck or just waiting for input.
+        let read_stdin = interactive || (!is_stdin_tty && files.is_empty());
+        Ok(InputOptions {
+            args: opts.free.clone(),
+            files,
+            delimiter,
+            interactive,
+            read_stdin,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty() && self.files.is_empty() && !self.read_stdin
+    }
+
+    // Passes every input to the given function in turn, reporting any that fail (along with where
+    // they came from) on stderr. Returns false if any input failed or couldn't be read, so that the
+    // caller can exit with a non-zero status once everything has been processed.
+    pub fn process<F>(&self, mut process: F) -> bool
+    where
+        F: FnMut(&str) -> Result<(), String>,
+    {
+        let mut success = true;
+        for (index, arg) in self.args.iter().enumerate() {
+            let location = format!("argument {}", index + 1);
+            success &= report(&location, process(arg));
+        }
+
+        for path in &self.files {
+            let result = if path == STDIN_PATH {
+                let stdin = io::stdin();
+                let reader = stdin.lock();
+                process_records(reader, STDIN_NAME, self.delimiter, &mut process)
+            } else {
+                File::open(path).and_then(|file| {
+                    process_records(BufReader::new(file), path, self.delimiter, &mut process)
+                })
+            };
+            success &= report_read(path, result);
+        }
+
+        if self.read_stdin {
+            let stdin = io::stdin();
+            let reader = stdin.lock();
+            let result = process_records(reader, STDIN_NAME, self.delimiter, &mut process);
+            success &= report_read(STDIN_NAME, result);
+        }
+        success
+    }
+}
+
+fn report(location: &str, result: Result<(), String>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(message) => {
+            eprintln!("Bad input ({}): {}", location, message);
+            false
+        }
+    }
+}
+
+fn report_read(name: &str, result: io::Result<bool>) -> bool {
+    match result {
+        Ok(success) => success,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", name, e);
+            false
+        }
+    }
+}
+
+// Reads each input from the reader and passes it to the given function, returning whether they
+// were all successful. Each input is handled as soon as it's read so that interactive use works.
+fn process_records<R, F>(
+    mut reader: R,
+    name: &str,
+    delimiter: Delimiter,
+    process: &mut F,
+) -> io::Result<bool>
+where
+    R: BufRead,
+    F: FnMut(&str) -> Result<(), String>,
+{
+    if delimiter == Delimiter::Whole {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        if buffer.ends_with(b"\n") {
+            buffer.pop();
+            if buffer.ends_with(b"\r") {
+                buffer.pop();
+            }
+        }
+        if buffer.is_empty() {
+            return Ok(true);
+        }
+        return Ok(report(name, decode_record(&buffer).and_then(process)));
+    }
+
+    let (terminator, kind) = match delimiter {
+        Delimiter::Nul => (0, "record"),
+        _ => (b'\n', "line"),
+    };
+    let mut success = true;
+    let mut buffer = Vec::new();
+    let mut number = 0;
+    loop {
+        buffer.clear();
+        if reader.read_until(terminator, &mut buffer)? == 0 {
+            break;
+        }
+        number += 1;
+        if buffer.last() == Some(&terminator) {
+            buffer.pop();
+        }
+
+        let result = decode_record(&buffer).map(|text| match delimiter {
+            Delimiter::Line => text.trim(),
+            _ => text,
+        });
+        let result = match result {
+            Ok("") => continue,
+            Ok(text) => process(text),
+            Err(e) => Err(e),
+        };
+        success &= report(&format!("{} {} of {}", kind, number, name), result);
+    }
+    Ok(success)
+}
+
+fn decode_record(bytes: &[u8]) -> Result<&str, String> {
+    std::str::from_utf8(bytes).map_err(|e| format!("not valid UTF-8 ({})", e))
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn collect(input: &[u8], delimiter: Delimiter) -> (Vec<String>, bool) {
+        let mut output = Vec::new();
+        let success = process_records(Cursor::new(input), "test", delimiter, &mut |text: &str| {
+            if text == "bad" {
+                return Err(String::from("bad"));
+            }
+            output.push(String::from(text));
+            Ok(())
+        }).unwrap();
+        (output, success)
+    }
+
+    #[test]
+    fn lines_skip_blanks_without_stopping() {
+        let (output, success) = collect(b" one \n\n\r\ntwo\r\nthree", Delimiter::Line);
+        assert_eq!(
+            vec!["one", "two", "three"],
+            output
+        );
+        assert!(success);
+    }
+
+    #[test]
+    fn failures_do_not_stop_processing() {
+        let (output, success) = collect(b"one\nbad\n\xff\ntwo\n", Delimiter::Line);
+        assert_eq!(
+            vec!["one", "two"],
+            output
+        );
+        assert!(!success);
+    }
+
+    #[test]
+    fn nul_records_are_kept_exactly() {
+        let (output, success) = collect(b"one two\n\0\0 three\0", Delimiter::Nul);
+        assert_eq!(
+            vec!["one two\n", " three"],
+            output
+        );
+        assert!(success);
+    }
+
+    #[test]
+    fn whole_input_drops_final_line_ending() {
+        let (output, success) = collect(b"one\ntwo\r\n", Delimiter::Whole);
+        assert_eq!(
+            vec!["one\ntwo"],
+            output
+        );
+        assert!(success);
+    }
+}
@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    // The input can't be the encoding of a whole number of bytes
+    BadLength,
+    // Padding characters in the wrong place, or the wrong number of them
+    BadPadding,
+    // A character which isn't part of the encoding's alphabet
+    InvalidDigit,
+    // The value is too large to be represented
+    Overflow,
+    // A check symbol or checksum that doesn't match the rest of the input
+    BadChecksum,
+}
+
+impl DecodeErrorKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            DecodeErrorKind::BadLength => "bad length",
+            DecodeErrorKind::BadPadding => "bad padding",
+            DecodeErrorKind::InvalidDigit => "invalid digit",
+            DecodeErrorKind::Overflow => "overflow",
+            DecodeErrorKind::BadChecksum => "bad checksum",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    // Byte offset into the input, which is the length of the input if the problem is that it ended
+    // too soon (in which case there's no character either)
+    pub offset: usize,
+    pub character: Option<char>,
+}
+
+impl DecodeError {
+    // The offset must be on a character boundary
+    pub fn at(kind: DecodeErrorKind, input: &str, offset: usize) -> DecodeError {
+        DecodeError {
+            kind,
+            offset,
+            character: input[offset..].chars().next(),
+        }
+    }
+
+    pub fn end(kind: DecodeErrorKind, input: &str) -> DecodeError {
+        DecodeError::at(kind, input, input.len())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.character {
+            Some(c) => write!(f, "{} {:?} at offset {}", self.kind.description(), c, self.offset),
+            None => write!(f, "{} at end of input (offset {})", self.kind.description(), self.offset),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+pub trait Codec {
+    fn name(&self) -> &str;
+
+    // Other names that the codec can be selected by, such as the numeric base
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // The (name, description) of each option that can be passed to set_option. Options that take
+    // a value are given as "name=value".
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    // Returns Ok(false) if the codec doesn't have the option, or an error if its value isn't valid
+    fn set_option(&mut self, _option: &str) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    fn encode(&self, input: &[u8]) -> String;
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError>;
+
+    // Codecs that can be converted a piece at a time return themselves as a BlockCodec, so that
+    // they can be used with stream::Encoder and stream::Decoder
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        None
+    }
+}
+
+// An encoding in which each block of bytes maps to a fixed-size block of characters (except that
+// the final block may be shorter), so that a stream can be converted without holding all of it.
+pub trait BlockCodec {
+    // The number of bytes in each block and the number of characters that they're encoded as
+    fn block_sizes(&self) -> (usize, usize);
+
+    // Written before the encoded data, and skipped if present at the start of encoded input
+    fn prefix(&self) -> &'static [u8] {
+        b""
+    }
+
+    // Encoded output is split into lines of this many characters, if set, separated by CRLF as in
+    // MIME (RFC 2045 section 2.1)
+    fn line_length(&self) -> Option<usize> {
+        None
+    }
+
+    // Characters that are skipped when decoding. Encoded streams are nearly always split into
+    // lines, so whitespace is always ignored.
+    fn is_ignorable(&self, c: u8) -> bool {
+        c.is_ascii_whitespace()
+    }
+
+    // Whether every character of the encoding is ASCII, so that anything else can be rejected
+    // straight away without waiting for the rest of the block
+    fn is_ascii_only(&self) -> bool {
+        true
+    }
+
+    // The input is a whole block, except for the final one which may be shorter (but not empty)
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>);
+
+    // As with encode_block, only the final block can be short. Errors give the index into the
+    // block of the character at fault (which is the block length if there were too few).
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)>;
+
+    // Whether the block was padded, so must have been the last one
+    fn is_padded(&self, _block: &[u8]) -> bool {
+        false
+    }
+}
+
+// The bytes themselves, which when shown as text are assumed to be UTF-8
+#[derive(Clone, Default)]
+pub struct Raw {}
+
+impl Codec for Raw {
+    fn name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["text"]
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        String::from_utf8_lossy(input).into_owned()
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        Ok(input.as_bytes().to_vec())
+    }
+
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl BlockCodec for Raw {
+    fn block_sizes(&self) -> (usize, usize) {
+        (1, 1)
+    }
+
+    fn is_ignorable(&self, _c: u8) -> bool {
+        false
+    }
+
+    fn is_ascii_only(&self) -> bool {
+        false
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        output.extend_from_slice(input);
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        output.extend_from_slice(input);
+        Ok(())
+    }
+}
+
+pub fn all_codecs() -> Vec<Box<dyn Codec>> {
+    vec![
+        Box::new(Raw::default()),
+        Box::new(crate::binary::Binary::default()),
+        Box::new(crate::radix::Radix::octal()),
+        Box::new(crate::decimal::Decimal::default()),
+        Box::new(crate::hex::Hex::default()),
+        Box::new(crate::base32::Base32::default()),
+        Box::new(crate::base32::Base32::hex()),
+        Box::new(crate::base32::Base32::z_base_32()),
+        Box::new(crate::base32::Crockford::default()),
+        Box::new(crate::base58::Base58::default()),
+        Box::new(crate::base58::Base58::flickr()),
+        Box::new(crate::base58::Base58::check()),
+        Box::new(crate::radix::Radix::base62()),
+        Box::new(crate::base64::Base64::default()),
+        Box::new(crate::base64::Base64::url()),
+        Box::new(crate::base85::Base85::default()),
+        Box::new(crate::base85::Base85::z85()),
+        Box::new(crate::base85::Base85::rfc1924()),
+    ]
+}
+
+// Finds a codec by its name or one of its aliases, ignoring case. Any other radix can be given
+// as a number (e.g "36") or with "base" in front of it (e.g "base36"), except where that's the name
+// of another codec: "32" and "base32" are RFC 4648 base32 rather than the number in base 32.
+pub fn find_codec(name: &str) -> Option<Box<dyn Codec>> {
+    let codec = all_codecs().into_iter().find(|codec| {
+        codec.name().eq_ignore_ascii_case(name)
+            || codec.aliases().iter().any(|alias| alias.eq_ignore_ascii_case(name))
+    });
+    if codec.is_some() {
+        return codec;
+    }
+
+    let lowercase = name.to_ascii_lowercase();
+    let number = lowercase.strip_prefix("base").unwrap_or(&lowercase);
+    match number.parse::<usize>().ok().and_then(crate::radix::Radix::new) {
+        Some(radix) => Some(Box::new(radix)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod codec_tests {
+    use super::*;
+
+    #[test]
+    fn find_codecs_by_name_or_alias() {
+        assert_eq!("hex", find_codec("16").unwrap().name());
+        assert_eq!("base64", find_codec("Base64").unwrap().name());
+        assert_eq!("base32", find_codec("32").unwrap().name());
+        assert_eq!("octal", find_codec("oct").unwrap().name());
+        assert_eq!("base7", find_codec("7").unwrap().name());
+        assert_eq!("base36", find_codec("BASE36").unwrap().name());
+        assert!(find_codec("37").is_none());
+    }
+
+    #[test]
+    fn decode_error_messages() {
+        let error = DecodeError::at(DecodeErrorKind::InvalidDigit, "0xfz", 3);
+        assert_eq!(
+            "invalid digit 'z' at offset 3",
+            error.to_string()
+        );
+        let error = DecodeError::end(DecodeErrorKind::BadLength, "abc");
+        assert_eq!(
+            "bad length at end of input (offset 3)",
+            error.to_string()
+        );
+    }
+}
@@ -1,37 +1,297 @@
-use std::mem::size_of;
+use crate::codec::{Codec, DecodeError, DecodeErrorKind};
 
-pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
-    if input.len() == 0 {
-        return Some(vec![]);
+// Numbers of any size are held as base 2^32 limbs, least significant first, and converted nine
+// decimal digits at a time since 10^9 is the largest power of ten that fits in a limb
+const DIGITS_PER_LIMB: usize = 9;
+const LIMB_DECIMAL_BASE: u32 = 1_000_000_000;
+
+// value = (value * multiplier) + addend
+pub(crate) fn multiply_add(value: &mut Vec<u32>, multiplier: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for limb in value.iter_mut() {
+        let product = (*limb as u64) * (multiplier as u64) + carry;
+        *limb = product as u32;
+        carry = product >> 32;
+    }
+    if carry != 0 {
+        value.push(carry as u32);
     }
+}
 
-    let input = match input.parse::<u64>() {
-        Ok(i) => i,
-        Err(_) => {
-            return None;
-        }
-    };
+// value = value / divisor, returning the remainder
+pub(crate) fn divide(value: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder: u64 = 0;
+    for limb in value.iter_mut().rev() {
+        let current = (remainder << 32) | (*limb as u64);
+        *limb = (current / (divisor as u64)) as u32;
+        remainder = current % (divisor as u64);
+    }
+    while value.last() == Some(&0) {
+        value.pop();
+    }
+    remainder as u32
+}
 
-    let mut result = Vec::with_capacity(size_of::<u64>());
-    let mut current = input;
-    while current != 0 {
-        let temp = (current & 0xFF) as u8;
-        current >>= 8;
-        result.push(temp);
+// Big-endian bytes, without any leading zeros
+pub(crate) fn limbs_to_bytes(value: &[u32]) -> Vec<u8> {
+    let mut result: Vec<u8> = value.iter().rev().flat_map(|limb| limb.to_be_bytes()).collect();
+    let leading_zeros = result.iter().take_while(|&&b| b == 0).count();
+    result.drain(..leading_zeros);
+    result
+}
+
+pub(crate) fn bytes_to_limbs(input: &[u8]) -> Vec<u32> {
+    let mut value: Vec<u32> = input.rchunks(4).map(|chunk| {
+        chunk.iter().fold(0, |limb, &b| (limb << 8) | (b as u32))
+    }).collect();
+    while value.last() == Some(&0) {
+        value.pop();
     }
+    value
+}
 
-    result.reverse();
-    Some(result)
+pub fn to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let bytes = input.as_bytes();
+    let mut value = Vec::with_capacity(bytes.len() / DIGITS_PER_LIMB + 1);
+    for (chunk_index, chunk) in bytes.chunks(DIGITS_PER_LIMB).enumerate() {
+        let mut chunk_value = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            match c {
+                b'0'..=b'9' => chunk_value = (chunk_value * 10) + (c - b'0') as u32,
+                _ => {
+                    let offset = (chunk_index * DIGITS_PER_LIMB) + i;
+                    return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, offset));
+                }
+            }
+        }
+        multiply_add(&mut value, 10u32.pow(chunk.len() as u32), chunk_value);
+    }
+
+    Ok(limbs_to_bytes(&value))
 }
 
 pub fn from_bytes(input: &[u8]) -> String {
-    let mut value: u64 = 0;
+    let mut value = bytes_to_limbs(input);
+    let mut chunks = Vec::new();
+    while !value.is_empty() {
+        chunks.push(divide(&mut value, LIMB_DECIMAL_BASE));
+    }
+
+    // Every chunk but the most significant is padded to the full nine digits
+    let mut output = chunks.pop().unwrap_or(0).to_string();
+    for chunk in chunks.iter().rev() {
+        output.push_str(&format!("{:09}", chunk));
+    }
+    output
+}
+
+// How a value with its top bit set is read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Representation {
+    Unsigned,
+    TwosComplement,
+    // The top bit is the sign and the rest is the magnitude
+    SignMagnitude,
+    // Negative values have every bit of the magnitude inverted
+    OnesComplement,
+}
+
+// Invert every bit and add one, which negates a two's complement value
+fn negate(bytes: &mut [u8]) {
+    let mut carry = true;
+    for b in bytes.iter_mut().rev() {
+        let (value, overflow) = (!*b).overflowing_add(carry as u8);
+        *b = value;
+        carry = overflow;
+    }
+}
+
+fn invert(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        *b = !*b;
+    }
+}
+
+fn is_top_bit_set(bytes: &[u8]) -> bool {
+    bytes.first().is_some_and(|b| b & 0x80 != 0)
+}
 
-    for (index, byte) in input.iter().rev().enumerate() {
-        value |= (*byte as u64) << (index*8);
+// The value as exactly `length` bytes in the given representation, or None if it's out of range.
+// The magnitude is as returned by to_bytes, so has no leading zeros.
+fn signed_to_bytes(negative: bool, magnitude: &[u8], length: usize, representation: Representation) -> Option<Vec<u8>> {
+    if magnitude.len() > length {
+        return None;
     }
+    let mut bytes = vec![0; length - magnitude.len()];
+    bytes.extend_from_slice(magnitude);
 
-    value.to_string()
+    match (negative, representation) {
+        (_, Representation::Unsigned) => Some(bytes),
+        (false, _) if is_top_bit_set(&bytes) => None,
+        (false, _) => Some(bytes),
+        (true, Representation::TwosComplement) => {
+            // -2^(8n-1) is the only negative value whose magnitude has the top bit set, and
+            // is its own negation
+            negate(&mut bytes);
+            match magnitude.is_empty() || is_top_bit_set(&bytes) {
+                true => Some(bytes),
+                false => None
+            }
+        },
+        // There's no room for the sign bit, or no byte to put it in
+        (true, _) if is_top_bit_set(&bytes) || bytes.is_empty() => None,
+        (true, Representation::SignMagnitude) => {
+            bytes[0] |= 0x80;
+            Some(bytes)
+        },
+        (true, Representation::OnesComplement) => {
+            invert(&mut bytes);
+            Some(bytes)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Decimal {
+    pub representation: Representation,
+    // The number of bits in each value, if fixed. The bytes are then split into values of this
+    // size, which are written separated by spaces. Otherwise all of the bytes are one value.
+    pub width: Option<usize>,
+    // Whether the least significant byte of each value comes first
+    pub little_endian: bool,
+}
+
+impl Default for Decimal {
+    fn default() -> Decimal {
+        Decimal { representation: Representation::Unsigned, width: None, little_endian: false }
+    }
+}
+
+impl Decimal {
+    pub fn signed(width: Option<usize>) -> Decimal {
+        Decimal { representation: Representation::TwosComplement, width, ..Decimal::default() }
+    }
+
+    // Puts the bytes of a value into big-endian order, or back again
+    fn reorder(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+        if self.little_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    fn value_to_string(&self, bytes: &[u8]) -> String {
+        let bytes = &self.reorder(bytes.to_vec())[..];
+        if (self.representation == Representation::Unsigned) || !is_top_bit_set(bytes) {
+            return from_bytes(bytes);
+        }
+        let mut magnitude = bytes.to_vec();
+        match self.representation {
+            Representation::TwosComplement => negate(&mut magnitude),
+            Representation::SignMagnitude => magnitude[0] &= 0x7F,
+            _ => invert(&mut magnitude),
+        }
+        format!("-{}", from_bytes(&magnitude))
+    }
+
+    // Parses the value at input[start..end]
+    fn value_to_bytes(&self, input: &str, start: usize, end: usize) -> Result<Vec<u8>, DecodeError> {
+        let text = &input[start..end];
+        let negative = (self.representation != Representation::Unsigned) && text.starts_with('-');
+        let digits_start = start + (negative as usize);
+        if negative && (digits_start == end) {
+            return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, start));
+        }
+        let magnitude = to_bytes(&input[digits_start..end])
+            .map_err(|e| DecodeError::at(e.kind, input, digits_start + e.offset))?;
+
+        let bytes = match self.width {
+            Some(width) => signed_to_bytes(negative, &magnitude, width / 8, self.representation),
+            // The fewest bytes that can hold the value
+            None if self.representation == Representation::Unsigned => Some(magnitude),
+            None => signed_to_bytes(negative, &magnitude, magnitude.len(), self.representation)
+                .or_else(|| signed_to_bytes(negative, &magnitude, magnitude.len() + 1, self.representation)),
+        };
+        match bytes {
+            Some(bytes) => Ok(self.reorder(bytes)),
+            None => Err(DecodeError::at(DecodeErrorKind::Overflow, input, start)),
+        }
+    }
+}
+
+impl Codec for Decimal {
+    fn name(&self) -> &'static str {
+        "decimal"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["10", "dec"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("signed", "read and write negative numbers in two's complement"),
+            ("sign-magnitude", "read and write negative numbers with a sign bit"),
+            ("ones-complement", "read and write negative numbers in ones' complement"),
+            ("width=BITS", "split the bytes into values of this many bits (e.g 8, 16, 32, 64 or 128), separated by spaces"),
+            ("little-endian", "read and write the bytes of each value least significant first"),
+        ]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "signed" => self.representation = Representation::TwosComplement,
+            "sign-magnitude" => self.representation = Representation::SignMagnitude,
+            "ones-complement" => self.representation = Representation::OnesComplement,
+            "little-endian" => self.little_endian = true,
+            _ => match option.strip_prefix("width=") {
+                Some(width) => match width.parse::<usize>() {
+                    Ok(bits) if (bits > 0) && (bits % 8 == 0) => self.width = Some(bits),
+                    _ => return Err(format!("The width must be a positive multiple of 8 bits, not {}", width)),
+                },
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let length = match self.width {
+            Some(width) => width / 8,
+            None => return self.value_to_string(input),
+        };
+
+        // Zero bytes are added to the most significant end to make up a whole number of values
+        let padding = vec![0; (length - (input.len() % length)) % length];
+        let mut padded = match self.little_endian {
+            true => [input, &padding].concat(),
+            false => [&padding, input].concat(),
+        };
+        if padded.is_empty() {
+            padded.resize(length, 0);
+        }
+        padded.chunks(length).map(|value| self.value_to_string(value)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        if self.width.is_none() {
+            return self.value_to_bytes(input, 0, input.len());
+        }
+
+        let mut result = Vec::new();
+        let mut start = None;
+        for (i, c) in input.char_indices().chain(std::iter::once((input.len(), ' '))) {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(i),
+                (Some(value_start), true) => {
+                    result.extend_from_slice(&self.value_to_bytes(input, value_start, i)?);
+                    start = None;
+                },
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
@@ -48,6 +308,29 @@ mod binary_tests {
         );
     }
 
+    #[test]
+    fn tobytes_invalid_digit() {
+        let input = "1234567890x";
+        let output = to_bytes(input).unwrap_err();
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 10, character: Some('x') },
+            output
+        );
+    }
+
+    #[test]
+    fn tobytes_larger_than_u64() {
+        let input = "340282366920938463463374607431768211457";
+        let output = to_bytes(input).unwrap();
+        let mut expected = vec![0x01];
+        expected.extend_from_slice(&[0x00; 15]);
+        expected.push(0x01);
+        assert_eq!(
+            expected,
+            output
+        );
+    }
+
     #[test]
     fn frombytes() {
         let input = vec![0x11, 0x06];
@@ -58,6 +341,75 @@ mod binary_tests {
         );
     }
 
+    #[test]
+    fn frombytes_larger_than_u64() {
+        let input = vec![0xFF; 16];
+        let output = from_bytes(&input);
+        assert_eq!(
+            "340282366920938463463374607431768211455",
+            output
+        );
+    }
+
+    #[test]
+    fn frombytes_zero() {
+        assert_eq!("0", from_bytes(&[]));
+        assert_eq!("0", from_bytes(&[0x00, 0x00]));
+        assert_eq!(Vec::<u8>::new(), to_bytes("000").unwrap());
+    }
+
+    #[test]
+    fn signed_fixed_width() {
+        let mut codec = Decimal::signed(Some(16));
+        assert_eq!("-1 -32768 32767 0", codec.encode(&[0xFF, 0xFF, 0x80, 0x00, 0x7F, 0xFF, 0x00, 0x00]));
+        assert_eq!(vec![0xFF, 0xFF, 0x80, 0x00, 0x00, 0x05], codec.decode(" -1  -32768\t5 ").unwrap());
+        assert_eq!("133", codec.encode(&[0x85]));
+        assert_eq!("-123", Decimal::signed(Some(8)).encode(&[0x85]));
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::Overflow, offset: 3, character: Some('3') },
+            codec.decode("-1 32768").unwrap_err()
+        );
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::Overflow, offset: 0, character: Some('-') },
+            codec.decode("-32769").unwrap_err()
+        );
+
+        codec.set_option("sign-magnitude").unwrap();
+        assert_eq!("-1 -0 32767", codec.encode(&[0x80, 0x01, 0x80, 0x00, 0x7F, 0xFF]));
+        assert_eq!(vec![0x80, 0x01], codec.decode("-1").unwrap());
+        codec.set_option("ones-complement").unwrap();
+        assert_eq!("-1 -0", codec.encode(&[0xFF, 0xFE, 0xFF, 0xFF]));
+        assert_eq!(vec![0xFF, 0xFE], codec.decode("-1").unwrap());
+        assert!(codec.set_option("width=12").is_err());
+    }
+
+    #[test]
+    fn little_endian() {
+        let mut codec = Decimal::default();
+        codec.set_option("little-endian").unwrap();
+        assert_eq!("1", codec.encode(&[0x01, 0x00]));
+        assert_eq!(vec![0x00, 0x01], codec.decode("256").unwrap());
+        codec.set_option("width=32").unwrap();
+        codec.set_option("signed").unwrap();
+        assert_eq!("-2 16909060 1", codec.encode(&[0xFE, 0xFF, 0xFF, 0xFF, 0x04, 0x03, 0x02, 0x01, 0x01]));
+        assert_eq!(vec![0xFE, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x00, 0x00], codec.decode("-2 1").unwrap());
+    }
+
+    #[test]
+    fn signed_arbitrary_width() {
+        let codec = Decimal::signed(None);
+        assert_eq!(vec![0xFF], codec.decode("-1").unwrap());
+        assert_eq!(vec![0x80], codec.decode("-128").unwrap());
+        assert_eq!(vec![0xFF, 0x7F], codec.decode("-129").unwrap());
+        assert_eq!(vec![0x00, 0x80], codec.decode("128").unwrap());
+        assert_eq!("-129", codec.encode(&[0xFF, 0x7F]));
+        assert_eq!("-340282366920938463463374607431768211456", codec.encode(&codec.decode("-340282366920938463463374607431768211456").unwrap()));
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 0, character: Some('-') },
+            Decimal::default().decode("-1").unwrap_err()
+        );
+    }
+
     #[test]
     fn frombytes_leading_zeroes_excluded() {
         let input = vec![0x00, 0x01, 0x06];
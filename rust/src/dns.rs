@@ -0,0 +1,153 @@
+use std::io;
+
+mod edns;
+mod mail;
+mod mdns;
+mod packet;
+mod pcap;
+mod record;
+mod resolver;
+mod system;
+mod tsig;
+mod update;
+
+pub use edns::*;
+pub use mail::*;
+pub use mdns::*;
+pub use packet::*;
+pub use pcap::*;
+pub use record::*;
+pub use resolver::*;
+pub use system::*;
+pub use tsig::*;
+pub use update::*;
+
+// RFC 1035 section 3.3: A <character-string> is a single length octet followed by that number of
+// characters, so no single string can be longer than 255 bytes.
+pub const MAX_CHARACTER_STRING_LENGTH: usize = 255;
+
+pub fn parse_character_strings(data: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut result = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let len = data[index] as usize;
+        let start = index + 1;
+        let end = start + len;
+        if end > data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Character-string at offset {} claims to be {} bytes long but only {} bytes remain", index, len, data.len() - start),
+            ));
+        }
+        result.push(data[start..end].to_vec());
+        index = end;
+    }
+    Ok(result)
+}
+
+pub fn build_character_strings(text: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(text.len() + (text.len() / MAX_CHARACTER_STRING_LENGTH) + 1);
+    if text.is_empty() {
+        result.push(0);
+        return result;
+    }
+
+    for chunk in text.chunks(MAX_CHARACTER_STRING_LENGTH) {
+        result.push(chunk.len() as u8);
+        result.extend_from_slice(chunk);
+    }
+    result
+}
+
+// Produces the zone-file presentation format of the given bytes (without surrounding quotes).
+// Printable text (including non-ASCII UTF-8) is kept as-is, quotes and backslashes are escaped with
+// a backslash and everything else is written as a backslash followed by three decimal digits.
+pub fn escape_character_string(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    let mut remaining = bytes;
+    while !remaining.is_empty() {
+        let (valid, invalid_len) = match std::str::from_utf8(remaining) {
+            Ok(valid) => (valid, 0),
+            Err(e) => {
+                let valid = std::str::from_utf8(&remaining[..e.valid_up_to()]).unwrap();
+                (valid, e.error_len().unwrap_or(remaining.len() - e.valid_up_to()))
+            }
+        };
+
+        for c in valid.chars() {
+            match c {
+                '"' | '\\' => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                c if c.is_control() => {
+                    let mut buffer = [0; 4];
+                    for b in c.encode_utf8(&mut buffer).bytes() {
+                        result.push_str(&format!("\\{:03}", b));
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+
+        let invalid_start = valid.len();
+        for b in &remaining[invalid_start..invalid_start + invalid_len] {
+            result.push_str(&format!("\\{:03}", b));
+        }
+        remaining = &remaining[invalid_start + invalid_len..];
+    }
+    result
+}
+
+#[cfg(test)]
+mod dns_tests {
+    use super::*;
+
+    #[test]
+    fn parse_multiple_character_strings() {
+        let input = b"\x05hello\x00\x05world";
+        let output = parse_character_strings(input).unwrap();
+        assert_eq!(
+            vec![b"hello".to_vec(), b"".to_vec(), b"world".to_vec()],
+            output
+        );
+    }
+
+    #[test]
+    fn parse_truncated_character_string() {
+        let input = b"\x05hello\x06world";
+        assert!(parse_character_strings(input).is_err());
+    }
+
+    #[test]
+    fn build_long_character_string() {
+        let input = vec![b'a'; 300];
+        let output = build_character_strings(&input);
+        assert_eq!(302, output.len());
+        assert_eq!(255, output[0]);
+        assert_eq!(45, output[256]);
+        assert_eq!(
+            vec![vec![b'a'; 255], vec![b'a'; 45]],
+            parse_character_strings(&output).unwrap()
+        );
+    }
+
+    #[test]
+    fn build_empty_character_string() {
+        let output = build_character_strings(b"");
+        assert_eq!(
+            vec![0],
+            output
+        );
+    }
+
+    #[test]
+    fn escape_non_printable_and_invalid_utf8() {
+        let input = b"caf\xc3\xa9 \"x\"\\ \x00\xff";
+        let output = escape_character_string(input);
+        assert_eq!(
+            "café \\\"x\\\"\\\\ \\000\\255",
+            output
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use super::packet::{DnsPacket, QueryType};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// RFC 6891 section 6.1.2 only says the payload size should be "large enough", but 1232 bytes
+// avoids fragmentation on virtually every path and is what the major resolvers agreed to use.
+pub const DEFAULT_EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+// RFC 8467 section 4.1 recommends padding queries to a multiple of 128 bytes
+pub const QUERY_PADDING_BLOCK_SIZE: usize = 128;
+
+const OPTION_NSID: u16 = 3;
+const OPTION_CLIENT_SUBNET: u16 = 8;
+const OPTION_COOKIE: u16 = 10;
+const OPTION_PADDING: u16 = 12;
+
+const CLIENT_COOKIE_LENGTH: usize = 8;
+const MIN_SERVER_COOKIE_LENGTH: usize = 8;
+const MAX_SERVER_COOKIE_LENGTH: usize = 32;
+
+// Extended response codes are 12 bits, with the top 8 bits carried in the OPT record
+pub fn extended_rcode_name(rcode: u16) -> Option<&'static str> {
+    match rcode {
+        16 => Some("BADVERS"),
+        23 => Some("BADCOOKIE"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EdnsOption {
+    // RFC 5001: Empty in a query, holds the server's identifier in a response
+    Nsid(Vec<u8>),
+    // RFC 7871: The scope prefix is always 0 in a query and says how widely the answer applies in a response
+    ClientSubnet {
+        address: IpAddr,
+        source_prefix: u8,
+        scope_prefix: u8,
+    },
+    // RFC 7873: The server cookie is empty until the server has given us one
+    Cookie {
+        client: [u8; CLIENT_COOKIE_LENGTH],
+        server: Vec<u8>,
+    },
+    // RFC 7830: The given number of zero bytes
+    Padding(usize),
+    Unknown {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+fn invalid_option(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl EdnsOption {
+    pub fn client_subnet(address: IpAddr, source_prefix: u8) -> io::Result<EdnsOption> {
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        if source_prefix > max_prefix {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Prefix length {} is too long for {}", source_prefix, address),
+            ));
+        }
+        Ok(EdnsOption::ClientSubnet {
+            address: mask_address(address, source_prefix),
+            source_prefix,
+            scope_prefix: 0,
+        })
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => OPTION_NSID,
+            EdnsOption::ClientSubnet { .. } => OPTION_CLIENT_SUBNET,
+            EdnsOption::Cookie { .. } => OPTION_COOKIE,
+            EdnsOption::Padding(_) => OPTION_PADDING,
+            EdnsOption::Unknown { code, .. } => *code,
+        }
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match self {
+            EdnsOption::Nsid(id) => id.clone(),
+            EdnsOption::ClientSubnet {
+                address,
+                source_prefix,
+                scope_prefix,
+            } => {
+                // Only as many address bytes as are needed to hold the prefix are sent
+                let (family, octets) = match address {
+                    IpAddr::V4(address) => (1, address.octets().to_vec()),
+                    IpAddr::V6(address) => (2, address.octets().to_vec()),
+                };
+                let address_len = (*source_prefix as usize).div_ceil(8);
+                let mut result = vec![0, family, *source_prefix, *scope_prefix];
+                result.extend_from_slice(&octets[..address_len]);
+                result
+            }
+            EdnsOption::Cookie { client, server } => {
+                let mut result = client.to_vec();
+                result.extend_from_slice(server);
+                result
+            }
+            EdnsOption::Padding(len) => vec![0; *len],
+            EdnsOption::Unknown { data, .. } => data.clone(),
+        }
+    }
+
+    fn parse(code: u16, data: &[u8]) -> io::Result<EdnsOption> {
+        match code {
+            OPTION_NSID => Ok(EdnsOption::Nsid(data.to_vec())),
+            OPTION_CLIENT_SUBNET => {
+                if data.len() < 4 {
+                    return Err(invalid_option("Client subnet option is too short"));
+                }
+                let family = u16::from_be_bytes([data[0], data[1]]);
+                let source_prefix = data[2];
+                let scope_prefix = data[3];
+                let address_bytes = &data[4..];
+                let address = match family {
+                    1 if address_bytes.len() <= 4 => {
+                        let mut octets = [0; 4];
+                        octets[..address_bytes.len()].copy_from_slice(address_bytes);
+                        IpAddr::V4(Ipv4Addr::from(octets))
+                    }
+                    2 if address_bytes.len() <= 16 => {
+                        let mut octets = [0; 16];
+                        octets[..address_bytes.len()].copy_from_slice(address_bytes);
+                        IpAddr::V6(Ipv6Addr::from(octets))
+                    }
+                    _ => return Err(invalid_option("Client subnet option has an invalid address")),
+                };
+                Ok(EdnsOption::ClientSubnet {
+                    address,
+                    source_prefix,
+                    scope_prefix,
+                })
+            }
+            OPTION_COOKIE => {
+                let server_len = data.len().wrapping_sub(CLIENT_COOKIE_LENGTH);
+                if (data.len() < CLIENT_COOKIE_LENGTH)
+                    || ((server_len != 0)
+                        && !(MIN_SERVER_COOKIE_LENGTH..=MAX_SERVER_COOKIE_LENGTH).contains(&server_len))
+                {
+                    return Err(invalid_option("Cookie option has an invalid length"));
+                }
+                Ok(EdnsOption::Cookie {
+                    client: data[..CLIENT_COOKIE_LENGTH].try_into().unwrap(),
+                    server: data[CLIENT_COOKIE_LENGTH..].to_vec(),
+                })
+            }
+            OPTION_PADDING => Ok(EdnsOption::Padding(data.len())),
+            _ => Ok(EdnsOption::Unknown {
+                code,
+                data: data.to_vec(),
+            }),
+        }
+    }
+}
+
+fn mask_address(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+        }
+    }
+}
+
+// The OPT pseudo-record (RFC 6891 section 6.1.2). It reuses the class field for the largest UDP
+// response we can accept and the TTL for the extended response code, version and flags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Edns {
+        Edns {
+            udp_payload_size: DEFAULT_EDNS_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl Edns {
+    // Combines the upper bits from the OPT record with the 4 bits from the message header
+    pub fn full_rcode(&self, header_rcode: u16) -> u16 {
+        ((self.extended_rcode as u16) << 4) | (header_rcode & 0x0F)
+    }
+
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        let mut data = Vec::new();
+        for option in &self.options {
+            let option_data = option.data();
+            data.write_u16::<BigEndian>(option.code())?;
+            data.write_u16::<BigEndian>(option_data.len() as u16)?;
+            data.extend_from_slice(&option_data);
+        }
+        let data_length: u16 = match data.len().try_into() {
+            Ok(len) => len,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "EDNS options are longer than 65535 bytes",
+                ))
+            }
+        };
+
+        buffer.push(0); // The owner name is always the root
+        buffer.write_u16::<BigEndian>(QueryType::OPT.to_int())?;
+        buffer.write_u16::<BigEndian>(self.udp_payload_size)?;
+        buffer.push(self.extended_rcode);
+        buffer.push(self.version);
+        buffer.write_u16::<BigEndian>(if self.dnssec_ok { 0x8000 } else { 0 })?;
+        buffer.write_u16::<BigEndian>(data_length)?;
+        buffer.extend_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn deserialize_from(cursor: &mut io::Cursor<&[u8]>) -> io::Result<Edns> {
+        if cursor.read_u8()? != 0 {
+            return Err(invalid_option("OPT record owner name must be the root"));
+        }
+        if cursor.read_u16::<BigEndian>()? != QueryType::OPT.to_int() {
+            return Err(invalid_option("Record is not an OPT record"));
+        }
+        let mut result = Edns {
+            udp_payload_size: cursor.read_u16::<BigEndian>()?,
+            extended_rcode: cursor.read_u8()?,
+            version: cursor.read_u8()?,
+            dnssec_ok: (cursor.read_u16::<BigEndian>()? & 0x8000) != 0,
+            options: Vec::new(),
+        };
+
+        let data_length = cursor.read_u16::<BigEndian>()? as usize;
+        let start = cursor.position() as usize;
+        let data = match cursor.get_ref().get(start..start + data_length) {
+            Some(data) => data,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "OPT record data is truncated",
+                ))
+            }
+        };
+        cursor.set_position((start + data_length) as u64);
+
+        let mut offset = 0;
+        while offset < data.len() {
+            if offset + 4 > data.len() {
+                return Err(invalid_option("EDNS option header is truncated"));
+            }
+            let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+            let option_data = match data.get(offset + 4..offset + 4 + len) {
+                Some(option_data) => option_data,
+                None => return Err(invalid_option("EDNS option data is truncated")),
+            };
+            result.options.push(EdnsOption::parse(code, option_data)?);
+            offset += 4 + len;
+        }
+        Ok(result)
+    }
+}
+
+// Replaces any existing padding option with one that makes the whole message a multiple of the
+// block size, so that the length of an encrypted query doesn't give away what was asked.
+pub fn pad_to_block_size(packet: &mut DnsPacket, block_size: usize) -> io::Result<()> {
+    let edns = match packet.edns.as_mut() {
+        Some(edns) => edns,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Padding can only be added to packets with an OPT record",
+            ))
+        }
+    };
+    edns.options.retain(|option| !matches!(option, EdnsOption::Padding(_)));
+    edns.options.push(EdnsOption::Padding(0));
+
+    let mut buffer = Vec::new();
+    packet.serialize(&mut buffer)?;
+    let padding = (block_size - (buffer.len() % block_size)) % block_size;
+    if let Some(edns) = packet.edns.as_mut() {
+        edns.options.pop();
+        edns.options.push(EdnsOption::Padding(padding));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod edns_tests {
+    use super::*;
+    use crate::dns::packet::{DnsHeader, DnsQuestion, DomainClass};
+
+    fn query_packet() -> DnsPacket {
+        let mut packet = DnsPacket::default();
+        packet.header.request_id = 0x1234;
+        packet.header.query_count = 1;
+        packet.questions.push(DnsQuestion {
+            domain_name: String::from("www.example.com"),
+            query_type: QueryType::A,
+            query_class: DomainClass::Internet,
+        });
+        packet
+    }
+
+    #[test]
+    fn serialize_client_subnet_truncates_address() {
+        let option = EdnsOption::client_subnet("198.51.100.77".parse().unwrap(), 20).unwrap();
+        let output = option.data();
+        assert_eq!(
+            vec![0, 1, 20, 0, 198, 51, 96],
+            output
+        );
+        assert!(EdnsOption::client_subnet("2001:db8::".parse().unwrap(), 129).is_err());
+    }
+
+    #[test]
+    fn edns_round_trip_through_packet() {
+        let mut packet = query_packet();
+        packet.set_edns(Edns {
+            dnssec_ok: true,
+            options: vec![
+                EdnsOption::Nsid(Vec::new()),
+                EdnsOption::client_subnet("2001:db8:1234::1".parse().unwrap(), 48).unwrap(),
+                EdnsOption::Cookie {
+                    client: [1, 2, 3, 4, 5, 6, 7, 8],
+                    server: Vec::new(),
+                },
+            ],
+            ..Edns::default()
+        });
+        let mut data = Vec::new();
+        packet.serialize(&mut data).unwrap();
+
+        let mut cursor = io::Cursor::new(&data[..]);
+        let header = DnsHeader::deserialize_from(&mut cursor).unwrap();
+        assert_eq!(1, header.additional_count);
+        let output = DnsPacket::deserialize_from(header, &mut cursor, &data).unwrap();
+        assert!(output.additionals.is_empty());
+        assert_eq!(packet.edns, output.edns);
+    }
+
+    #[test]
+    fn parse_response_options() {
+        // NSID "ns1", client subnet 192.0.2.0/24 with scope /16 and a 16 byte cookie
+        let input = b"\x00\x00\x29\x04\xd0\x01\x00\x00\x00\x00\x26\
+            \x00\x03\x00\x03ns1\
+            \x00\x08\x00\x07\x00\x01\x18\x10\xc0\x00\x02\
+            \x00\x0a\x00\x10\x01\x02\x03\x04\x05\x06\x07\x08\xa1\xa2\xa3\xa4\xa5\xa6\xa7\xa8";
+        let mut cursor = io::Cursor::new(&input[..]);
+        let output = Edns::deserialize_from(&mut cursor).unwrap();
+        assert_eq!(1232, output.udp_payload_size);
+        assert_eq!(23, output.full_rcode(7));
+        assert_eq!(
+            vec![
+                EdnsOption::Nsid(b"ns1".to_vec()),
+                EdnsOption::ClientSubnet {
+                    address: "192.0.2.0".parse().unwrap(),
+                    source_prefix: 24,
+                    scope_prefix: 16,
+                },
+                EdnsOption::Cookie {
+                    client: [1, 2, 3, 4, 5, 6, 7, 8],
+                    server: vec![0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8],
+                },
+            ],
+            output.options
+        );
+        assert_eq!(input.len() as u64, cursor.position());
+    }
+
+    #[test]
+    fn parse_invalid_cookie_length() {
+        let input = b"\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x0a\x00\x0a\x00\x06\x01\x02\x03\x04\x05\x06";
+        let mut cursor = io::Cursor::new(&input[..]);
+        assert!(Edns::deserialize_from(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn padding_fills_block() {
+        let mut packet = query_packet();
+        packet.set_edns(Edns::default());
+        pad_to_block_size(&mut packet, QUERY_PADDING_BLOCK_SIZE).unwrap();
+        let mut data = Vec::new();
+        packet.serialize(&mut data).unwrap();
+        assert_eq!(QUERY_PADDING_BLOCK_SIZE, data.len());
+    }
+}
@@ -0,0 +1,719 @@
+use std::cmp;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// NOTE: RFC 7208 section 4.6.4 limits the number of mechanisms and modifiers that require DNS
+//       lookups during SPF evaluation, as well as the number of those lookups that return nothing.
+pub const SPF_MAX_DNS_LOOKUPS: usize = 10;
+pub const SPF_MAX_VOID_LOOKUPS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// Parses a DKIM (or DMARC) record's semicolon-separated list of tag=value pairs, RFC 6376
+// section 3.2. A tag without a value is kept with an empty one.
+pub fn parse_tag_list(input: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    for tag in input.split(';') {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            continue;
+        }
+        match tag.find('=') {
+            Some(index) => result.push((
+                tag[..index].trim().to_string(),
+                tag[index + 1..].trim().to_string(),
+            )),
+            None => result.push((tag.to_string(), String::new())),
+        }
+    }
+    result
+}
+
+pub fn find_tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case(name))
+        .map(|(_, value)| &value[..])
+}
+
+fn read_der_header(data: &[u8], pos: &mut usize) -> Option<(u8, usize)> {
+    let tag = *data.get(*pos)?;
+    let first_len = *data.get(*pos + 1)? as usize;
+    *pos += 2;
+    if first_len < 0x80 {
+        return Some((tag, first_len));
+    }
+
+    let len_bytes = first_len & 0x7F;
+    if len_bytes > 4 {
+        return None;
+    }
+    let mut len = 0;
+    for _ in 0..len_bytes {
+        len = (len << 8) | (*data.get(*pos)? as usize);
+        *pos += 1;
+    }
+    Some((tag, len))
+}
+
+// Returns the size of the modulus of an RSA key that is either DER-encoded SubjectPublicKeyInfo
+// (which is what RFC 6376 asks for) or a bare RSAPublicKey (which some signers publish anyway).
+pub fn rsa_key_bits(der: &[u8]) -> Option<usize> {
+    const SEQUENCE: u8 = 0x30;
+    const INTEGER: u8 = 0x02;
+    const BIT_STRING: u8 = 0x03;
+
+    let mut pos = 0;
+    if read_der_header(der, &mut pos)?.0 != SEQUENCE {
+        return None;
+    }
+    let (mut tag, mut len) = read_der_header(der, &mut pos)?;
+    if tag == SEQUENCE {
+        pos += len;
+        if read_der_header(der, &mut pos)?.0 != BIT_STRING {
+            return None;
+        }
+        pos += 1; // Skip the count of unused bits
+        if read_der_header(der, &mut pos)?.0 != SEQUENCE {
+            return None;
+        }
+        let (modulus_tag, modulus_len) = read_der_header(der, &mut pos)?;
+        tag = modulus_tag;
+        len = modulus_len;
+    }
+    if tag != INTEGER {
+        return None;
+    }
+
+    let modulus = der.get(pos..pos + len)?;
+    let first_nonzero = modulus.iter().position(|b| *b != 0)?;
+    let significant_bytes = modulus.len() - first_nonzero;
+    Some((significant_bytes * 8) - (modulus[first_nonzero].leading_zeros() as usize))
+}
+
+// One space-separated term of an SPF record, RFC 7208 section 4.6.1
+#[derive(Debug, PartialEq)]
+pub struct SpfTerm<'a> {
+    // One of + - ~ ?, which is + if the term doesn't have one
+    pub qualifier: char,
+    // The mechanism or modifier name, in lowercase
+    pub name: String,
+    // Whether this is a modifier (name=value) rather than a mechanism
+    pub is_modifier: bool,
+    // Everything after the ':', '=' or '/', or empty if there isn't one
+    pub argument: &'a str,
+    // The domain the mechanism applies to, which is the record's own domain if none is given
+    pub target: &'a str,
+    // The prefix length(s) after the domain, including the leading '/'
+    pub cidr: Option<&'a str>,
+}
+
+impl<'a> SpfTerm<'a> {
+    pub fn parse(term: &'a str, domain: &'a str) -> SpfTerm<'a> {
+        let (qualifier, mechanism) = match term.chars().next() {
+            Some(q) if "+-~?".contains(q) => (q, &term[1..]),
+            _ => ('+', term),
+        };
+        let name_end = mechanism
+            .find(&[':', '/', '='][..])
+            .unwrap_or(mechanism.len());
+        let separator = mechanism[name_end..].chars().next();
+        let argument = &mechanism[cmp::min(name_end + 1, mechanism.len())..];
+        let (target, cidr) = match separator {
+            Some(':') => match argument.find('/') {
+                Some(index) => (&argument[..index], Some(&argument[index..])),
+                None => (argument, None),
+            },
+            Some('/') => (domain, Some(&mechanism[name_end..])),
+            _ => (domain, None),
+        };
+        SpfTerm {
+            qualifier,
+            name: mechanism[..name_end].to_ascii_lowercase(),
+            is_modifier: separator == Some('='),
+            argument,
+            target,
+            cidr,
+        }
+    }
+
+    // Whether the target domain uses macros (RFC 7208 section 7), which depend on the message
+    // being checked and so can't be resolved ahead of time
+    pub fn uses_macros(&self) -> bool {
+        self.target.contains('%') && (self.name != "ip4") && (self.name != "ip6")
+    }
+
+    // Whether an ip4 or ip6 mechanism has an address of the right family and a prefix length
+    // that fits it
+    pub fn is_valid_network(&self) -> bool {
+        let (address, prefix_len) = match self.argument.find('/') {
+            Some(index) => (&self.argument[..index], Some(&self.argument[index + 1..])),
+            None => (self.argument, None),
+        };
+        let max_prefix_len = if self.name == "ip4" { 32 } else { 128 };
+        let address_valid = match IpAddr::from_str(address) {
+            Ok(IpAddr::V4(_)) => self.name == "ip4",
+            Ok(IpAddr::V6(_)) => self.name == "ip6",
+            Err(_) => false,
+        };
+        let prefix_valid = match prefix_len.map(|p| p.parse::<u8>()) {
+            None => true,
+            Some(Ok(p)) => p <= max_prefix_len,
+            Some(Err(_)) => false,
+        };
+        address_valid && prefix_valid
+    }
+
+    // The prefix length to give an address that an a or mx mechanism resolved to, which can be
+    // different for IPv4 and IPv6 (e.g a/24//64)
+    pub fn cidr_for(&self, address: IpAddr) -> &'a str {
+        let cidr = self.cidr.unwrap_or("");
+        let (ip4_cidr, ip6_cidr) = match cidr.find("//") {
+            Some(index) => (&cidr[..index], &cidr[index + 1..]),
+            None => (cidr, ""),
+        };
+        match address {
+            IpAddr::V4(_) => ip4_cidr,
+            IpAddr::V6(_) => ip6_cidr,
+        }
+    }
+}
+
+// The DNS lookups that SPF evaluation needs
+pub trait SpfLookup {
+    // The text of each TXT record for the name
+    fn txt(&mut self, name: &str) -> Vec<String>;
+    // The exchanges of the name's MX records
+    fn mx(&mut self, name: &str) -> Vec<String>;
+    // The name's IPv4 and IPv6 addresses
+    fn addresses(&mut self, name: &str) -> Vec<IpAddr>;
+}
+
+#[derive(Debug, Default)]
+pub struct SpfEvaluation {
+    // The domain's own SPF record, if it has one
+    pub record: Option<String>,
+    // Each include or redirect that was followed, with the record it led to
+    pub followed: Vec<(String, String)>,
+    pub lookups: usize,
+    pub void_lookups: usize,
+    pub problems: Vec<(Severity, String)>,
+    visited: Vec<String>,
+    // The terms of the record with includes and redirects replaced by what they contain and
+    // a and mx replaced by the addresses they resolve to, each starting with its qualifier
+    terms: Vec<String>,
+}
+
+// Evaluates the SPF record of a domain along with everything it includes or redirects to, to
+// count the DNS lookups that a receiver would need and to flatten it into a single record
+pub fn evaluate_spf<L: SpfLookup>(domain: &str, lookup: &mut L) -> SpfEvaluation {
+    let mut spf = SpfEvaluation {
+        visited: vec![domain.to_string()],
+        ..SpfEvaluation::default()
+    };
+    match spf.evaluate(domain, true, lookup) {
+        Some((record, terms)) => {
+            spf.record = Some(record);
+            spf.terms = terms;
+        }
+        None => {
+            spf.report(Severity::Error, format!("{} has no SPF record", domain));
+            return spf;
+        }
+    }
+
+    if spf.lookups > SPF_MAX_DNS_LOOKUPS {
+        spf.report(Severity::Error, format!("SPF evaluation requires {} DNS lookups, more than the limit of {}, so receivers will treat it as a permanent error", spf.lookups, SPF_MAX_DNS_LOOKUPS));
+    }
+    if spf.void_lookups > SPF_MAX_VOID_LOOKUPS {
+        spf.report(Severity::Error, format!("SPF evaluation has {} lookups that return no records, more than the limit of {}", spf.void_lookups, SPF_MAX_VOID_LOOKUPS));
+    }
+    spf
+}
+
+impl SpfEvaluation {
+    fn report(&mut self, severity: Severity, message: String) {
+        self.problems.push((severity, message));
+    }
+
+    // An equivalent record that needs no DNS lookups (apart from exists and macros), with
+    // duplicate terms removed
+    pub fn flattened(&self) -> String {
+        let mut terms: Vec<&str> = Vec::new();
+        for term in &self.terms {
+            let term = term.trim_start_matches('+');
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+        let mut result = String::from("v=spf1");
+        for term in terms {
+            result.push(' ');
+            result.push_str(term);
+        }
+        result
+    }
+
+    // Returns the domain's record and its flattened terms
+    fn evaluate<L: SpfLookup>(
+        &mut self,
+        domain: &str,
+        is_top_level: bool,
+        lookup: &mut L,
+    ) -> Option<(String, Vec<String>)> {
+        let records: Vec<String> = lookup
+            .txt(domain)
+            .into_iter()
+            .filter(|txt| {
+                let lower = txt.to_ascii_lowercase();
+                lower.starts_with("v=spf1") && matches!(lower[6..].chars().next(), None | Some(' '))
+            })
+            .collect();
+        if records.is_empty() {
+            self.void_lookups += 1;
+            return None;
+        }
+        if records.len() > 1 {
+            self.report(
+                Severity::Error,
+                format!(
+                    "{} publishes {} SPF records, only one is permitted",
+                    domain,
+                    records.len()
+                ),
+            );
+        }
+
+        let record = records[0].clone();
+        let mut terms = Vec::new();
+        let mut all_qualifier = None;
+        let mut redirect = None;
+        for term in record.split_whitespace().skip(1) {
+            let parsed = SpfTerm::parse(term, domain);
+            let (qualifier, name, target) = (parsed.qualifier, parsed.name.as_str(), parsed.target);
+            if parsed.is_modifier {
+                match name {
+                    "redirect" => redirect = Some(parsed.argument.to_string()),
+                    "exp" => {}
+                    _ => self.report(
+                        Severity::Warning,
+                        format!("Unknown SPF modifier in {}: {}", domain, term),
+                    ),
+                }
+                continue;
+            }
+
+            if parsed.uses_macros() {
+                self.report(
+                    Severity::Warning,
+                    format!(
+                        "SPF term {} in {} uses macros, which cannot be checked or flattened",
+                        term, domain
+                    ),
+                );
+                if name != "all" {
+                    self.lookups += 1;
+                }
+                continue;
+            }
+
+            match name {
+                "all" => all_qualifier = Some(qualifier),
+                "include" => {
+                    self.lookups += 1;
+                    if self.visited.iter().any(|v| v.eq_ignore_ascii_case(target)) {
+                        self.report(
+                            Severity::Error,
+                            format!(
+                                "SPF include loop: {} includes {} which has already been evaluated",
+                                domain, target
+                            ),
+                        );
+                        continue;
+                    }
+                    self.visited.push(target.to_string());
+                    match self.evaluate(target, false, lookup) {
+                        Some((included, included_terms)) => {
+                            self.followed.push((format!("include:{}", target), included));
+                            // An include only matches when the included record passes, so only its
+                            // pass terms carry over, and they take the include's qualifier. Any
+                            // other match (including its "all") just means the include didn't.
+                            for included_term in included_terms {
+                                if let Some(mechanism) = included_term.strip_prefix('+') {
+                                    if mechanism != "all" {
+                                        terms.push(format!("{}{}", qualifier, mechanism));
+                                    }
+                                }
+                            }
+                        }
+                        None => self.report(
+                            Severity::Error,
+                            format!(
+                                "SPF record for {} includes {}, which has no SPF record",
+                                domain, target
+                            ),
+                        ),
+                    }
+                }
+                "a" | "mx" => {
+                    self.lookups += 1;
+                    let hosts = if name == "a" {
+                        vec![target.to_string()]
+                    } else {
+                        lookup.mx(target)
+                    };
+                    let mut resolved_any = false;
+                    for host in hosts {
+                        for address in lookup.addresses(&host) {
+                            resolved_any = true;
+                            let prefix = match address {
+                                IpAddr::V4(_) => "ip4",
+                                IpAddr::V6(_) => "ip6",
+                            };
+                            terms.push(format!(
+                                "{}{}:{}{}",
+                                qualifier,
+                                prefix,
+                                address,
+                                parsed.cidr_for(address)
+                            ));
+                        }
+                    }
+                    if !resolved_any {
+                        self.void_lookups += 1;
+                    }
+                }
+                "ptr" => {
+                    self.lookups += 1;
+                    self.report(Severity::Warning, format!("SPF record for {} uses the ptr mechanism, which RFC 7208 section 5.5 says should not be used", domain));
+                }
+                "exists" => {
+                    self.lookups += 1;
+                    terms.push(format!("{}exists:{}", qualifier, target));
+                }
+                "ip4" | "ip6" => {
+                    if parsed.is_valid_network() {
+                        terms.push(format!("{}{}:{}", qualifier, name, parsed.argument));
+                    } else {
+                        self.report(
+                            Severity::Error,
+                            format!("Invalid address in SPF record for {}: {}", domain, term),
+                        );
+                    }
+                }
+                _ => self.report(
+                    Severity::Error,
+                    format!("Unknown SPF mechanism in {}: {}", domain, term),
+                ),
+            }
+        }
+
+        if let Some(redirect) = redirect {
+            // RFC 7208 section 6.1: The redirect modifier is ignored if there is an "all" mechanism
+            if all_qualifier.is_none() {
+                self.lookups += 1;
+                if self.visited.iter().any(|v| v.eq_ignore_ascii_case(&redirect)) {
+                    self.report(Severity::Error, format!("SPF redirect loop: {} redirects to {} which has already been evaluated", domain, redirect));
+                } else {
+                    self.visited.push(redirect.clone());
+                    // Whatever didn't match here is decided by the redirect's record instead, "all"
+                    // and all, so its terms carry on from this record's
+                    match self.evaluate(&redirect, false, lookup) {
+                        Some((redirected, redirected_terms)) => {
+                            self.followed.push((format!("redirect={}", redirect), redirected));
+                            terms.extend(redirected_terms);
+                        }
+                        None => self.report(
+                            Severity::Error,
+                            format!(
+                                "SPF record for {} redirects to {}, which has no SPF record",
+                                domain, redirect
+                            ),
+                        ),
+                    }
+                }
+            }
+        } else if is_top_level {
+            match all_qualifier {
+                Some('+') => self.report(Severity::Error, format!("SPF record for {} ends with +all, which allows any host to send mail", domain)),
+                Some('?') => self.report(Severity::Warning, format!("SPF record for {} ends with ?all, which gives no protection", domain)),
+                Some(_) => {}
+                None => self.report(Severity::Warning, format!("SPF record for {} has no \"all\" mechanism, so unlisted hosts get a neutral result", domain)),
+            }
+        }
+        if let Some(qualifier) = all_qualifier {
+            terms.push(format!("{}all", qualifier));
+        }
+
+        Some((record, terms))
+    }
+}
+
+#[cfg(test)]
+mod mail_tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Answers SPF lookups from fixed records, remembering which names were asked about
+    #[derive(Default)]
+    struct Zone {
+        txt: HashMap<&'static str, &'static str>,
+        mx: HashMap<&'static str, &'static str>,
+        addresses: HashMap<&'static str, &'static str>,
+        queried: Vec<String>,
+    }
+
+    impl Zone {
+        fn new(txt: &[(&'static str, &'static str)]) -> Zone {
+            Zone { txt: txt.iter().cloned().collect(), ..Zone::default() }
+        }
+    }
+
+    impl SpfLookup for Zone {
+        fn txt(&mut self, name: &str) -> Vec<String> {
+            self.queried.push(name.to_string());
+            self.txt.get(name).map(|txt| txt.to_string()).into_iter().collect()
+        }
+
+        fn mx(&mut self, name: &str) -> Vec<String> {
+            self.queried.push(name.to_string());
+            self.mx.get(name).map(|exchange| exchange.to_string()).into_iter().collect()
+        }
+
+        fn addresses(&mut self, name: &str) -> Vec<IpAddr> {
+            self.queried.push(name.to_string());
+            self.addresses.get(name).map(|address| address.parse().unwrap()).into_iter().collect()
+        }
+    }
+
+    fn has_problem(spf: &SpfEvaluation, severity: Severity, text: &str) -> bool {
+        spf.problems.iter().any(|(s, message)| (*s == severity) && message.contains(text))
+    }
+
+    // Wraps the contents in a DER tag and (short or long form) length
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut result = vec![tag];
+        if contents.len() < 0x80 {
+            result.push(contents.len() as u8);
+        } else {
+            result.push(0x82);
+            result.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        }
+        result.extend_from_slice(contents);
+        result
+    }
+
+    fn rsa_public_key(modulus: &[u8]) -> Vec<u8> {
+        let mut contents = der(0x02, modulus);
+        contents.extend(der(0x02, &[0x01, 0x00, 0x01]));
+        der(0x30, &contents)
+    }
+
+    #[test]
+    fn tag_list_is_parsed() {
+        let output = parse_tag_list("v=DKIM1; k=rsa;\tt = y:s ;; p=MIGf MA0=;flag");
+        assert_eq!(
+            vec![
+                (String::from("v"), String::from("DKIM1")),
+                (String::from("k"), String::from("rsa")),
+                (String::from("t"), String::from("y:s")),
+                (String::from("p"), String::from("MIGf MA0=")),
+                (String::from("flag"), String::new()),
+            ],
+            output
+        );
+        assert_eq!(Some("y:s"), find_tag(&output, "T"));
+        assert_eq!(None, find_tag(&output, "h"));
+    }
+
+    #[test]
+    fn rsa_key_sizes() {
+        // A leading zero keeps the modulus positive, and isn't counted
+        let mut modulus = vec![0x00, 0x80];
+        modulus.extend_from_slice(&[0xFF; 127]);
+        let bare_key = rsa_public_key(&modulus);
+        assert_eq!(Some(1024), rsa_key_bits(&bare_key));
+
+        // SubjectPublicKeyInfo with the rsaEncryption algorithm identifier
+        modulus[1] = 0x01;
+        let algorithm = der(0x30, b"\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x01\x05\x00");
+        let mut bit_string = vec![0x00];
+        bit_string.extend(rsa_public_key(&modulus));
+        let mut contents = algorithm;
+        contents.extend(der(0x03, &bit_string));
+        assert_eq!(Some(1017), rsa_key_bits(&der(0x30, &contents)));
+
+        assert_eq!(None, rsa_key_bits(&bare_key[..100]));
+        assert_eq!(None, rsa_key_bits(b"\x04\x00"));
+    }
+
+    #[test]
+    fn spf_terms_are_parsed() {
+        let domain = "example.com";
+        assert_eq!(
+            SpfTerm { qualifier: '-', name: String::from("all"), is_modifier: false, argument: "", target: domain, cidr: None },
+            SpfTerm::parse("-all", domain)
+        );
+        assert_eq!(
+            SpfTerm { qualifier: '+', name: String::from("include"), is_modifier: false, argument: "_spf.example.net", target: "_spf.example.net", cidr: None },
+            SpfTerm::parse("include:_spf.example.net", domain)
+        );
+        assert_eq!(
+            SpfTerm { qualifier: '~', name: String::from("mx"), is_modifier: false, argument: "mail.example.net/28", target: "mail.example.net", cidr: Some("/28") },
+            SpfTerm::parse("~MX:mail.example.net/28", domain)
+        );
+        assert_eq!(
+            SpfTerm { qualifier: '+', name: String::from("a"), is_modifier: false, argument: "24//64", target: domain, cidr: Some("/24//64") },
+            SpfTerm::parse("a/24//64", domain)
+        );
+        assert_eq!(
+            SpfTerm { qualifier: '+', name: String::from("redirect"), is_modifier: true, argument: "_spf.example.net", target: domain, cidr: None },
+            SpfTerm::parse("redirect=_spf.example.net", domain)
+        );
+        assert!(SpfTerm::parse("exists:%{i}._spf.example.com", domain).uses_macros());
+        assert!(!SpfTerm::parse("include:_spf.example.com", domain).uses_macros());
+    }
+
+    #[test]
+    fn spf_networks_are_checked() {
+        let domain = "example.com";
+        assert!(SpfTerm::parse("ip4:192.0.2.0/24", domain).is_valid_network());
+        assert!(SpfTerm::parse("-ip6:2001:db8::/32", domain).is_valid_network());
+        assert!(SpfTerm::parse("ip4:192.0.2.1", domain).is_valid_network());
+        assert!(!SpfTerm::parse("ip4:192.0.2.0/33", domain).is_valid_network());
+        assert!(!SpfTerm::parse("ip4:2001:db8::1", domain).is_valid_network());
+        assert!(!SpfTerm::parse("ip6:192.0.2.1", domain).is_valid_network());
+        assert!(!SpfTerm::parse("ip4:mail.example.com", domain).is_valid_network());
+    }
+
+    #[test]
+    fn spf_includes_and_redirects_are_counted() {
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 include:_spf.example.net mx a:www.example.com redirect=_spf.example.org"),
+            ("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all"),
+            ("_spf.example.org", "v=spf1 include:_spf2.example.org ~all"),
+            ("_spf2.example.org", "v=spf1 ip6:2001:db8::/32 ~all"),
+        ]);
+        zone.mx.insert("example.com", "mail.example.com");
+        zone.addresses.insert("mail.example.com", "198.51.100.1");
+        zone.addresses.insert("www.example.com", "198.51.100.2");
+        let spf = evaluate_spf("example.com", &mut zone);
+        // include, mx, a, redirect and the redirect's include
+        assert_eq!(5, spf.lookups);
+        assert_eq!(0, spf.void_lookups);
+        assert!(spf.problems.is_empty());
+        assert_eq!(
+            vec![
+                (String::from("include:_spf.example.net"), String::from("v=spf1 ip4:192.0.2.0/24 -all")),
+                (String::from("include:_spf2.example.org"), String::from("v=spf1 ip6:2001:db8::/32 ~all")),
+                (String::from("redirect=_spf.example.org"), String::from("v=spf1 include:_spf2.example.org ~all")),
+            ],
+            spf.followed
+        );
+        assert_eq!(
+            "v=spf1 ip4:192.0.2.0/24 ip4:198.51.100.1 ip4:198.51.100.2 ip6:2001:db8::/32 ~all",
+            spf.flattened()
+        );
+    }
+
+    #[test]
+    fn spf_lookup_limit_is_enforced() {
+        let mut zone = Zone::new(&[(
+            "example.com",
+            "v=spf1 a:a.example.com a:b.example.com a:c.example.com a:d.example.com a:e.example.com a:f.example.com a:g.example.com a:h.example.com a:i.example.com a:j.example.com -all",
+        )]);
+        for host in &[
+            "a.example.com", "b.example.com", "c.example.com", "d.example.com", "e.example.com",
+            "f.example.com", "g.example.com", "h.example.com", "i.example.com", "j.example.com",
+        ] {
+            zone.addresses.insert(host, "192.0.2.1");
+        }
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(10, spf.lookups);
+        assert!(spf.problems.is_empty());
+        assert_eq!("v=spf1 ip4:192.0.2.1 -all", spf.flattened());
+
+        zone.txt.insert("example.com", "v=spf1 a:a.example.com a:b.example.com a:c.example.com a:d.example.com a:e.example.com a:f.example.com a:g.example.com a:h.example.com a:i.example.com a:j.example.com exists:example.com -all");
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(11, spf.lookups);
+        assert!(has_problem(&spf, Severity::Error, "requires 11 DNS lookups, more than the limit of 10"));
+    }
+
+    #[test]
+    fn spf_void_lookups_are_counted() {
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 a:missing.example.com mx include:_spf.example.net -all"),
+            ("_spf.example.net", "v=spf1 ip4:192.0.2.1 -all"),
+        ]);
+        // The a has no addresses and there are no MX records
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(2, spf.void_lookups);
+        assert!(spf.problems.is_empty());
+
+        zone.txt.remove("_spf.example.net");
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(3, spf.void_lookups);
+        assert!(has_problem(&spf, Severity::Error, "includes _spf.example.net, which has no SPF record"));
+        assert!(has_problem(&spf, Severity::Error, "3 lookups that return no records, more than the limit of 2"));
+    }
+
+    #[test]
+    fn spf_loops_are_detected() {
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 include:_spf.example.net -all"),
+            ("_spf.example.net", "v=spf1 ip4:192.0.2.1 include:_spf.example.com -all"),
+            ("_spf.example.com", "v=spf1 include:example.com -all"),
+        ]);
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert!(has_problem(&spf, Severity::Error, "SPF include loop: _spf.example.com includes example.com"));
+        assert_eq!(3, spf.lookups);
+        assert_eq!("v=spf1 ip4:192.0.2.1 -all", spf.flattened());
+
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 redirect=_spf.example.com"),
+            ("_spf.example.com", "v=spf1 redirect=EXAMPLE.COM"),
+        ]);
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert!(has_problem(&spf, Severity::Error, "SPF redirect loop: _spf.example.com redirects to EXAMPLE.COM"));
+    }
+
+    #[test]
+    fn spf_redirect_is_ignored_with_all() {
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 ip4:192.0.2.1 -all redirect=_spf.example.net"),
+            ("_spf.example.net", "v=spf1 ip4:198.51.100.0/24 ~all"),
+        ]);
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(0, spf.lookups);
+        assert_eq!(vec![String::from("example.com")], zone.queried);
+        assert_eq!("v=spf1 ip4:192.0.2.1 -all", spf.flattened());
+    }
+
+    #[test]
+    fn spf_flattening_keeps_the_meaning() {
+        // Only what an included record passes carries over, under the include's qualifier
+        let mut zone = Zone::new(&[
+            ("example.com", "v=spf1 include:_spf.example.net ~include:_spf.example.org a/24//64"),
+            ("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -ip4:192.0.2.128/25 ?ip6:2001:db8::/32 ~all"),
+            ("_spf.example.org", "v=spf1 +ip4:198.51.100.0/24 include:_spf2.example.org -include:_spf3.example.org -all"),
+            ("_spf2.example.org", "v=spf1 ip6:2001:db8:1::/48 +all"),
+            ("_spf3.example.org", "v=spf1 ip6:2001:db8:2::/48 -all"),
+        ]);
+        zone.addresses.insert("example.com", "2001:db8::25");
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!(
+            "v=spf1 ip4:192.0.2.0/24 ~ip4:198.51.100.0/24 ~ip6:2001:db8:1::/48 ip6:2001:db8::25/64",
+            spf.flattened()
+        );
+        // Without an "all" in the record itself, unlisted hosts are still neutral when flattened
+        assert!(has_problem(&spf, Severity::Warning, "has no \"all\" mechanism"));
+
+        zone.txt.insert("example.com", "v=spf1 -include:_spf.example.net ?all");
+        let spf = evaluate_spf("example.com", &mut zone);
+        assert_eq!("v=spf1 -ip4:192.0.2.0/24 ?all", spf.flattened());
+        assert!(has_problem(&spf, Severity::Warning, "ends with ?all"));
+    }
+}
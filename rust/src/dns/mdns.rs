@@ -0,0 +1,366 @@
+use super::packet::{DnsHeader, DnsPacket, DnsQuestion, DomainClass, QueryType};
+use super::record::{Record, RecordData};
+use super::resolver::DnsResponse;
+use rand::Rng;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use std::time::{Duration, Instant};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_IPV4_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_IPV6_GROUP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+pub const DEFAULT_MDNS_WINDOW: Duration = Duration::from_secs(2);
+
+// RFC 6763 section 9: Querying this name for PTR records lists the types of service on the network
+pub const DNS_SD_SERVICES: &str = "_services._dns-sd._udp.local";
+
+// RFC 6762 section 17: Multicast DNS messages may be up to 9000 bytes, the size of a jumbo frame
+const MAX_MDNS_MESSAGE_SIZE: usize = 9000;
+
+// Sends a one-shot multicast query (RFC 6762 section 5.1) and collects every response that arrives
+// before the window closes. Because the query is sent from an ephemeral port, responders send
+// their answers back to us directly (section 6.7) rather than to the multicast group.
+pub fn send_multicast_query(
+    questions: Vec<DnsQuestion>,
+    ipv6: bool,
+    window: Duration,
+) -> io::Result<Vec<DnsResponse>> {
+    let mut request = DnsPacket::default();
+    request.header.request_id = rand::thread_rng().gen::<u16>();
+    request.header.query_count = questions.len() as u16;
+    request.questions = questions;
+    let mut request_data = Vec::new();
+    request.serialize(&mut request_data)?;
+
+    let (socket, group) = if ipv6 {
+        let socket = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?;
+        socket.set_multicast_loop_v6(true)?;
+        let group = SocketAddr::V6(SocketAddrV6::new(MDNS_IPV6_GROUP, MDNS_PORT, 0, 0));
+        (socket, group)
+    } else {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        // RFC 6762 section 11: All multicast DNS messages are sent with an IP TTL of 255
+        socket.set_multicast_ttl_v4(255)?;
+        socket.set_multicast_loop_v4(true)?;
+        (socket, SocketAddr::new(IpAddr::V4(MDNS_IPV4_GROUP), MDNS_PORT))
+    };
+
+    let send_instant = Instant::now();
+    if let Err(e) = socket.send_to(&request_data, group) {
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Failed to send query to {}: {}", group, e),
+        ));
+    }
+
+    let mut result = Vec::new();
+    let mut buffer = vec![0; MAX_MDNS_MESSAGE_SIZE];
+    loop {
+        let elapsed = send_instant.elapsed();
+        if elapsed >= window {
+            break;
+        }
+        socket.set_read_timeout(Some(window - elapsed))?;
+        let (bytes_read, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        let roundtrip = send_instant.elapsed();
+        let data = &buffer[..bytes_read];
+
+        // Anything that isn't a well-formed response to this query is just noise on the network
+        let mut cursor = io::Cursor::new(data);
+        let header = match DnsHeader::deserialize_from(&mut cursor) {
+            Ok(header) => header,
+            Err(_) => continue,
+        };
+        if !header.is_response || (header.request_id != request.header.request_id) {
+            continue;
+        }
+        if let Ok(packet) = DnsPacket::deserialize_from(header, &mut cursor, data) {
+            result.push(DnsResponse {
+                request_id: request.header.request_id,
+                packet,
+                data: data.to_vec(),
+                source,
+                roundtrip,
+                rejected: Vec::new(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+// Gathers the records from every section of every response, since responders put related records
+// (e.g the SRV, TXT and addresses for a PTR) in the additional section to save further queries.
+pub fn collect_records(responses: &[DnsResponse]) -> Vec<(SocketAddr, Record)> {
+    let mut result: Vec<(SocketAddr, Record)> = Vec::new();
+    for response in responses {
+        let packet = &response.packet;
+        let all_records = packet
+            .answers
+            .iter()
+            .chain(&packet.authorities)
+            .chain(&packet.additionals);
+        for resource_record in all_records {
+            let record = match Record::deserialize_from(resource_record, &response.data) {
+                Ok(record) => record,
+                Err(_) => continue,
+            };
+            if !result
+                .iter()
+                .any(|(source, r)| (*source == response.source) && r.is_same_record(&record))
+            {
+                result.push((response.source, record));
+            }
+        }
+    }
+    result
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceInstance {
+    pub name: String,
+    pub service_type: String,
+    pub responder: Option<SocketAddr>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub addresses: Vec<IpAddr>,
+    pub txt: Vec<String>,
+}
+
+impl ServiceInstance {
+    // The instance name without the service type, e.g "Office Printer" for
+    // "Office Printer._ipp._tcp.local"
+    pub fn short_name(&self) -> &str {
+        // The name comes from the responder and can have multi-byte characters anywhere, so the
+        // suffix is compared as bytes rather than by slicing the string at an arbitrary index
+        let suffix = format!(".{}", self.service_type);
+        let name = self.name.as_bytes();
+        let short_len = name.len().saturating_sub(suffix.len());
+        if (short_len > 0) && name[short_len..].eq_ignore_ascii_case(suffix.as_bytes()) {
+            if let Some(short_name) = self.name.get(..short_len) {
+                return short_name;
+            }
+        }
+        &self.name
+    }
+}
+
+fn names_match(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn find_records<'a>(
+    records: &'a [(SocketAddr, Record)],
+    name: &'a str,
+    data_type: QueryType,
+) -> impl Iterator<Item = &'a (SocketAddr, Record)> + 'a {
+    records
+        .iter()
+        .filter(move |(_, r)| (r.data_type == data_type) && names_match(&r.domain_name, name))
+}
+
+pub fn service_types(records: &[(SocketAddr, Record)]) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for (_, record) in find_records(records, DNS_SD_SERVICES, QueryType::PTR) {
+        let service_type = record.data.to_string();
+        if !result.iter().any(|t| names_match(t, &service_type)) {
+            result.push(service_type);
+        }
+    }
+    result.sort();
+    result
+}
+
+// Pieces together the instances of the given service type from the PTR, SRV, TXT, A and AAAA
+// records that have been received so far (RFC 6763 sections 4 to 6).
+pub fn service_instances(service_type: &str, records: &[(SocketAddr, Record)]) -> Vec<ServiceInstance> {
+    let mut result: Vec<ServiceInstance> = Vec::new();
+    for (responder, ptr) in find_records(records, service_type, QueryType::PTR) {
+        let name = ptr.data.to_string();
+        if result.iter().any(|instance| names_match(&instance.name, &name)) {
+            continue;
+        }
+
+        let mut instance = ServiceInstance {
+            name: name.clone(),
+            service_type: String::from(service_type.trim_end_matches('.')),
+            responder: Some(*responder),
+            host: None,
+            port: None,
+            addresses: Vec::new(),
+            txt: Vec::new(),
+        };
+        if let Some((_, srv)) = find_records(records, &name, QueryType::SRV).next() {
+            if let RecordData::SRV { port, target, .. } = &srv.data {
+                instance.host = Some(target.clone());
+                instance.port = Some(*port);
+            }
+        }
+        for (_, txt) in find_records(records, &name, QueryType::TXT) {
+            if let RecordData::TXT(strings) = &txt.data {
+                // RFC 6763 section 6.1: A TXT record with a single empty string means "no data"
+                instance.txt.extend(
+                    strings
+                        .iter()
+                        .filter(|s| !s.is_empty())
+                        .map(|s| super::escape_character_string(s)),
+                );
+            }
+        }
+        if let Some(host) = &instance.host {
+            for (_, record) in records.iter().filter(|(_, r)| names_match(&r.domain_name, host)) {
+                let address = match record.data {
+                    RecordData::A(addr) => IpAddr::V4(addr),
+                    RecordData::AAAA(addr) => IpAddr::V6(addr),
+                    _ => continue,
+                };
+                if !instance.addresses.contains(&address) {
+                    instance.addresses.push(address);
+                }
+            }
+        }
+        result.push(instance);
+    }
+    result.sort_by_key(|instance| instance.name.to_lowercase());
+    result
+}
+
+fn question(name: &str, query_type: QueryType) -> DnsQuestion {
+    DnsQuestion {
+        domain_name: String::from(name),
+        query_type,
+        query_class: DomainClass::Internet,
+    }
+}
+
+// Browses for instances of the given service types (e.g "_ipp._tcp.local"), or of every type of
+// service that is advertised on the network if none are given. Each step only queries for the
+// records that weren't already included in earlier responses.
+pub fn browse_services(
+    service_types_to_browse: &[String],
+    ipv6: bool,
+    window: Duration,
+) -> io::Result<Vec<ServiceInstance>> {
+    let mut records = Vec::new();
+    let types = if service_types_to_browse.is_empty() {
+        let responses = send_multicast_query(vec![question(DNS_SD_SERVICES, QueryType::PTR)], ipv6, window)?;
+        records.extend(collect_records(&responses));
+        service_types(&records)
+    } else {
+        service_types_to_browse.to_vec()
+    };
+    if types.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let questions = types.iter().map(|t| question(t, QueryType::PTR)).collect();
+    let responses = send_multicast_query(questions, ipv6, window)?;
+    records.extend(collect_records(&responses));
+
+    let mut questions = Vec::new();
+    for service_type in &types {
+        for instance in service_instances(service_type, &records) {
+            if instance.host.is_none() {
+                questions.push(question(&instance.name, QueryType::SRV));
+            }
+            if find_records(&records, &instance.name, QueryType::TXT).next().is_none() {
+                questions.push(question(&instance.name, QueryType::TXT));
+            }
+        }
+    }
+    if !questions.is_empty() {
+        let responses = send_multicast_query(questions, ipv6, window)?;
+        records.extend(collect_records(&responses));
+    }
+
+    let mut questions: Vec<DnsQuestion> = Vec::new();
+    for service_type in &types {
+        for instance in service_instances(service_type, &records) {
+            if let (Some(host), true) = (&instance.host, instance.addresses.is_empty()) {
+                if !questions.iter().any(|q| names_match(&q.domain_name, host)) {
+                    questions.push(question(host, QueryType::A));
+                    questions.push(question(host, QueryType::AAAA));
+                }
+            }
+        }
+    }
+    if !questions.is_empty() {
+        let responses = send_multicast_query(questions, ipv6, window)?;
+        records.extend(collect_records(&responses));
+    }
+
+    Ok(types
+        .iter()
+        .flat_map(|service_type| service_instances(service_type, &records))
+        .collect())
+}
+
+#[cfg(test)]
+mod mdns_tests {
+    use super::*;
+
+    fn responder() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)), MDNS_PORT)
+    }
+
+    fn test_records() -> Vec<(SocketAddr, Record)> {
+        let records = vec![
+            Record::new(DNS_SD_SERVICES, RecordData::PTR(String::from("_ipp._tcp.local")), 4500),
+            Record::new(DNS_SD_SERVICES, RecordData::PTR(String::from("_http._tcp.local")), 4500),
+            Record::new("_ipp._tcp.local", RecordData::PTR(String::from("Office Printer._ipp._tcp.local")), 4500),
+            Record::new("Office Printer._ipp._tcp.local", RecordData::parse(QueryType::SRV, "0 0 631 printer.local").unwrap(), 120),
+            Record::new("Office Printer._ipp._tcp.local", RecordData::TXT(vec![b"rp=ipp/print".to_vec(), b"ty=Laser".to_vec()]), 4500),
+            Record::new("printer.local", RecordData::A(Ipv4Addr::new(192, 168, 1, 20)), 120),
+            Record::new("PRINTER.local", RecordData::AAAA(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x20)), 120),
+        ];
+        records.into_iter().map(|r| (responder(), r)).collect()
+    }
+
+    #[test]
+    fn service_types_are_listed() {
+        let output = service_types(&test_records());
+        assert_eq!(
+            vec![String::from("_http._tcp.local"), String::from("_ipp._tcp.local")],
+            output
+        );
+    }
+
+    #[test]
+    fn short_name_of_instance_outside_the_service_type() {
+        // Cutting the service type's length off the end of this name would split a character
+        let name = "Drucker \u{2615}\u{2615}\u{2615}\u{2615}\u{2615}.local";
+        let records = vec![(responder(), Record::new("_ipp._tcp.local", RecordData::PTR(String::from(name)), 4500))];
+        let output = service_instances("_ipp._tcp.local", &records);
+        assert_eq!(name, output[0].short_name());
+    }
+
+    #[test]
+    fn service_instance_is_assembled_from_records() {
+        let output = service_instances("_ipp._tcp.local", &test_records());
+        assert_eq!(1, output.len());
+        assert_eq!("Office Printer", output[0].short_name());
+        assert_eq!(Some(String::from("printer.local")), output[0].host);
+        assert_eq!(Some(631), output[0].port);
+        assert_eq!(2, output[0].addresses.len());
+        assert_eq!(
+            vec![String::from("rp=ipp/print"), String::from("ty=Laser")],
+            output[0].txt
+        );
+    }
+
+    #[test]
+    fn service_instance_without_srv_has_no_host() {
+        let output = service_instances("_http._tcp.local", &test_records());
+        assert!(output.is_empty());
+
+        let mut records = test_records();
+        records.push((responder(), Record::new("_http._tcp.local", RecordData::PTR(String::from("Admin._http._tcp.local")), 4500)));
+        let output = service_instances("_http._tcp.local", &records);
+        assert_eq!(None, output[0].host);
+        assert!(output[0].addresses.is_empty());
+    }
+}
@@ -0,0 +1,765 @@
+use super::edns::Edns;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryInto;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DomainClass {
+    Reserved = 0,
+    Internet = 1,
+    Chaos = 3,
+    Hesiod = 4,
+    None = 254,
+    Any = 255,
+}
+
+impl DomainClass {
+    pub fn from_int(i: u16) -> Option<DomainClass> {
+        match i {
+            0 => Some(DomainClass::Reserved),
+            1 => Some(DomainClass::Internet),
+            3 => Some(DomainClass::Chaos),
+            4 => Some(DomainClass::Hesiod),
+            254 => Some(DomainClass::None),
+            255 => Some(DomainClass::Any),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QueryType {
+    // Any type without a variant of its own (including the reserved type 0), whose data can still
+    // be passed around as raw bytes
+    Unknown(u16),
+    A,
+    NS,
+    MD,
+    MF,
+    CNAME,
+    SOA,
+    MB,
+    MG,
+    MR,
+    NULL,
+    WKS,
+    PTR,
+    HINFO,
+    MINFO,
+    MX,
+    TXT,
+    RP,
+    AFSDB,
+    X25,
+    ISDN,
+    RT,
+    NSAP,
+    NSAPPTR,
+    SIG,
+    KEY,
+    PX,
+    GPOS,
+    AAAA,
+    LOC,
+    NXT,
+    EID,
+    NIMLOC,
+    SRV,
+    NAPTR,
+    OPT,
+    DS,
+    SSHFP,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    TLSA,
+    SVCB,
+    HTTPS,
+    TSIG,
+    ANY,
+}
+
+impl QueryType {
+    pub fn from_int(i: u16) -> QueryType {
+        match i {
+            1 => QueryType::A,
+            2 => QueryType::NS,
+            3 => QueryType::MD,
+            4 => QueryType::MF,
+            5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            7 => QueryType::MB,
+            8 => QueryType::MG,
+            9 => QueryType::MR,
+            10 => QueryType::NULL,
+            11 => QueryType::WKS,
+            12 => QueryType::PTR,
+            13 => QueryType::HINFO,
+            14 => QueryType::MINFO,
+            15 => QueryType::MX,
+            16 => QueryType::TXT,
+            17 => QueryType::RP,
+            18 => QueryType::AFSDB,
+            19 => QueryType::X25,
+            20 => QueryType::ISDN,
+            21 => QueryType::RT,
+            22 => QueryType::NSAP,
+            23 => QueryType::NSAPPTR,
+            24 => QueryType::SIG,
+            25 => QueryType::KEY,
+            26 => QueryType::PX,
+            27 => QueryType::GPOS,
+            28 => QueryType::AAAA,
+            29 => QueryType::LOC,
+            30 => QueryType::NXT,
+            31 => QueryType::EID,
+            32 => QueryType::NIMLOC,
+            33 => QueryType::SRV,
+            35 => QueryType::NAPTR,
+            41 => QueryType::OPT,
+            43 => QueryType::DS,
+            44 => QueryType::SSHFP,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            52 => QueryType::TLSA,
+            64 => QueryType::SVCB,
+            65 => QueryType::HTTPS,
+            250 => QueryType::TSIG,
+            255 => QueryType::ANY,
+            _ => QueryType::Unknown(i),
+        }
+    }
+
+    pub fn to_int(self) -> u16 {
+        match self {
+            QueryType::Unknown(i) => i,
+            QueryType::A => 1,
+            QueryType::NS => 2,
+            QueryType::MD => 3,
+            QueryType::MF => 4,
+            QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::MB => 7,
+            QueryType::MG => 8,
+            QueryType::MR => 9,
+            QueryType::NULL => 10,
+            QueryType::WKS => 11,
+            QueryType::PTR => 12,
+            QueryType::HINFO => 13,
+            QueryType::MINFO => 14,
+            QueryType::MX => 15,
+            QueryType::TXT => 16,
+            QueryType::RP => 17,
+            QueryType::AFSDB => 18,
+            QueryType::X25 => 19,
+            QueryType::ISDN => 20,
+            QueryType::RT => 21,
+            QueryType::NSAP => 22,
+            QueryType::NSAPPTR => 23,
+            QueryType::SIG => 24,
+            QueryType::KEY => 25,
+            QueryType::PX => 26,
+            QueryType::GPOS => 27,
+            QueryType::AAAA => 28,
+            QueryType::LOC => 29,
+            QueryType::NXT => 30,
+            QueryType::EID => 31,
+            QueryType::NIMLOC => 32,
+            QueryType::SRV => 33,
+            QueryType::NAPTR => 35,
+            QueryType::OPT => 41,
+            QueryType::DS => 43,
+            QueryType::SSHFP => 44,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::TLSA => 52,
+            QueryType::SVCB => 64,
+            QueryType::HTTPS => 65,
+            QueryType::TSIG => 250,
+            QueryType::ANY => 255,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpCode {
+    Standard = 0,
+    Inverse = 1,
+    Status = 2,
+    Notify = 4,
+    Update = 5,
+}
+
+impl OpCode {
+    pub fn from_int(i: u16) -> Option<OpCode> {
+        match i {
+            0 => Some(OpCode::Standard),
+            1 => Some(OpCode::Inverse),
+            2 => Some(OpCode::Status),
+            4 => Some(OpCode::Notify),
+            5 => Some(OpCode::Update),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResponseCode {
+    NoError = 0,
+    FormatError = 1,
+    ServerFailure = 2,
+    NameError = 3,
+    NotImplemented = 4,
+    Refused = 5,
+    NameExists = 6,
+    RecordSetExists = 7,
+    RecordSetDoesNotExist = 8,
+    NotAuthorized = 9,
+    NotInZone = 10,
+}
+
+impl ResponseCode {
+    pub fn from_int(i: u16) -> Option<ResponseCode> {
+        match i {
+            0 => Some(ResponseCode::NoError),
+            1 => Some(ResponseCode::FormatError),
+            2 => Some(ResponseCode::ServerFailure),
+            3 => Some(ResponseCode::NameError),
+            4 => Some(ResponseCode::NotImplemented),
+            5 => Some(ResponseCode::Refused),
+            6 => Some(ResponseCode::NameExists),
+            7 => Some(ResponseCode::RecordSetExists),
+            8 => Some(ResponseCode::RecordSetDoesNotExist),
+            9 => Some(ResponseCode::NotAuthorized),
+            10 => Some(ResponseCode::NotInZone),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DnsHeader {
+    pub request_id: u16,
+    pub is_response: bool,
+    pub opcode: OpCode,
+    pub authoritative_answer: bool,
+    pub message_truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub rcode: ResponseCode,
+    pub query_count: u16,
+    pub answer_count: u16,
+    pub nameserver_count: u16,
+    pub additional_count: u16,
+}
+
+impl Default for DnsHeader {
+    fn default() -> DnsHeader {
+        DnsHeader {
+            request_id: 0,
+            is_response: false,
+            opcode: OpCode::Standard,
+            authoritative_answer: false,
+            message_truncated: false,
+            recursion_desired: false,
+            recursion_available: false,
+            rcode: ResponseCode::NoError,
+            query_count: 0,
+            answer_count: 0,
+            nameserver_count: 0,
+            additional_count: 0,
+        }
+    }
+}
+
+impl DnsHeader {
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<usize> {
+        let bitflags: u16 = (u16::from(self.is_response) << 15)
+            | ((self.opcode as u16) << 11)
+            | (u16::from(self.authoritative_answer) << 10)
+            | (u16::from(self.message_truncated) << 9)
+            | (u16::from(self.recursion_desired) << 8)
+            | (u16::from(self.recursion_available) << 7)
+            // 4 0 bits << 4
+            | (self.rcode as u16);
+
+        let start_len = buffer.len();
+        buffer.write_u16::<BigEndian>(self.request_id)?;
+        buffer.write_u16::<BigEndian>(bitflags)?;
+        buffer.write_u16::<BigEndian>(self.query_count)?;
+        buffer.write_u16::<BigEndian>(self.answer_count)?;
+        buffer.write_u16::<BigEndian>(self.nameserver_count)?;
+        buffer.write_u16::<BigEndian>(self.additional_count)?;
+        let end_len = buffer.len();
+        Ok(end_len - start_len)
+    }
+
+    pub fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>) -> io::Result<()> {
+        self.request_id = cursor.read_u16::<BigEndian>()?;
+        let bitflags = cursor.read_u16::<BigEndian>()?;
+        match OpCode::from_int((bitflags >> 11) & 0b1111) {
+            Some(oc) => {
+                self.opcode = oc;
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid query operation code",
+                ));
+            }
+        }
+        self.is_response = ((bitflags >> 15) & 0b1) == 1;
+        self.authoritative_answer = ((bitflags >> 10) & 0b1) == 1;
+        self.message_truncated = ((bitflags >> 9) & 0b1) == 1;
+        self.recursion_desired = ((bitflags >> 8) & 0b1) == 1;
+        self.recursion_available = ((bitflags >> 7) & 0b1) == 1;
+        match ResponseCode::from_int(bitflags & 0b1111) {
+            Some(rc) => {
+                self.rcode = rc;
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid response code",
+                ));
+            }
+        }
+        self.query_count = cursor.read_u16::<BigEndian>()?;
+        self.answer_count = cursor.read_u16::<BigEndian>()?;
+        self.nameserver_count = cursor.read_u16::<BigEndian>()?;
+        self.additional_count = cursor.read_u16::<BigEndian>()?;
+        Ok(())
+    }
+
+    pub fn deserialize_from(cursor: &mut io::Cursor<&[u8]>) -> io::Result<DnsHeader> {
+        let mut result = DnsHeader::default();
+        result.deserialize(cursor)?;
+        Ok(result)
+    }
+}
+
+// RFC 6762 sections 5.4 and 10.2: Multicast DNS uses the top bit of the class field in questions
+// to request a unicast response and in records to tell caches to flush older records, so it is
+// never part of the class itself.
+const CLASS_MASK: u16 = 0x7FFF;
+
+#[derive(Clone, Debug)]
+pub struct DnsQuestion {
+    pub domain_name: String,
+    pub query_type: QueryType,
+    pub query_class: DomainClass,
+}
+
+impl Default for DnsQuestion {
+    fn default() -> DnsQuestion {
+        DnsQuestion {
+            domain_name: String::new(),
+            query_type: QueryType::Unknown(0),
+            query_class: DomainClass::Reserved,
+        }
+    }
+}
+
+impl DnsQuestion {
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        serialize_name(&self.domain_name, buffer)?;
+        buffer.write_u16::<BigEndian>(self.query_type.to_int())?;
+        buffer.write_u16::<BigEndian>(self.query_class as u16)?;
+        Ok(())
+    }
+
+    pub fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>) -> io::Result<()> {
+        // NOTE: Questions after the first may use compression pointers into the rest of the message
+        let all_data: &[u8] = cursor.get_ref();
+        self.domain_name = deserialize_name_string(cursor, all_data)?;
+
+        self.query_type = QueryType::from_int(cursor.read_u16::<BigEndian>()?);
+        match DomainClass::from_int(cursor.read_u16::<BigEndian>()? & CLASS_MASK) {
+            Some(class) => {
+                self.query_class = class;
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid domain class",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn deserialize_from(cursor: &mut io::Cursor<&[u8]>) -> io::Result<DnsQuestion> {
+        let mut result = DnsQuestion::default();
+        result.deserialize(cursor)?;
+        Ok(result)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DnsResourceRecord {
+    pub domain_name: String,
+    pub data_type: QueryType,
+    pub data_class: DomainClass,
+    pub ttl: u32,
+    pub data_length: u16,
+    pub data: Vec<u8>, // TODO: Maybe we want this to be a &[u8]? To save on copying? Depends on the deserialize
+}
+
+impl Default for DnsResourceRecord {
+    fn default() -> DnsResourceRecord {
+        DnsResourceRecord {
+            domain_name: String::new(),
+            data_type: QueryType::Unknown(0),
+            data_class: DomainClass::Reserved,
+            ttl: 0,
+            data_length: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+// RFC 1035 section 2.3.4
+pub const MAX_LABEL_LENGTH: usize = 63;
+pub const MAX_NAME_LENGTH: usize = 255;
+
+pub fn serialize_name(name: &str, buffer: &mut Vec<u8>) -> io::Result<()> {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Label \"{}\" is longer than {} bytes", label, MAX_LABEL_LENGTH),
+            ));
+        }
+        buffer.write_u8(label.len() as u8)?;
+        buffer.extend_from_slice(label.as_bytes());
+    }
+    buffer.write_u8(0)?;
+    Ok(())
+}
+
+// Following more pointers than this in one name can only be a malicious message
+const MAX_POINTER_JUMPS: usize = 64;
+
+pub fn deserialize_name(cursor: &mut io::Cursor<&[u8]>, all_data: &[u8]) -> io::Result<Vec<u8>> {
+    const POINTER_MASK: u8 = 0b11 << 6;
+    // Names in record data are read from a copy of it rather than from the message, so the first
+    // pointer can only be checked against where it came from when the cursor is over the message
+    let in_message = std::ptr::eq(*cursor.get_ref(), all_data);
+    let mut reader = cursor.clone();
+    let mut result = Vec::new();
+    let mut jumps = 0;
+    // Including the length bytes and the final zero
+    let mut wire_length = 1;
+    loop {
+        let label_start = reader.position();
+        let len = reader.read_u8()?;
+        if len == 0 {
+            break;
+        }
+
+        if len & POINTER_MASK == POINTER_MASK {
+            let offset_hi = len & !POINTER_MASK;
+            let offset_lo = reader.read_u8()?;
+            let offset = ((offset_hi as u64) << 8) | (offset_lo as u64);
+            // Every pointer has to go strictly backwards, so they can't form a loop
+            if (in_message || jumps > 0) && offset >= label_start {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Name compression pointer at {} doesn't point backwards", label_start),
+                ));
+            }
+            jumps += 1;
+            if jumps > MAX_POINTER_JUMPS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Name has more than {} compression pointers", MAX_POINTER_JUMPS),
+                ));
+            }
+            // The name ends (in the original data) just after the first pointer
+            if jumps == 1 {
+                cursor.set_position(reader.position());
+            }
+            reader = io::Cursor::new(all_data);
+            reader.set_position(offset);
+            continue;
+        }
+
+        wire_length += 1 + len as usize;
+        if wire_length > MAX_NAME_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Name is longer than {} bytes", MAX_NAME_LENGTH),
+            ));
+        }
+        if !result.is_empty() {
+            result.push(b'.');
+        }
+        for _ in 0..len {
+            result.push(reader.read_u8()?);
+        }
+    }
+    if jumps == 0 {
+        cursor.set_position(reader.position());
+    }
+    Ok(result)
+}
+
+pub fn deserialize_name_string(cursor: &mut io::Cursor<&[u8]>, all_data: &[u8]) -> io::Result<String> {
+    let name_bytes = deserialize_name(cursor, all_data)?;
+    match String::from_utf8(name_bytes) {
+        Ok(name) => Ok(name),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+impl DnsResourceRecord {
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        let data_length: u16 = match self.data.len().try_into() {
+            Ok(len) => len,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Record data is longer than 65535 bytes",
+                ))
+            }
+        };
+        serialize_name(&self.domain_name, buffer)?;
+        buffer.write_u16::<BigEndian>(self.data_type.to_int())?;
+        buffer.write_u16::<BigEndian>(self.data_class as u16)?;
+        buffer.write_u32::<BigEndian>(self.ttl)?;
+        buffer.write_u16::<BigEndian>(data_length)?;
+        buffer.extend_from_slice(&self.data);
+        Ok(())
+    }
+
+    pub fn deserialize(&mut self, cursor: &mut io::Cursor<&[u8]>, all_data: &[u8]) -> io::Result<()> {
+        let name_bytes = deserialize_name(cursor, all_data)?;
+        match String::from_utf8(name_bytes) {
+            Ok(name) => {
+                self.domain_name = name;
+            }
+            Err(e) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+
+        self.data_type = QueryType::from_int(cursor.read_u16::<BigEndian>()?);
+        let class_int = cursor.read_u16::<BigEndian>()?;
+        match DomainClass::from_int(class_int & CLASS_MASK) {
+            Some(class) => {
+                self.data_class = class;
+            }
+            // The class of an OPT record is really a payload size (see Edns) so it's left as-is here
+            None if self.data_type == QueryType::OPT => {
+                self.data_class = DomainClass::Reserved;
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid domain class",
+                ));
+            }
+        };
+        self.ttl = cursor.read_u32::<BigEndian>()?;
+        self.data_length = cursor.read_u16::<BigEndian>()?;
+        self.data.reserve_exact(self.data_length as usize);
+        for _ in 0..self.data_length {
+            let b = cursor.read_u8()?;
+            self.data.push(b);
+        }
+        Ok(())
+    }
+
+    pub fn deserialize_from(
+        cursor: &mut io::Cursor<&[u8]>,
+        all_data: &[u8],
+    ) -> io::Result<DnsResourceRecord> {
+        let mut result = DnsResourceRecord::default();
+        result.deserialize(cursor, all_data)?;
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DnsPacket {
+    pub header: DnsHeader,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsResourceRecord>,
+    pub authorities: Vec<DnsResourceRecord>,
+    pub additionals: Vec<DnsResourceRecord>,
+    // The OPT pseudo-record, which is counted as part of the additional section
+    pub edns: Option<Edns>,
+}
+
+impl DnsPacket {
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        self.header.serialize(buffer)?;
+
+        for question in &self.questions {
+            question.serialize(buffer)?;
+        }
+        for record in self.answers.iter().chain(&self.authorities) {
+            record.serialize(buffer)?;
+        }
+        // The OPT record goes first in the additional section since a TSIG record must be last
+        if let Some(edns) = &self.edns {
+            edns.serialize(buffer)?;
+        }
+        for record in &self.additionals {
+            record.serialize(buffer)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_edns(&mut self, edns: Edns) {
+        if self.edns.is_none() {
+            self.header.additional_count += 1;
+        }
+        self.edns = Some(edns);
+    }
+
+    pub fn deserialize(
+        &mut self,
+        header: DnsHeader,
+        cursor: &mut io::Cursor<&[u8]>,
+        all_data: &[u8],
+    ) -> io::Result<()> {
+        self.header = header;
+        for _ in 0..self.header.query_count {
+            self.questions.push(DnsQuestion::deserialize_from(cursor)?);
+        }
+        for _ in 0..self.header.answer_count {
+            self.answers
+                .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+        }
+        for _ in 0..self.header.nameserver_count {
+            self.authorities
+                .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+        }
+        for _ in 0..self.header.additional_count {
+            // An OPT record always has the root as its owner name, so it starts with a zero byte
+            let position = cursor.position() as usize;
+            if all_data.get(position..position + 3) == Some(&[0, 0, QueryType::OPT.to_int() as u8]) {
+                self.edns = Some(Edns::deserialize_from(cursor)?);
+            } else {
+                self.additionals
+                    .push(DnsResourceRecord::deserialize_from(cursor, all_data)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn deserialize_from(
+        header: DnsHeader,
+        cursor: &mut io::Cursor<&[u8]>,
+        all_data: &[u8],
+    ) -> io::Result<DnsPacket> {
+        let mut result = DnsPacket::default();
+        result.deserialize(header, cursor, all_data)?;
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod packet_tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_compressed_name() {
+        // "example.com" at offset 0 followed by "www" and a pointer back to offset 0
+        let input = b"\x07example\x03com\x00\x03www\xc0\x00";
+        let mut cursor = io::Cursor::new(&input[..]);
+        cursor.set_position(13);
+        let output = deserialize_name_string(&mut cursor, input).unwrap();
+        assert_eq!(
+            "www.example.com",
+            output
+        );
+        assert_eq!(19, cursor.position());
+    }
+
+    #[test]
+    fn deserialize_name_rejects_pointer_loops() {
+        // A header's worth of padding, then a pointer to itself
+        let mut input = vec![0; 12];
+        input.extend_from_slice(b"\xc0\x0c");
+        let mut cursor = io::Cursor::new(&input[..]);
+        cursor.set_position(12);
+        assert!(deserialize_name(&mut cursor, &input).is_err());
+
+        // Names in record data are read from a copy, so only the pointers after the first are
+        // known to be going backwards
+        let message = b"\x03www\xc0\x06\xc0\x00";
+        let record_data = b"\xc0\x00";
+        let mut cursor = io::Cursor::new(&record_data[..]);
+        assert!(deserialize_name(&mut cursor, message).is_err());
+    }
+
+    #[test]
+    fn deserialize_name_rejects_long_names() {
+        let input = b"\x01a".repeat(128);
+        let mut input = input.to_vec();
+        input.push(0);
+        let mut cursor = io::Cursor::new(&input[..]);
+        assert!(deserialize_name(&mut cursor, &input).is_err());
+
+        let input = &input[2..];
+        let mut cursor = io::Cursor::new(input);
+        assert_eq!(253, deserialize_name(&mut cursor, input).unwrap().len());
+    }
+
+    #[test]
+    fn serialize_name_rejects_long_labels() {
+        let mut buffer = Vec::new();
+        assert!(serialize_name(&"a".repeat(64), &mut buffer).is_err());
+    }
+
+    #[test]
+    fn packet_roundtrip() {
+        let mut input = DnsPacket::default();
+        input.header.request_id = 0x1234;
+        input.header.recursion_desired = true;
+        input.header.query_count = 1;
+        input.questions.push(DnsQuestion {
+            domain_name: String::from("www.example.com"),
+            query_type: QueryType::AAAA,
+            query_class: DomainClass::Internet,
+        });
+        let mut data = Vec::new();
+        input.serialize(&mut data).unwrap();
+
+        let mut cursor = io::Cursor::new(&data[..]);
+        let header = DnsHeader::deserialize_from(&mut cursor).unwrap();
+        let output = DnsPacket::deserialize_from(header, &mut cursor, &data).unwrap();
+        assert_eq!(0x1234, output.header.request_id);
+        assert!(output.header.recursion_desired);
+        assert_eq!("www.example.com", output.questions[0].domain_name);
+        assert_eq!(QueryType::AAAA, output.questions[0].query_type);
+    }
+
+    #[test]
+    fn unknown_types_keep_their_data() {
+        // A CAA record, which has no type of its own here
+        let input = DnsResourceRecord {
+            domain_name: String::from("example.com"),
+            data_type: QueryType::from_int(257),
+            data_class: DomainClass::Internet,
+            ttl: 300,
+            data_length: 7,
+            data: b"\x00\x05issue".to_vec(),
+        };
+        let mut data = Vec::new();
+        input.serialize(&mut data).unwrap();
+
+        let mut cursor = io::Cursor::new(&data[..]);
+        let output = DnsResourceRecord::deserialize_from(&mut cursor, &data).unwrap();
+        assert_eq!(QueryType::Unknown(257), output.data_type);
+        assert_eq!(input.data, output.data);
+    }
+}
@@ -0,0 +1,690 @@
+use super::packet::{DnsHeader, DnsPacket};
+use super::resolver::DNS_PORT;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use std::{fmt, io};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+// https://www.tcpdump.org/linktypes.html
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+const IP_PROTOCOL_TCP: u8 = 6;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html section 4
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+fn truncated(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("Capture file is truncated in the middle of a {}", what),
+    )
+}
+
+fn read_u16(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(if big_endian {
+        BigEndian::read_u16(bytes)
+    } else {
+        LittleEndian::read_u16(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(if big_endian {
+        BigEndian::read_u32(bytes)
+    } else {
+        LittleEndian::read_u32(bytes)
+    })
+}
+
+pub struct Frame<'a> {
+    pub timestamp: Duration,
+    pub link_type: u32,
+    pub data: &'a [u8],
+}
+
+// Reads every frame out of a libpcap or pcapng capture, telling the two apart by their magic number
+pub fn read_frames(data: &[u8]) -> io::Result<Vec<Frame<'_>>> {
+    match read_u32(data, 0, false) {
+        Some(0xA1B2_C3D4) | Some(0xA1B2_3C4D) | Some(0xD4C3_B2A1) | Some(0x4D3C_B2A1) => {
+            read_pcap_frames(data)
+        }
+        Some(PCAPNG_SECTION_HEADER) => read_pcapng_frames(data),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a pcap or pcapng capture file",
+        )),
+    }
+}
+
+fn read_pcap_frames(data: &[u8]) -> io::Result<Vec<Frame<'_>>> {
+    const GLOBAL_HEADER_LEN: usize = 24;
+    const RECORD_HEADER_LEN: usize = 16;
+    let magic = read_u32(data, 0, false).unwrap();
+    let big_endian = (magic == 0xD4C3_B2A1) || (magic == 0x4D3C_B2A1);
+    let nanosecond = (magic == 0xA1B2_3C4D) || (magic == 0x4D3C_B2A1);
+    // The top bits of the link type field are used for other things (e.g FCS length)
+    let link_type = read_u32(data, 20, big_endian).ok_or_else(|| truncated("file header"))? & 0x0FFF_FFFF;
+
+    let mut result = Vec::new();
+    let mut offset = GLOBAL_HEADER_LEN;
+    while offset < data.len() {
+        let header = data
+            .get(offset..offset + RECORD_HEADER_LEN)
+            .ok_or_else(|| truncated("packet header"))?;
+        let seconds = read_u32(header, 0, big_endian).unwrap() as u64;
+        let fraction = read_u32(header, 4, big_endian).unwrap();
+        let captured_len = read_u32(header, 8, big_endian).unwrap() as usize;
+        let start = offset + RECORD_HEADER_LEN;
+        let frame_data = data
+            .get(start..start + captured_len)
+            .ok_or_else(|| truncated("packet"))?;
+        // A corrupt fraction can be over a second, and in microseconds that overflows a u32
+        let nanos = if nanosecond { fraction as u64 } else { fraction as u64 * 1000 };
+        result.push(Frame {
+            timestamp: Duration::from_secs(seconds) + Duration::from_nanos(nanos),
+            link_type,
+            data: frame_data,
+        });
+        offset = start + captured_len;
+    }
+    Ok(result)
+}
+
+struct PcapngInterface {
+    link_type: u32,
+    // Timestamps are in units of 1/units_per_second, which defaults to microseconds
+    units_per_second: u64,
+}
+
+fn read_pcapng_interface(body: &[u8], big_endian: bool) -> io::Result<PcapngInterface> {
+    let link_type = read_u16(body, 0, big_endian).ok_or_else(|| truncated("interface description"))? as u32;
+    let mut units_per_second = 1_000_000;
+    let mut offset = 8;
+    while let (Some(code), Some(len)) = (read_u16(body, offset, big_endian), read_u16(body, offset + 2, big_endian)) {
+        let len = len as usize;
+        if (code == PCAPNG_OPTION_TSRESOL) && (len == 1) {
+            let resolution = body.get(offset + 4).copied().unwrap_or(6);
+            let exponent = (resolution & 0x7F) as u32;
+            units_per_second = if resolution & 0x80 == 0 {
+                10u64.checked_pow(exponent)
+            } else {
+                2u64.checked_pow(exponent)
+            }
+            .unwrap_or(1_000_000);
+        }
+        if code == 0 {
+            break;
+        }
+        offset += 4 + ((len + 3) & !3);
+    }
+    Ok(PcapngInterface {
+        link_type,
+        units_per_second,
+    })
+}
+
+fn read_pcapng_frames(data: &[u8]) -> io::Result<Vec<Frame<'_>>> {
+    let mut result = Vec::new();
+    let mut interfaces: Vec<PcapngInterface> = Vec::new();
+    let mut big_endian = false;
+    let mut offset = 0;
+    while offset < data.len() {
+        let block_type = read_u32(data, offset, big_endian).ok_or_else(|| truncated("block header"))?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            // Each section can have a different byte order and starts with no interfaces
+            big_endian = match read_u32(data, offset + 8, false) {
+                Some(PCAPNG_BYTE_ORDER_MAGIC) => false,
+                Some(_) => true,
+                None => return Err(truncated("section header")),
+            };
+            interfaces.clear();
+        }
+        let block_len = read_u32(data, offset + 4, big_endian).ok_or_else(|| truncated("block header"))? as usize;
+        if block_len < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid pcapng block length {} at offset {}", block_len, offset),
+            ));
+        }
+        let body = data
+            .get(offset + 8..offset + block_len - 4)
+            .ok_or_else(|| truncated("block"))?;
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => interfaces.push(read_pcapng_interface(body, big_endian)?),
+            PCAPNG_ENHANCED_PACKET => {
+                let interface_id = read_u32(body, 0, big_endian).ok_or_else(|| truncated("packet block"))? as usize;
+                let interface = interfaces.get(interface_id).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Packet refers to unknown interface {}", interface_id),
+                    )
+                })?;
+                let timestamp_high = read_u32(body, 4, big_endian).ok_or_else(|| truncated("packet block"))? as u64;
+                let timestamp_low = read_u32(body, 8, big_endian).ok_or_else(|| truncated("packet block"))? as u64;
+                let captured_len = read_u32(body, 12, big_endian).ok_or_else(|| truncated("packet block"))? as usize;
+                let frame_data = body.get(20..20 + captured_len).ok_or_else(|| truncated("packet block"))?;
+                let timestamp = (timestamp_high << 32) | timestamp_low;
+                let units = interface.units_per_second;
+                let nanos = ((timestamp % units) as u128 * 1_000_000_000 / units as u128) as u32;
+                result.push(Frame {
+                    timestamp: Duration::new(timestamp / units, nanos),
+                    link_type: interface.link_type,
+                    data: frame_data,
+                });
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                // Simple packets have no timestamp and always belong to the first interface
+                if let Some(interface) = interfaces.first() {
+                    let original_len = read_u32(body, 0, big_endian).ok_or_else(|| truncated("packet block"))? as usize;
+                    let captured_len = original_len.min(body.len() - 4);
+                    result.push(Frame {
+                        timestamp: Duration::from_secs(0),
+                        link_type: interface.link_type,
+                        data: &body[4..4 + captured_len],
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += block_len;
+    }
+    Ok(result)
+}
+
+// Strips off the link layer header, returning the ethertype of the payload along with the payload
+fn link_payload(link_type: u32, data: &[u8]) -> Option<(u16, &[u8])> {
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = read_u16(data, offset, true)?;
+            while (ethertype == ETHERTYPE_VLAN) || (ethertype == ETHERTYPE_QINQ) {
+                offset += 4;
+                ethertype = read_u16(data, offset, true)?;
+            }
+            Some((ethertype, data.get(offset + 2..)?))
+        }
+        LINKTYPE_LINUX_SLL => Some((read_u16(data, 14, true)?, data.get(16..)?)),
+        LINKTYPE_LINUX_SLL2 => Some((read_u16(data, 0, true)?, data.get(20..)?)),
+        LINKTYPE_RAW => match data.first()? >> 4 {
+            4 => Some((ETHERTYPE_IPV4, data)),
+            6 => Some((ETHERTYPE_IPV6, data)),
+            _ => None,
+        },
+        LINKTYPE_NULL => {
+            // The address family is in the byte order of the machine that did the capture
+            let family = read_u32(data, 0, false)?;
+            let family = if family > 0xFFFF { family.swap_bytes() } else { family };
+            match family {
+                2 => Some((ETHERTYPE_IPV4, data.get(4..)?)),
+                24 | 28 | 30 => Some((ETHERTYPE_IPV6, data.get(4..)?)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Returns the source, destination, transport protocol and payload of an IP packet. Fragments are
+// skipped since DNS messages that need fragmentation are rare enough not to be worth reassembling.
+fn ip_payload(ethertype: u16, data: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match ethertype {
+        ETHERTYPE_IPV4 => {
+            const MIN_HEADER_LEN: usize = 20;
+            let header = data.get(..MIN_HEADER_LEN)?;
+            let header_len = ((header[0] & 0x0F) as usize) * 4;
+            if header_len < MIN_HEADER_LEN || header_len > data.len() {
+                return None;
+            }
+            let total_len = (read_u16(header, 2, true)? as usize).min(data.len());
+            let fragment = read_u16(header, 6, true)?;
+            if (fragment & 0x2000 != 0) || (fragment & 0x1FFF != 0) {
+                return None;
+            }
+            let source = Ipv4Addr::new(header[12], header[13], header[14], header[15]);
+            let destination = Ipv4Addr::new(header[16], header[17], header[18], header[19]);
+            Some((
+                IpAddr::V4(source),
+                IpAddr::V4(destination),
+                header[9],
+                data.get(header_len..total_len)?,
+            ))
+        }
+        ETHERTYPE_IPV6 => {
+            const HEADER_LEN: usize = 40;
+            let header = data.get(..HEADER_LEN)?;
+            let payload_len = read_u16(header, 4, true)? as usize;
+            let mut next_header = header[6];
+            let mut address_bytes = [0; 16];
+            address_bytes.copy_from_slice(&header[8..24]);
+            let source = Ipv6Addr::from(address_bytes);
+            address_bytes.copy_from_slice(&header[24..40]);
+            let destination = Ipv6Addr::from(address_bytes);
+
+            let mut payload = data.get(HEADER_LEN..(HEADER_LEN + payload_len).min(data.len()))?;
+            loop {
+                let extension_len = match next_header {
+                    0 | 43 | 60 => (*payload.get(1)? as usize + 1) * 8, // Hop-by-hop, routing, destination options
+                    51 => (*payload.get(1)? as usize + 2) * 4,         // Authentication header
+                    _ => break,
+                };
+                next_header = *payload.first()?;
+                payload = payload.get(extension_len..)?;
+            }
+            Some((IpAddr::V6(source), IpAddr::V6(destination), next_header, payload))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transport::Udp => write!(f, "UDP"),
+            Transport::Tcp => write!(f, "TCP"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CapturedMessage {
+    pub timestamp: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub transport: Transport,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct TcpStream {
+    next_sequence: Option<u32>,
+    buffer: Vec<u8>,
+    broken: bool,
+}
+
+// Pulls the DNS messages out of UDP datagrams and TCP streams to or from port 53. Over TCP each
+// message is preceded by its length (RFC 1035 section 4.2.2) and may be split across segments.
+pub fn extract_messages(frames: &[Frame]) -> Vec<CapturedMessage> {
+    let mut result = Vec::new();
+    let mut streams: HashMap<(SocketAddr, SocketAddr), TcpStream> = HashMap::new();
+    for frame in frames {
+        let (source_ip, destination_ip, protocol, payload) = match link_payload(frame.link_type, frame.data)
+            .and_then(|(ethertype, data)| ip_payload(ethertype, data))
+        {
+            Some(packet) => packet,
+            None => continue,
+        };
+        let (source_port, destination_port) = match (read_u16(payload, 0, true), read_u16(payload, 2, true)) {
+            (Some(source_port), Some(destination_port)) => (source_port, destination_port),
+            _ => continue,
+        };
+        if (source_port != DNS_PORT) && (destination_port != DNS_PORT) {
+            continue;
+        }
+        let source = SocketAddr::new(source_ip, source_port);
+        let destination = SocketAddr::new(destination_ip, destination_port);
+
+        match protocol {
+            IP_PROTOCOL_UDP => {
+                let udp_len = read_u16(payload, 4, true).map(|len| len as usize).unwrap_or(0);
+                if let Some(data) = payload.get(8..udp_len.min(payload.len())) {
+                    result.push(CapturedMessage {
+                        timestamp: frame.timestamp,
+                        source,
+                        destination,
+                        transport: Transport::Udp,
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            IP_PROTOCOL_TCP => {
+                const SYN: u8 = 0x02;
+                const RST: u8 = 0x04;
+                let (sequence, header_len, flags) = match (read_u32(payload, 4, true), payload.get(12), payload.get(13)) {
+                    (Some(sequence), Some(offset), Some(flags)) => (sequence, ((offset >> 4) as usize) * 4, *flags),
+                    _ => continue,
+                };
+                let segment = match payload.get(header_len..) {
+                    Some(segment) => segment,
+                    None => continue,
+                };
+                let stream = streams.entry((source, destination)).or_default();
+                if flags & (SYN | RST) != 0 {
+                    *stream = TcpStream::default();
+                    if flags & SYN != 0 {
+                        stream.next_sequence = Some(sequence.wrapping_add(1));
+                    }
+                    continue;
+                }
+                if segment.is_empty() || stream.broken {
+                    continue;
+                }
+
+                let expected = *stream.next_sequence.get_or_insert(sequence);
+                if sequence != expected {
+                    // Retransmissions of data we already have are harmless, but if something
+                    // was missed then there's no way to find the start of the next message.
+                    if (expected.wrapping_sub(sequence) as i32) <= 0 {
+                        stream.broken = true;
+                        stream.buffer.clear();
+                    }
+                    continue;
+                }
+                stream.next_sequence = Some(sequence.wrapping_add(segment.len() as u32));
+                stream.buffer.extend_from_slice(segment);
+                while let Some(len) = read_u16(&stream.buffer, 0, true) {
+                    let len = len as usize;
+                    if stream.buffer.len() < len + 2 {
+                        break;
+                    }
+                    let data: Vec<u8> = stream.buffer.drain(..len + 2).skip(2).collect();
+                    result.push(CapturedMessage {
+                        timestamp: frame.timestamp,
+                        source,
+                        destination,
+                        transport: Transport::Tcp,
+                        data,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+pub struct Transaction {
+    pub query: Option<(CapturedMessage, DnsPacket)>,
+    pub response: Option<(CapturedMessage, DnsPacket)>,
+}
+
+impl Transaction {
+    pub fn latency(&self) -> Option<Duration> {
+        match (&self.query, &self.response) {
+            (Some((query, _)), Some((response, _))) => response.timestamp.checked_sub(query.timestamp),
+            _ => None,
+        }
+    }
+}
+
+pub struct CaptureAnalysis {
+    pub transactions: Vec<Transaction>,
+    pub malformed: Vec<(CapturedMessage, io::Error)>,
+}
+
+// Responses are matched to queries using the request ID along with the addresses and ports at
+// either end, since IDs alone are frequently reused by different clients.
+pub fn pair_transactions(messages: Vec<CapturedMessage>) -> CaptureAnalysis {
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut malformed = Vec::new();
+    let mut pending: HashMap<(u16, SocketAddr, SocketAddr, Transport), usize> = HashMap::new();
+    for message in messages {
+        let mut cursor = io::Cursor::new(&message.data[..]);
+        let packet = match DnsHeader::deserialize_from(&mut cursor)
+            .and_then(|header| DnsPacket::deserialize_from(header, &mut cursor, &message.data))
+        {
+            Ok(packet) => packet,
+            Err(e) => {
+                malformed.push((message, e));
+                continue;
+            }
+        };
+
+        let request_id = packet.header.request_id;
+        if packet.header.is_response {
+            let key = (request_id, message.destination, message.source, message.transport);
+            match pending.remove(&key) {
+                Some(index) => transactions[index].response = Some((message, packet)),
+                None => transactions.push(Transaction {
+                    query: None,
+                    response: Some((message, packet)),
+                }),
+            }
+        } else {
+            let key = (request_id, message.source, message.destination, message.transport);
+            pending.insert(key, transactions.len());
+            transactions.push(Transaction {
+                query: Some((message, packet)),
+                response: None,
+            });
+        }
+    }
+    CaptureAnalysis {
+        transactions,
+        malformed,
+    }
+}
+
+pub fn analyse_capture(data: &[u8]) -> io::Result<CaptureAnalysis> {
+    let frames = read_frames(data)?;
+    Ok(pair_transactions(extract_messages(&frames)))
+}
+
+#[cfg(test)]
+mod pcap_tests {
+    use super::*;
+    use crate::dns::packet::{DnsQuestion, DomainClass, QueryType, ResponseCode};
+
+    fn dns_message(request_id: u16, is_response: bool, rcode: ResponseCode) -> Vec<u8> {
+        let mut packet = DnsPacket::default();
+        packet.header.request_id = request_id;
+        packet.header.is_response = is_response;
+        packet.header.rcode = rcode;
+        packet.header.query_count = 1;
+        packet.questions.push(DnsQuestion {
+            domain_name: String::from("www.example.com"),
+            query_type: QueryType::A,
+            query_class: DomainClass::Internet,
+        });
+        let mut data = Vec::new();
+        packet.serialize(&mut data).unwrap();
+        data
+    }
+
+    fn ipv4_packet(source: [u8; 4], destination: [u8; 4], protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut result = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, protocol, 0, 0];
+        result[2..4].copy_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+        result.extend_from_slice(&source);
+        result.extend_from_slice(&destination);
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn ipv6_packet(source: Ipv6Addr, destination: Ipv6Addr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut result = vec![0x60, 0, 0, 0];
+        result.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        result.extend_from_slice(&[protocol, 64]);
+        result.extend_from_slice(&source.octets());
+        result.extend_from_slice(&destination.octets());
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn udp_segment(source_port: u16, destination_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&source_port.to_be_bytes());
+        result.extend_from_slice(&destination_port.to_be_bytes());
+        result.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        result.extend_from_slice(&[0, 0]);
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn tcp_segment(source_port: u16, destination_port: u16, sequence: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&source_port.to_be_bytes());
+        result.extend_from_slice(&destination_port.to_be_bytes());
+        result.extend_from_slice(&sequence.to_be_bytes());
+        result.extend_from_slice(&[0, 0, 0, 0, 5 << 4, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn ethernet_frame(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut result = vec![0; 12];
+        result.extend_from_slice(&ethertype.to_be_bytes());
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn linux_cooked_frame(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut result = vec![0; 14];
+        result.extend_from_slice(&ethertype.to_be_bytes());
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn pcap_file(link_type: u32, frames: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut result = 0xA1B2_C3D4u32.to_le_bytes().to_vec();
+        result.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0, 0]);
+        result.extend_from_slice(&link_type.to_le_bytes());
+        for (seconds, micros, frame) in frames {
+            for value in &[*seconds, *micros, frame.len() as u32, frame.len() as u32] {
+                result.extend_from_slice(&value.to_le_bytes());
+            }
+            result.extend_from_slice(frame);
+        }
+        result
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut padded = body.to_vec();
+        padded.resize((body.len() + 3) & !3, 0);
+        let block_len = (padded.len() + 12) as u32;
+        let mut result = Vec::new();
+        result.extend_from_slice(&block_type.to_le_bytes());
+        result.extend_from_slice(&block_len.to_le_bytes());
+        result.extend_from_slice(&padded);
+        result.extend_from_slice(&block_len.to_le_bytes());
+        result
+    }
+
+    // Uses nanosecond timestamps to check that the interface's resolution option is respected
+    fn pcapng_file(link_type: u16, frames: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut section_header = PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().to_vec();
+        section_header.extend_from_slice(&[1, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let mut interface = link_type.to_le_bytes().to_vec();
+        interface.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0, 0]);
+        interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut result = pcapng_block(PCAPNG_SECTION_HEADER, &section_header);
+        result.extend(pcapng_block(PCAPNG_INTERFACE_DESCRIPTION, &interface));
+        for (nanos, frame) in frames {
+            let mut body = Vec::new();
+            for value in &[0, (nanos >> 32) as u32, *nanos as u32, frame.len() as u32, frame.len() as u32] {
+                body.extend_from_slice(&value.to_le_bytes());
+            }
+            body.extend_from_slice(frame);
+            result.extend(pcapng_block(PCAPNG_ENHANCED_PACKET, &body));
+        }
+        result
+    }
+
+    #[test]
+    fn pcap_udp_ipv4_transactions_are_paired() {
+        let client = [192, 0, 2, 10];
+        let server = [192, 0, 2, 53];
+        let frames = vec![
+            (1_700_000_000, 100_000, ethernet_frame(ETHERTYPE_IPV4, &ipv4_packet(client, server, IP_PROTOCOL_UDP, &udp_segment(40000, 53, &dns_message(1, false, ResponseCode::NoError))))),
+            // Same ID from a different client port, so it mustn't be paired with the first query
+            (1_700_000_000, 200_000, ethernet_frame(ETHERTYPE_IPV4, &ipv4_packet(client, server, IP_PROTOCOL_UDP, &udp_segment(40001, 53, &dns_message(1, false, ResponseCode::NoError))))),
+            (1_700_000_000, 112_500, ethernet_frame(ETHERTYPE_IPV4, &ipv4_packet(server, client, IP_PROTOCOL_UDP, &udp_segment(53, 40000, &dns_message(1, true, ResponseCode::NameError))))),
+        ];
+        let output = analyse_capture(&pcap_file(LINKTYPE_ETHERNET, &frames)).unwrap();
+        assert_eq!(2, output.transactions.len());
+        assert_eq!(Some(Duration::from_micros(12_500)), output.transactions[0].latency());
+        let (response, packet) = output.transactions[0].response.as_ref().unwrap();
+        assert_eq!(ResponseCode::NameError, packet.header.rcode);
+        assert_eq!("192.0.2.53:53".parse::<SocketAddr>().unwrap(), response.source);
+        assert!(output.transactions[1].response.is_none());
+    }
+
+    #[test]
+    fn pcapng_linux_cooked_ipv6_is_read() {
+        let client: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let server: Ipv6Addr = "2001:db8::53".parse().unwrap();
+        let frames = vec![
+            (1_700_000_000_000_000_000, linux_cooked_frame(ETHERTYPE_IPV6, &ipv6_packet(client, server, IP_PROTOCOL_UDP, &udp_segment(40000, 53, &dns_message(7, false, ResponseCode::NoError))))),
+            (1_700_000_000_000_250_000, linux_cooked_frame(ETHERTYPE_IPV6, &ipv6_packet(server, client, IP_PROTOCOL_UDP, &udp_segment(53, 40000, &dns_message(7, true, ResponseCode::NoError))))),
+            (1_700_000_000_000_300_000, linux_cooked_frame(ETHERTYPE_IPV6, &ipv6_packet(server, client, IP_PROTOCOL_UDP, &udp_segment(53, 40000, b"\x00\x07")))),
+        ];
+        let output = analyse_capture(&pcapng_file(LINKTYPE_LINUX_SLL as u16, &frames)).unwrap();
+        assert_eq!(1, output.transactions.len());
+        assert_eq!(Some(Duration::from_micros(250)), output.transactions[0].latency());
+        assert_eq!(1, output.malformed.len());
+    }
+
+    #[test]
+    fn tcp_messages_are_reassembled() {
+        let client = [192, 0, 2, 10];
+        let server = [192, 0, 2, 53];
+        let mut query = (dns_message(9, false, ResponseCode::NoError).len() as u16).to_be_bytes().to_vec();
+        query.extend(dns_message(9, false, ResponseCode::NoError));
+        let mut response = (dns_message(9, true, ResponseCode::NoError).len() as u16).to_be_bytes().to_vec();
+        response.extend(dns_message(9, true, ResponseCode::NoError));
+
+        let tcp = |source: [u8; 4], destination: [u8; 4], ports: (u16, u16), sequence: u32, flags: u8, payload: &[u8]| {
+            ethernet_frame(ETHERTYPE_IPV4, &ipv4_packet(source, destination, IP_PROTOCOL_TCP, &tcp_segment(ports.0, ports.1, sequence, flags, payload)))
+        };
+        let frames = vec![
+            (1, 0, tcp(client, server, (40000, 53), 1000, 0x02, &[])),
+            (1, 10, tcp(client, server, (40000, 53), 1001, 0x18, &query[..5])),
+            // A retransmission of the first segment shouldn't be added to the stream again
+            (1, 20, tcp(client, server, (40000, 53), 1001, 0x18, &query[..5])),
+            (1, 30, tcp(client, server, (40000, 53), 1006, 0x18, &query[5..])),
+            (1, 1030, tcp(server, client, (53, 40000), 5000, 0x18, &response)),
+        ];
+        let output = analyse_capture(&pcap_file(LINKTYPE_ETHERNET, &frames)).unwrap();
+        assert!(output.malformed.is_empty());
+        assert_eq!(1, output.transactions.len());
+        let (query_message, _) = output.transactions[0].query.as_ref().unwrap();
+        assert_eq!(Transport::Tcp, query_message.transport);
+        assert_eq!(Some(Duration::from_micros(1000)), output.transactions[0].latency());
+    }
+
+    #[test]
+    fn truncated_frames_are_skipped() {
+        let client = [192, 0, 2, 10];
+        let server = [192, 0, 2, 53];
+        let query = ipv4_packet(client, server, IP_PROTOCOL_UDP, &udp_segment(40000, 53, &dns_message(3, false, ResponseCode::NoError)));
+        let frames = vec![
+            (1, 0, query[..10].to_vec()),
+            // A header length that runs past the end of the frame
+            (1, 10, vec![0x4F; 20]),
+            // More than a second's worth of microseconds
+            (1, 5_000_000, query),
+        ];
+        let output = analyse_capture(&pcap_file(LINKTYPE_RAW, &frames)).unwrap();
+        assert!(output.malformed.is_empty());
+        assert_eq!(1, output.transactions.len());
+    }
+
+    #[test]
+    fn unknown_file_format_is_rejected() {
+        assert!(analyse_capture(b"not a capture file").is_err());
+    }
+}
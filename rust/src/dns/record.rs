@@ -0,0 +1,547 @@
+use super::packet::{serialize_name, deserialize_name_string, DnsResourceRecord, DomainClass, QueryType};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::{fmt, io};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+    NS(String),
+    TXT(Vec<Vec<u8>>),
+    PTR(String),
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    SOA {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+}
+
+impl RecordData {
+    pub fn deserialize_from(record: &DnsResourceRecord, all_data: &[u8]) -> io::Result<RecordData> {
+        let invalid = |what: &str, e: io::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse {} from {:?} record: {}",
+                    what, record.data_type, e
+                ),
+            )
+        };
+
+        let mut cursor = io::Cursor::new(&record.data[..]);
+        match record.data_type {
+            QueryType::A => {
+                const EXPECTED_LEN: usize = 4;
+                if record.data.len() != EXPECTED_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Response for data type A is expected to contain exactly {} bytes and instead contained {} bytes", EXPECTED_LEN, record.data.len()),
+                    ));
+                }
+                Ok(RecordData::A(Ipv4Addr::new(
+                    record.data[0],
+                    record.data[1],
+                    record.data[2],
+                    record.data[3],
+                )))
+            }
+            QueryType::CNAME => {
+                let name = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("canonical name", e))?;
+                Ok(RecordData::CNAME(name))
+            }
+            QueryType::MX => {
+                const MIN_LEN: usize = 3;
+                if record.data.len() < MIN_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Response for data type MX is expected to contain at least {} bytes and instead contained {} bytes", MIN_LEN, record.data.len()),
+                    ));
+                }
+                let preference = cursor.read_u16::<BigEndian>()?;
+                let exchange = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("exchange name", e))?;
+                Ok(RecordData::MX {
+                    preference,
+                    exchange,
+                })
+            }
+            QueryType::NS => {
+                let name = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("authoritative name-server", e))?;
+                Ok(RecordData::NS(name))
+            }
+            QueryType::TXT => {
+                // NOTE: TXT data is one or more <character-string>s, each of which can be at most 255 bytes
+                //       long. Anything longer (e.g DKIM keys) gets split across multiple strings that
+                //       should be concatenated back together (RFC 7208 section 3.3, RFC 6376 section 3.6.2.2).
+                let strings = super::parse_character_strings(&record.data)
+                    .map_err(|e| invalid("text data", e))?;
+                Ok(RecordData::TXT(strings))
+            }
+            QueryType::PTR => {
+                let name = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("domain name", e))?;
+                Ok(RecordData::PTR(name))
+            }
+            QueryType::SRV => {
+                // https://tools.ietf.org/html/rfc2782
+                const MIN_LEN: usize = 7;
+                if record.data.len() < MIN_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Response for data type SRV is expected to contain at least {} bytes and instead contained {} bytes", MIN_LEN, record.data.len()),
+                    ));
+                }
+                let priority = cursor.read_u16::<BigEndian>()?;
+                let weight = cursor.read_u16::<BigEndian>()?;
+                let port = cursor.read_u16::<BigEndian>()?;
+                let target = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("target name", e))?;
+                Ok(RecordData::SRV {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                })
+            }
+            QueryType::SOA => {
+                const MIN_LEN: usize = 3;
+                if record.data.len() < MIN_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Response for data type SOA is expected to contain at least {} bytes and instead contained {} bytes", MIN_LEN, record.data.len()),
+                    ));
+                }
+                let mname = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("origin name", e))?;
+                let rname = deserialize_name_string(&mut cursor, all_data)
+                    .map_err(|e| invalid("responsible mailbox", e))?;
+                Ok(RecordData::SOA {
+                    mname,
+                    rname,
+                    serial: cursor.read_u32::<BigEndian>()?,
+                    refresh: cursor.read_u32::<BigEndian>()?,
+                    retry: cursor.read_u32::<BigEndian>()?,
+                    expire: cursor.read_u32::<BigEndian>()?,
+                    minimum: cursor.read_u32::<BigEndian>()?,
+                })
+            }
+            QueryType::AAAA => {
+                // https://tools.ietf.org/html/rfc3596
+                const EXPECTED_LEN: usize = 16;
+                if record.data.len() != EXPECTED_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Response for data type AAAA is expected to contain exactly {} bytes and instead contained {} bytes", EXPECTED_LEN, record.data.len()),
+                    ));
+                }
+                let mut octets = [0; EXPECTED_LEN];
+                octets.copy_from_slice(&record.data);
+                Ok(RecordData::AAAA(Ipv6Addr::from(octets)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported data type: {:?}", record.data_type),
+            )),
+        }
+    }
+
+    pub fn data_type(&self) -> QueryType {
+        match self {
+            RecordData::A(_) => QueryType::A,
+            RecordData::AAAA(_) => QueryType::AAAA,
+            RecordData::CNAME(_) => QueryType::CNAME,
+            RecordData::MX { .. } => QueryType::MX,
+            RecordData::NS(_) => QueryType::NS,
+            RecordData::TXT(_) => QueryType::TXT,
+            RecordData::PTR(_) => QueryType::PTR,
+            RecordData::SRV { .. } => QueryType::SRV,
+            RecordData::SOA { .. } => QueryType::SOA,
+        }
+    }
+
+    pub fn serialize(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            RecordData::A(addr) => buffer.extend_from_slice(&addr.octets()),
+            RecordData::AAAA(addr) => buffer.extend_from_slice(&addr.octets()),
+            RecordData::CNAME(name) | RecordData::NS(name) | RecordData::PTR(name) => {
+                serialize_name(name, buffer)?
+            }
+            RecordData::MX {
+                preference,
+                exchange,
+            } => {
+                buffer.write_u16::<BigEndian>(*preference)?;
+                serialize_name(exchange, buffer)?;
+            }
+            RecordData::TXT(strings) => {
+                for string in strings {
+                    if string.len() > super::MAX_CHARACTER_STRING_LENGTH {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "TXT string is longer than {} bytes",
+                                super::MAX_CHARACTER_STRING_LENGTH
+                            ),
+                        ));
+                    }
+                    buffer.write_u8(string.len() as u8)?;
+                    buffer.extend_from_slice(string);
+                }
+            }
+            RecordData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buffer.write_u16::<BigEndian>(*priority)?;
+                buffer.write_u16::<BigEndian>(*weight)?;
+                buffer.write_u16::<BigEndian>(*port)?;
+                serialize_name(target, buffer)?;
+            }
+            RecordData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                serialize_name(mname, buffer)?;
+                serialize_name(rname, buffer)?;
+                buffer.write_u32::<BigEndian>(*serial)?;
+                buffer.write_u32::<BigEndian>(*refresh)?;
+                buffer.write_u32::<BigEndian>(*retry)?;
+                buffer.write_u32::<BigEndian>(*expire)?;
+                buffer.write_u32::<BigEndian>(*minimum)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Long text is split into as many <character-string>s as necessary, see RFC 7208 section 3.3
+    pub fn txt_from_str(text: &str) -> RecordData {
+        let strings = text
+            .as_bytes()
+            .chunks(super::MAX_CHARACTER_STRING_LENGTH)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<Vec<u8>>>();
+        if strings.is_empty() {
+            RecordData::TXT(vec![Vec::new()])
+        } else {
+            RecordData::TXT(strings)
+        }
+    }
+
+    // Parses record data given in the same form that it is displayed, e.g "10 mx1.example.com" for MX
+    pub fn parse(data_type: QueryType, text: &str) -> io::Result<RecordData> {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid {} for {:?} record: {}", what, data_type, text),
+            )
+        };
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        match data_type {
+            QueryType::A => Ok(RecordData::A(text.trim().parse().map_err(|_| invalid("IPv4 address"))?)),
+            QueryType::AAAA => Ok(RecordData::AAAA(text.trim().parse().map_err(|_| invalid("IPv6 address"))?)),
+            QueryType::CNAME | QueryType::NS | QueryType::PTR => {
+                if fields.len() != 1 {
+                    return Err(invalid("domain name"));
+                }
+                let name = String::from(fields[0].trim_end_matches('.'));
+                Ok(match data_type {
+                    QueryType::CNAME => RecordData::CNAME(name),
+                    QueryType::NS => RecordData::NS(name),
+                    _ => RecordData::PTR(name),
+                })
+            }
+            QueryType::MX => {
+                if fields.len() != 2 {
+                    return Err(invalid("preference and exchange"));
+                }
+                Ok(RecordData::MX {
+                    preference: fields[0].parse().map_err(|_| invalid("preference"))?,
+                    exchange: String::from(fields[1].trim_end_matches('.')),
+                })
+            }
+            QueryType::TXT => {
+                let trimmed = text.trim();
+                let unquoted = if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+                    &trimmed[1..trimmed.len() - 1]
+                } else {
+                    trimmed
+                };
+                Ok(RecordData::txt_from_str(unquoted))
+            }
+            QueryType::SRV => {
+                if fields.len() != 4 {
+                    return Err(invalid("SRV fields (PRIORITY WEIGHT PORT TARGET)"));
+                }
+                let number = |index: usize| fields[index].parse::<u16>().map_err(|_| invalid("SRV number"));
+                Ok(RecordData::SRV {
+                    priority: number(0)?,
+                    weight: number(1)?,
+                    port: number(2)?,
+                    target: String::from(fields[3].trim_end_matches('.')),
+                })
+            }
+            QueryType::SOA => {
+                if fields.len() != 7 {
+                    return Err(invalid("SOA fields (MNAME RNAME SERIAL REFRESH RETRY EXPIRE MINIMUM)"));
+                }
+                let number = |index: usize| fields[index].parse::<u32>().map_err(|_| invalid("SOA number"));
+                Ok(RecordData::SOA {
+                    mname: String::from(fields[0].trim_end_matches('.')),
+                    rname: String::from(fields[1].trim_end_matches('.')),
+                    serial: number(2)?,
+                    refresh: number(3)?,
+                    retry: number(4)?,
+                    expire: number(5)?,
+                    minimum: number(6)?,
+                })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported data type: {:?}", data_type),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordData::A(addr) => write!(f, "{}", addr),
+            RecordData::AAAA(addr) => write!(f, "{}", addr),
+            RecordData::CNAME(name) => write!(f, "{}", name),
+            RecordData::MX { exchange, .. } => write!(f, "{}", exchange),
+            RecordData::NS(name) => write!(f, "{}", name),
+            RecordData::TXT(strings) => write!(
+                f,
+                "{}",
+                super::escape_character_string(&strings.concat())
+            ),
+            RecordData::PTR(name) => write!(f, "{}", name),
+            RecordData::SRV { port, target, .. } => write!(f, "{}:{}", target, port),
+            RecordData::SOA { mname, .. } => write!(f, "{}", mname),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub domain_name: String,
+    pub data_type: QueryType,
+    pub data: RecordData,
+    pub ttl: u32,
+}
+
+impl Record {
+    pub fn new(domain_name: &str, data: RecordData, ttl: u32) -> Record {
+        Record {
+            domain_name: String::from(domain_name),
+            data_type: data.data_type(),
+            data,
+            ttl,
+        }
+    }
+
+    pub fn deserialize_from(record: &DnsResourceRecord, all_data: &[u8]) -> io::Result<Record> {
+        Ok(Record {
+            domain_name: record.domain_name.clone(),
+            data_type: record.data_type,
+            data: RecordData::deserialize_from(record, all_data)?,
+            ttl: record.ttl,
+        })
+    }
+
+    pub fn to_resource_record(&self) -> io::Result<DnsResourceRecord> {
+        let mut data = Vec::new();
+        self.data.serialize(&mut data)?;
+        Ok(DnsResourceRecord {
+            domain_name: self.domain_name.clone(),
+            data_type: self.data_type,
+            data_class: DomainClass::Internet,
+            ttl: self.ttl,
+            data_length: data.len() as u16,
+            data,
+        })
+    }
+
+    pub fn is_same_record(&self, other: &Record) -> bool {
+        self.is_same_rrset(other) && (self.data == other.data)
+    }
+
+    pub fn is_same_rrset(&self, other: &Record) -> bool {
+        (self.data_type == other.data_type)
+            && self.domain_name.eq_ignore_ascii_case(&other.domain_name)
+    }
+}
+
+// How a record differs between two answers to the same query, e.g. in watch mode
+#[derive(Debug, PartialEq)]
+pub enum RecordChange<'a> {
+    Unchanged(&'a Record),
+    Added(&'a Record),
+    Removed(&'a Record),
+    Changed { old: &'a Record, new: &'a Record },
+}
+
+// Returns the current records in order followed by the ones that were removed.
+// NOTE: A record is only reported as "changed" if it is the only record in its RRset that
+//       disappeared and it was replaced by exactly one new record. Anything more complicated
+//       than that is shown as separate additions and removals.
+pub fn diff_records<'a>(previous: &'a [Record], current: &'a [Record]) -> Vec<RecordChange<'a>> {
+    let added: Vec<&Record> = current
+        .iter()
+        .filter(|c| !previous.iter().any(|p| p.is_same_record(c)))
+        .collect();
+    let removed: Vec<&Record> = previous
+        .iter()
+        .filter(|p| !current.iter().any(|c| c.is_same_record(p)))
+        .collect();
+    let is_changed = |record: &Record| {
+        (added.iter().filter(|a| a.is_same_rrset(record)).count() == 1)
+            && (removed.iter().filter(|r| r.is_same_rrset(record)).count() == 1)
+    };
+
+    let mut result = Vec::new();
+    for record in current {
+        if !added.iter().any(|a| a.is_same_record(record)) {
+            result.push(RecordChange::Unchanged(record));
+        } else if is_changed(record) {
+            let old = removed.iter().find(|r| r.is_same_rrset(record)).unwrap();
+            result.push(RecordChange::Changed { old, new: record });
+        } else {
+            result.push(RecordChange::Added(record));
+        }
+    }
+    for record in &removed {
+        if !is_changed(record) {
+            result.push(RecordChange::Removed(record));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod record_tests {
+    use super::*;
+
+    fn roundtrip(record: &Record) -> Record {
+        let mut data = Vec::new();
+        record.to_resource_record().unwrap().serialize(&mut data).unwrap();
+        let mut cursor = io::Cursor::new(&data[..]);
+        let resource_record = DnsResourceRecord::deserialize_from(&mut cursor, &data).unwrap();
+        Record::deserialize_from(&resource_record, &data).unwrap()
+    }
+
+    #[test]
+    fn mx_record_roundtrip() {
+        let input = Record::new("example.com", RecordData::MX { preference: 10, exchange: String::from("mx1.example.com") }, 300);
+        let output = roundtrip(&input);
+        assert_eq!(
+            input,
+            output
+        );
+    }
+
+    #[test]
+    fn srv_record_roundtrip() {
+        let input = Record::new("_ipp._tcp.example.com", RecordData::parse(QueryType::SRV, "0 5 631 printer.example.com.").unwrap(), 120);
+        let output = roundtrip(&input);
+        assert_eq!(
+            input,
+            output
+        );
+        assert_eq!("printer.example.com:631", output.data.to_string());
+    }
+
+    #[test]
+    fn soa_record_roundtrip() {
+        let input = Record::new(
+            "example.com",
+            RecordData::SOA {
+                mname: String::from("ns1.example.com"),
+                rname: String::from("hostmaster.example.com"),
+                serial: 2024010101,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 60,
+            },
+            3600,
+        );
+        let output = roundtrip(&input);
+        assert_eq!(
+            input,
+            output
+        );
+    }
+
+    #[test]
+    fn parse_record_data() {
+        assert_eq!(
+            RecordData::MX { preference: 10, exchange: String::from("mx1.example.com") },
+            RecordData::parse(QueryType::MX, "10 mx1.example.com.").unwrap()
+        );
+        assert_eq!(
+            RecordData::TXT(vec![b"v=spf1 -all".to_vec()]),
+            RecordData::parse(QueryType::TXT, "\"v=spf1 -all\"").unwrap()
+        );
+        assert!(RecordData::parse(QueryType::A, "192.0.2.300").is_err());
+    }
+
+    #[test]
+    fn long_txt_record_is_split() {
+        let input = RecordData::txt_from_str(&"a".repeat(300));
+        assert_eq!(
+            RecordData::TXT(vec![vec![b'a'; 255], vec![b'a'; 45]]),
+            input
+        );
+        assert_eq!(
+            RecordData::TXT(vec![Vec::new()]),
+            RecordData::txt_from_str("")
+        );
+    }
+
+    #[test]
+    fn diff_records_finds_changes() {
+        let a = |address: &str| Record::new("example.com", RecordData::A(address.parse().unwrap()), 60);
+        let mx = |exchange: &str| Record::new("example.com", RecordData::MX { preference: 10, exchange: String::from(exchange) }, 60);
+        let previous = vec![a("192.0.2.1"), a("192.0.2.2"), mx("mx1.example.com"), mx("mx2.example.com")];
+        let current = vec![a("192.0.2.1"), a("192.0.2.3"), mx("mx3.example.com")];
+        assert_eq!(
+            vec![
+                RecordChange::Unchanged(&current[0]),
+                RecordChange::Changed { old: &previous[1], new: &current[1] },
+                // Two MX records were replaced by one, which is too complicated to call a change
+                RecordChange::Added(&current[2]),
+                RecordChange::Removed(&previous[2]),
+                RecordChange::Removed(&previous[3]),
+            ],
+            diff_records(&previous, &current)
+        );
+    }
+}
@@ -0,0 +1,1048 @@
+use super::edns::{pad_to_block_size, Edns, EdnsOption, QUERY_PADDING_BLOCK_SIZE};
+use super::packet::{DnsHeader, DnsPacket, DnsQuestion, DomainClass, QueryType, ResponseCode};
+use super::record::{Record, RecordData};
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{cmp, io};
+
+pub const DNS_PORT: u16 = 53;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// RFC 1035 section 4.2.1: Messages carried by UDP are restricted to 512 bytes
+const MAX_UDP_MESSAGE_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub struct DnsResponse {
+    pub request_id: u16,
+    pub packet: DnsPacket,
+    pub data: Vec<u8>,
+    pub source: SocketAddr,
+    pub roundtrip: Duration,
+    // Why each packet that arrived before this one was ignored (e.g spoofing attempts)
+    pub rejected: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    pub edns: Option<Edns>,
+    // DNS 0x20 (draft-vixie-dnsext-dns0x20): Randomise the case of the letters in the query name
+    // and only accept responses that echo it back exactly, which makes spoofed answers harder to
+    // forge since there are more bits for an attacker to guess.
+    pub randomise_case: bool,
+}
+
+pub fn randomise_case(name: &str) -> String {
+    let mut rng = rand::thread_rng();
+    name.chars()
+        .map(|c| {
+            if rng.gen::<bool>() {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+pub fn reverse_domain_name(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4addr) => format!(
+            "{}.{}.{}.{}.in-addr.arpa",
+            v4addr.octets()[3],
+            v4addr.octets()[2],
+            v4addr.octets()[1],
+            v4addr.octets()[0]
+        ),
+        IpAddr::V6(v6addr) => {
+            // https://tools.ietf.org/html/rfc3596#section-2.5
+            let mut result = String::with_capacity(72);
+            for byte in v6addr.octets().iter().rev() {
+                result.push_str(&format!("{:x}.{:x}.", byte & 0xF, byte >> 4));
+            }
+            result.push_str("ip6.arpa");
+            result
+        }
+    }
+}
+
+// NOTE: Enumerating anything larger than this is almost certainly a mistake (e.g a typo in the
+//       prefix length) and would take a very long time to query.
+pub const MAX_REVERSE_RANGE_SIZE: u128 = 65536;
+
+fn address_to_int(address: IpAddr) -> (u128, u32) {
+    match address {
+        IpAddr::V4(v4addr) => (u32::from(v4addr) as u128, 32),
+        IpAddr::V6(v6addr) => (u128::from(v6addr), 128),
+    }
+}
+
+fn int_to_address(value: u128, bits: u32) -> IpAddr {
+    if bits == 32 {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(value))
+    }
+}
+
+fn parse_address(input: &str) -> Result<IpAddr, String> {
+    input
+        .trim()
+        .parse::<IpAddr>()
+        .map_err(|e| format!("Failed to parse IP address {}: {}", input, e))
+}
+
+// Expands a single address, a CIDR block (e.g 192.0.2.0/24) or a range of addresses (e.g
+// 192.0.2.10-192.0.2.20) into the addresses it contains
+pub fn parse_address_range(input: &str) -> Result<Vec<IpAddr>, String> {
+    let (first, last, bits) = if let Some(index) = input.find('/') {
+        let (value, bits) = address_to_int(parse_address(&input[..index])?);
+        let prefix_len = match input[index + 1..].parse::<u32>() {
+            Ok(prefix_len) if prefix_len <= bits => prefix_len,
+            _ => {
+                return Err(format!(
+                    "Invalid prefix length in {}, it must be a number from 0 to {}",
+                    input, bits
+                ))
+            }
+        };
+        let host_bits = bits - prefix_len;
+        let host_mask = if host_bits == 128 {
+            u128::MAX
+        } else {
+            (1u128 << host_bits) - 1
+        };
+        (value & !host_mask, value | host_mask, bits)
+    } else if let Some(index) = input.find('-') {
+        let (first, first_bits) = address_to_int(parse_address(&input[..index])?);
+        let (last, last_bits) = address_to_int(parse_address(&input[index + 1..])?);
+        if first_bits != last_bits {
+            return Err(format!(
+                "The start and end of the range {} are not the same type of address",
+                input
+            ));
+        }
+        if first > last {
+            return Err(format!(
+                "The start of the range {} is after the end of the range",
+                input
+            ));
+        }
+        (first, last, first_bits)
+    } else {
+        let (value, bits) = address_to_int(parse_address(input)?);
+        (value, value, bits)
+    };
+
+    if last - first >= MAX_REVERSE_RANGE_SIZE {
+        return Err(format!(
+            "{} contains more than {} addresses",
+            input, MAX_REVERSE_RANGE_SIZE
+        ));
+    }
+    Ok((first..=last)
+        .map(|value| int_to_address(value, bits))
+        .collect())
+}
+
+pub const MAX_CNAME_CHAIN_LENGTH: usize = 16;
+
+#[derive(Debug)]
+pub struct CnameHop {
+    pub name: String,
+    pub target: String,
+    pub ttl: u32,
+}
+
+#[derive(Debug)]
+pub struct CnameChain {
+    pub hops: Vec<CnameHop>,
+    pub records: Vec<Record>,
+}
+
+// Follows the CNAME records in the answers to a question through to the records that were asked
+// for. If the server left out the rest of the chain, it's asked for with the requery function.
+pub fn follow_cname_chain<F>(
+    question: &DnsQuestion,
+    initial_answers: Vec<Record>,
+    mut requery: F,
+) -> Result<CnameChain, String>
+where
+    F: FnMut(DnsQuestion) -> io::Result<Vec<Record>>,
+{
+    let mut chain = CnameChain {
+        hops: Vec::new(),
+        records: Vec::new(),
+    };
+    let mut current_name = question.domain_name.clone();
+    let mut answers = initial_answers;
+    let mut requeried = false;
+    loop {
+        let cname = answers.iter().find(|r| {
+            (r.data_type == QueryType::CNAME) && r.domain_name.eq_ignore_ascii_case(&current_name)
+        });
+        if let Some(cname) = cname {
+            let target = cname.data.to_string();
+            let is_loop = question.domain_name.eq_ignore_ascii_case(&target)
+                || chain
+                    .hops
+                    .iter()
+                    .any(|hop| hop.target.eq_ignore_ascii_case(&target));
+            chain.hops.push(CnameHop {
+                name: current_name,
+                target: target.clone(),
+                ttl: cname.ttl,
+            });
+            if is_loop {
+                return Err(format!("CNAME chain loops back to {}", target));
+            }
+            if chain.hops.len() > MAX_CNAME_CHAIN_LENGTH {
+                return Err(format!(
+                    "CNAME chain is longer than {} hops",
+                    MAX_CNAME_CHAIN_LENGTH
+                ));
+            }
+            current_name = target;
+            requeried = false;
+            continue;
+        }
+
+        chain.records = answers
+            .into_iter()
+            .filter(|r| {
+                (r.data_type == question.query_type)
+                    && r.domain_name.eq_ignore_ascii_case(&current_name)
+            })
+            .collect();
+        if !chain.records.is_empty() || requeried {
+            return Ok(chain);
+        }
+
+        // The server didn't include the rest of the chain in its answer so we need to ask for it
+        let mut next_question = question.clone();
+        next_question.domain_name = current_name.clone();
+        answers = match requery(next_question) {
+            Ok(answers) => answers,
+            Err(e) => return Err(format!("Failed to query {}: {}", current_name, e)),
+        };
+        requeried = true;
+    }
+}
+
+pub fn bind_random_port(server: SocketAddr) -> io::Result<UdpSocket> {
+    const MAX_PORT_SELECT_ATTEMPTS: usize = 15;
+    let local_addr = match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let port_distribution = rand::distributions::Uniform::<u16>::new(49152, 65535); // Taken from the recommendation at https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.xhtml
+    let mut rng = rand::thread_rng();
+    let mut port_select_attempts = 0;
+    loop {
+        let port = rng.sample(port_distribution);
+        match UdpSocket::bind((local_addr, port)) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => {
+                port_select_attempts += 1;
+                if port_select_attempts >= MAX_PORT_SELECT_ATTEMPTS {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Failed to bind to a local port after {} retries: {}",
+                            MAX_PORT_SELECT_ATTEMPTS, e
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+pub fn send_query(
+    server: SocketAddr,
+    question: DnsQuestion,
+    timeout: Duration,
+) -> io::Result<DnsResponse> {
+    send_query_with_options(server, question, &QueryOptions::default(), timeout)
+}
+
+pub fn send_query_with_options(
+    server: SocketAddr,
+    mut question: DnsQuestion,
+    options: &QueryOptions,
+    timeout: Duration,
+) -> io::Result<DnsResponse> {
+    if options.randomise_case {
+        question.domain_name = randomise_case(&question.domain_name);
+    }
+    let mut request = DnsPacket::default();
+    request.header.request_id = rand::thread_rng().gen::<u16>();
+    request.header.recursion_desired = true; // TODO: Maybe we want to be able to ask for no recursion?
+    request.header.query_count = 1;
+    request.questions.push(question);
+    if let Some(edns) = options.edns.clone() {
+        // Padding has to be worked out from the size of the rest of the query
+        let padded = edns.options.iter().any(|option| matches!(option, EdnsOption::Padding(_)));
+        request.set_edns(edns);
+        if padded {
+            pad_to_block_size(&mut request, QUERY_PADDING_BLOCK_SIZE)?;
+        }
+    }
+    exchange_packet(server, &request, options.randomise_case, timeout)
+}
+
+// Makes sure that a response really is the answer to our request, since anyone who can guess
+// which port we sent it from can send us something that looks like one (RFC 5452 section 9.1)
+fn check_response(
+    request: &DnsPacket,
+    response: &DnsPacket,
+    case_sensitive: bool,
+) -> Result<(), String> {
+    if !response.header.is_response {
+        return Err(String::from("packet is a query rather than a response"));
+    }
+    if response.header.request_id != request.header.request_id {
+        return Err(format!(
+            "request ID {} does not match {}",
+            response.header.request_id, request.header.request_id
+        ));
+    }
+    // A server that couldn't parse the request or doesn't implement its opcode may leave the
+    // question (or zone) section out, so there's nothing to compare in that case. Any other
+    // response without it could be a forged NXDOMAIN that only had to guess the request ID.
+    let may_omit_question = matches!(
+        response.header.rcode,
+        ResponseCode::FormatError | ResponseCode::NotImplemented
+    );
+    if response.questions.is_empty() && may_omit_question {
+        return Ok(());
+    }
+    if response.questions.len() != request.questions.len() {
+        return Err(format!(
+            "response has {} questions but the request had {}",
+            response.questions.len(),
+            request.questions.len()
+        ));
+    }
+    for (asked, answered) in request.questions.iter().zip(&response.questions) {
+        let asked_name = asked.domain_name.trim_end_matches('.');
+        let answered_name = answered.domain_name.trim_end_matches('.');
+        let same_name = if case_sensitive {
+            asked_name == answered_name
+        } else {
+            asked_name.eq_ignore_ascii_case(answered_name)
+        };
+        if !same_name
+            || (asked.query_type != answered.query_type)
+            || (asked.query_class != answered.query_class)
+        {
+            return Err(format!(
+                "question {} ({:?}, {:?}) does not match {} ({:?}, {:?})",
+                answered.domain_name,
+                answered.query_type,
+                answered.query_class,
+                asked.domain_name,
+                asked.query_type,
+                asked.query_class
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Sends the given packet exactly as-is, so the caller is responsible for picking a request ID
+pub fn send_packet(
+    server: SocketAddr,
+    request: &DnsPacket,
+    timeout: Duration,
+) -> io::Result<DnsResponse> {
+    exchange_packet(server, request, false, timeout)
+}
+
+// Anything that doesn't look like the genuine response is ignored rather than treated as an
+// error, so that a forged packet arriving first can't stop us from getting the real answer.
+fn exchange_packet(
+    server: SocketAddr,
+    request: &DnsPacket,
+    case_sensitive: bool,
+    timeout: Duration,
+) -> io::Result<DnsResponse> {
+    let mut request_data_buf: Vec<u8> = Vec::new();
+    if let Err(e) = request.serialize(&mut request_data_buf) {
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Failed to serialize request: {}", e),
+        ));
+    }
+
+    let socket = bind_random_port(server)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    let send_instant = Instant::now();
+    if let Err(e) = socket.send_to(&request_data_buf, server) {
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Failed to send request to the network: {}", e),
+        ));
+    }
+
+    // With EDNS the server is allowed to send responses up to the payload size we advertised
+    let max_response_size = match &request.edns {
+        Some(edns) => cmp::max(edns.udp_payload_size as usize, MAX_UDP_MESSAGE_SIZE),
+        None => MAX_UDP_MESSAGE_SIZE,
+    };
+    let mut resp_buffer = vec![0; max_response_size];
+    let mut rejected = Vec::new();
+    loop {
+        let remaining = timeout.checked_sub(send_instant.elapsed()).unwrap_or_default();
+        if remaining == Duration::from_secs(0) {
+            let mut message = String::from("Failed to read response from the network: timed out");
+            if !rejected.is_empty() {
+                message.push_str(&format!(
+                    " (ignored {} packets that did not match the request: {})",
+                    rejected.len(),
+                    rejected.join("; ")
+                ));
+            }
+            return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (bytes_read, src_addr) = match socket.recv_from(&mut resp_buffer) {
+            Ok((bytes_read, src_addr)) => (bytes_read, src_addr),
+            Err(e) if (e.kind() == io::ErrorKind::WouldBlock) || (e.kind() == io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to read response from the network: {}", e),
+                ));
+            }
+        };
+        let roundtrip = send_instant.elapsed();
+        let resp_bytes = &resp_buffer[..bytes_read];
+
+        if src_addr != server {
+            rejected.push(format!("packet from unexpected source address {}", src_addr));
+            continue;
+        }
+
+        let mut cursor = io::Cursor::new(resp_bytes);
+        let response = match DnsHeader::deserialize_from(&mut cursor)
+            .and_then(|header| DnsPacket::deserialize_from(header, &mut cursor, resp_bytes))
+        {
+            Ok(packet) => packet,
+            Err(e) => {
+                rejected.push(format!("failed to deserialise packet: {}", e));
+                continue;
+            }
+        };
+        if let Err(reason) = check_response(request, &response, case_sensitive) {
+            rejected.push(reason);
+            continue;
+        }
+
+        return Ok(DnsResponse {
+            request_id: request.header.request_id,
+            packet: response,
+            data: resp_bytes.to_vec(),
+            source: src_addr,
+            roundtrip,
+            rejected,
+        });
+    }
+}
+
+struct CacheEntry {
+    rcode: ResponseCode,
+    records: Vec<Record>,
+    inserted: Instant,
+    expires: Instant,
+}
+
+pub struct Resolver {
+    servers: Vec<SocketAddr>,
+    timeout: Duration,
+    cache: Mutex<HashMap<(String, QueryType), CacheEntry>>,
+}
+
+impl Resolver {
+    pub fn new(servers: &[IpAddr]) -> Resolver {
+        Resolver::with_socket_addrs(
+            servers
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, DNS_PORT))
+                .collect(),
+        )
+    }
+
+    pub fn with_socket_addrs(servers: Vec<SocketAddr>) -> Resolver {
+        Resolver {
+            servers,
+            timeout: DEFAULT_TIMEOUT,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    // Returns all of the records in the answer section of the response, which may include CNAME
+    // records as well as records of the requested type. A name that does not exist is reported
+    // as an error with kind NotFound while a name that exists but has no records of the requested
+    // type results in an empty list.
+    pub fn lookup(&self, name: &str, qtype: QueryType) -> io::Result<Vec<Record>> {
+        let key = (name.trim_end_matches('.').to_ascii_lowercase(), qtype);
+        if let Some(entry) = self.cache.lock().unwrap().get(&key) {
+            let now = Instant::now();
+            if now < entry.expires {
+                let elapsed = now.duration_since(entry.inserted).as_secs() as u32;
+                let records = entry
+                    .records
+                    .iter()
+                    .map(|record| Record {
+                        ttl: record.ttl.saturating_sub(elapsed),
+                        ..record.clone()
+                    })
+                    .collect();
+                return lookup_result(name, entry.rcode, records);
+            }
+        }
+
+        let response = self.query_servers(name, qtype)?;
+        let rcode = response.packet.header.rcode;
+        let mut records = Vec::new();
+        for answer in &response.packet.answers {
+            match Record::deserialize_from(answer, &response.data) {
+                Ok(record) => records.push(record),
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        // NOTE: Negative answers (either the name doesn't exist or it has no records of the type
+        //       we asked for) are cached for the lesser of the SOA record's TTL and its minimum
+        //       field, as described in RFC 2308 section 5. Without an SOA we don't cache at all.
+        let has_answer = records.iter().any(|r| r.data_type == qtype);
+        let cache_ttl = if has_answer {
+            records.iter().map(|r| r.ttl).min()
+        } else {
+            response
+                .packet
+                .authorities
+                .iter()
+                .filter_map(|authority| Record::deserialize_from(authority, &response.data).ok())
+                .find_map(|authority| match authority.data {
+                    RecordData::SOA { minimum, .. } => Some(cmp::min(authority.ttl, minimum)),
+                    _ => None,
+                })
+        };
+        if let Some(cache_ttl) = cache_ttl.filter(|ttl| *ttl > 0) {
+            let inserted = Instant::now();
+            self.cache.lock().unwrap().insert(
+                key,
+                CacheEntry {
+                    rcode,
+                    records: records.clone(),
+                    inserted,
+                    expires: inserted + Duration::from_secs(cache_ttl as u64),
+                },
+            );
+        }
+
+        lookup_result(name, rcode, records)
+    }
+
+    pub fn lookup_ipv4(&self, name: &str) -> io::Result<Vec<Ipv4Addr>> {
+        Ok(self
+            .lookup(name, QueryType::A)?
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::A(addr) => Some(addr),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub fn lookup_ipv6(&self, name: &str) -> io::Result<Vec<Ipv6Addr>> {
+        Ok(self
+            .lookup(name, QueryType::AAAA)?
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::AAAA(addr) => Some(addr),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub fn lookup_ip(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let mut result: Vec<IpAddr> = self
+            .lookup_ipv4(name)?
+            .into_iter()
+            .map(IpAddr::V4)
+            .collect();
+        result.extend(self.lookup_ipv6(name)?.into_iter().map(IpAddr::V6));
+        Ok(result)
+    }
+
+    // Mail exchanges are returned in order of preference, most preferred first
+    pub fn lookup_mx(&self, name: &str) -> io::Result<Vec<(u16, String)>> {
+        let mut result: Vec<(u16, String)> = self
+            .lookup(name, QueryType::MX)?
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::MX {
+                    preference,
+                    exchange,
+                } => Some((preference, exchange)),
+                _ => None,
+            })
+            .collect();
+        result.sort();
+        Ok(result)
+    }
+
+    // Each TXT record is returned as a single string, made up of all of its character-strings
+    // joined together. Invalid UTF-8 is replaced rather than treated as an error.
+    pub fn lookup_txt(&self, name: &str) -> io::Result<Vec<String>> {
+        Ok(self
+            .lookup(name, QueryType::TXT)?
+            .into_iter()
+            .filter_map(|r| match r.data {
+                RecordData::TXT(strings) => {
+                    Some(String::from_utf8_lossy(&strings.concat()).into_owned())
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub fn lookup_ns(&self, name: &str) -> io::Result<Vec<String>> {
+        self.lookup_names(name, QueryType::NS)
+    }
+
+    pub fn lookup_cname(&self, name: &str) -> io::Result<Vec<String>> {
+        self.lookup_names(name, QueryType::CNAME)
+    }
+
+    pub fn lookup_ptr(&self, address: IpAddr) -> io::Result<Vec<String>> {
+        self.lookup_names(&reverse_domain_name(address), QueryType::PTR)
+    }
+
+    fn lookup_names(&self, name: &str, qtype: QueryType) -> io::Result<Vec<String>> {
+        Ok(self
+            .lookup(name, qtype)?
+            .into_iter()
+            .filter(|r| r.data_type == qtype)
+            .map(|r| r.data.to_string())
+            .collect())
+    }
+
+    fn query_servers(&self, name: &str, qtype: QueryType) -> io::Result<DnsResponse> {
+        let mut last_error = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No upstream DNS servers have been configured",
+        );
+        for server in &self.servers {
+            let question = DnsQuestion {
+                domain_name: String::from(name),
+                query_type: qtype,
+                query_class: DomainClass::Internet,
+            };
+            match send_query(*server, question, self.timeout) {
+                Ok(response) => match response.packet.header.rcode {
+                    ResponseCode::NoError | ResponseCode::NameError => return Ok(response),
+                    rcode => {
+                        last_error =
+                            io::Error::other(format!("{} responded with {:?}", server, rcode));
+                    }
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+fn lookup_result(name: &str, rcode: ResponseCode, records: Vec<Record>) -> io::Result<Vec<Record>> {
+    match rcode {
+        ResponseCode::NameError => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", name),
+        )),
+        _ => Ok(records),
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    type Answer = (ResponseCode, Vec<Record>, Vec<Record>);
+
+    // A minimal local stand-in for an upstream DNS server that answers every question using the
+    // given function and keeps count of how many queries it has received.
+    fn start_server<F>(answer: F) -> (SocketAddr, Arc<AtomicUsize>)
+    where
+        F: Fn(&DnsQuestion) -> Answer + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let query_count = Arc::new(AtomicUsize::new(0));
+        let thread_query_count = Arc::clone(&query_count);
+        thread::spawn(move || loop {
+            let mut buffer = [0; MAX_UDP_MESSAGE_SIZE];
+            let (len, src) = socket.recv_from(&mut buffer).unwrap();
+            thread_query_count.fetch_add(1, Ordering::SeqCst);
+
+            let data = &buffer[..len];
+            let mut cursor = io::Cursor::new(data);
+            let header = DnsHeader::deserialize_from(&mut cursor).unwrap();
+            let request = DnsPacket::deserialize_from(header, &mut cursor, data).unwrap();
+            let (rcode, answers, authorities) = answer(&request.questions[0]);
+
+            let mut response = DnsPacket::default();
+            response.header.request_id = request.header.request_id;
+            response.header.is_response = true;
+            response.header.rcode = rcode;
+            response.header.query_count = 1;
+            response.header.answer_count = answers.len() as u16;
+            response.header.nameserver_count = authorities.len() as u16;
+            response.questions = request.questions;
+            for record in &answers {
+                response.answers.push(record.to_resource_record().unwrap());
+            }
+            for record in &authorities {
+                response.authorities.push(record.to_resource_record().unwrap());
+            }
+            let mut response_data = Vec::new();
+            response.serialize(&mut response_data).unwrap();
+            socket.send_to(&response_data, src).unwrap();
+        });
+        (addr, query_count)
+    }
+
+    fn soa(minimum: u32) -> Record {
+        Record::new(
+            "example.com",
+            RecordData::SOA {
+                mname: String::from("ns1.example.com"),
+                rname: String::from("hostmaster.example.com"),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum,
+            },
+            300,
+        )
+    }
+
+    // Builds a response to the request with the given ID and question name, the way a forger would
+    fn forged_response(request: &DnsPacket, request_id: u16, name: &str) -> Vec<u8> {
+        let mut response = DnsPacket::default();
+        response.header.request_id = request_id;
+        response.header.is_response = true;
+        response.header.query_count = 1;
+        response.header.answer_count = 1;
+        let mut question = request.questions[0].clone();
+        question.domain_name = String::from(name);
+        response.questions.push(question);
+        let answer = Record::new(name, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 300);
+        response.answers.push(answer.to_resource_record().unwrap());
+        let mut data = Vec::new();
+        response.serialize(&mut data).unwrap();
+        data
+    }
+
+    // Replies to every query with each of the responses produced by the given function, in order
+    fn start_replying_server<F>(replies: F) -> SocketAddr
+    where
+        F: Fn(&DnsPacket) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || loop {
+            let mut buffer = [0; MAX_UDP_MESSAGE_SIZE];
+            let (len, src) = socket.recv_from(&mut buffer).unwrap();
+            let data = &buffer[..len];
+            let mut cursor = io::Cursor::new(data);
+            let header = DnsHeader::deserialize_from(&mut cursor).unwrap();
+            let request = DnsPacket::deserialize_from(header, &mut cursor, data).unwrap();
+            for reply in replies(&request) {
+                socket.send_to(&reply, src).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn question(name: &str) -> DnsQuestion {
+        DnsQuestion {
+            domain_name: String::from(name),
+            query_type: QueryType::A,
+            query_class: DomainClass::Internet,
+        }
+    }
+
+    #[test]
+    fn mismatched_responses_are_ignored() {
+        let server = start_replying_server(|request| {
+            let request_id = request.header.request_id;
+            let name = &request.questions[0].domain_name;
+            vec![
+                forged_response(request, request_id.wrapping_add(1), name),
+                forged_response(request, request_id, "evil.example.com"),
+                forged_response(request, request_id, name),
+            ]
+        });
+        let output = send_query(server, question("www.example.com"), Duration::from_secs(2)).unwrap();
+        assert_eq!(2, output.rejected.len());
+        assert_eq!("www.example.com", output.packet.questions[0].domain_name);
+        assert_eq!(1, output.packet.answers.len());
+    }
+
+    #[test]
+    fn responses_without_the_question_are_ignored() {
+        let server = start_replying_server(|request| {
+            let mut spoofed = DnsPacket::default();
+            spoofed.header.request_id = request.header.request_id;
+            spoofed.header.is_response = true;
+            spoofed.header.rcode = ResponseCode::NameError;
+            let mut spoofed_data = Vec::new();
+            spoofed.serialize(&mut spoofed_data).unwrap();
+            vec![spoofed_data, forged_response(request, request.header.request_id, &request.questions[0].domain_name)]
+        });
+        let output = send_query(server, question("www.example.com"), Duration::from_secs(2)).unwrap();
+        assert_eq!(1, output.rejected.len());
+        assert_eq!(ResponseCode::NoError, output.packet.header.rcode);
+        assert_eq!(1, output.packet.answers.len());
+    }
+
+    #[test]
+    fn case_randomisation_rejects_responses_that_change_case() {
+        let name = "a-long-enough-name-for-the-case-to-be-randomised.example.com";
+        let server = start_replying_server(|request| {
+            let request_id = request.header.request_id;
+            vec![forged_response(request, request_id, &request.questions[0].domain_name.to_ascii_lowercase())]
+        });
+        let options = QueryOptions {
+            randomise_case: true,
+            ..QueryOptions::default()
+        };
+        let timeout = Duration::from_millis(300);
+        let error = send_query_with_options(server, question(name), &options, timeout).unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, error.kind());
+        assert!(error.to_string().contains("does not match"));
+        assert!(send_query(server, question(name), timeout).is_ok());
+    }
+
+    #[test]
+    fn cidr_blocks_are_expanded() {
+        assert_eq!(
+            vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))],
+            parse_address_range("192.0.2.1/32").unwrap()
+        );
+        let output = parse_address_range("192.0.2.77/24").unwrap();
+        assert_eq!(256, output.len());
+        assert_eq!(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)), output[0]);
+        assert_eq!(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 255)), output[255]);
+
+        assert_eq!(MAX_REVERSE_RANGE_SIZE as usize, parse_address_range("2001:db8::/112").unwrap().len());
+        assert!(parse_address_range("2001:db8::/111").unwrap_err().contains("more than"));
+        // Every IPv6 address, which mustn't overflow while working out the size
+        assert!(parse_address_range("::/0").unwrap_err().contains("more than"));
+
+        assert!(parse_address_range("192.0.2.0/33").unwrap_err().contains("prefix length"));
+        assert!(parse_address_range("2001:db8::/129").unwrap_err().contains("prefix length"));
+    }
+
+    #[test]
+    fn address_ranges_are_expanded() {
+        assert_eq!(
+            vec![
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 254)),
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 255)),
+                IpAddr::V4(Ipv4Addr::new(192, 0, 3, 0)),
+            ],
+            parse_address_range("192.0.2.254 - 192.0.3.0").unwrap()
+        );
+        assert!(parse_address_range("192.0.2.20-192.0.2.10").unwrap_err().contains("after the end"));
+        assert!(parse_address_range("192.0.2.1-2001:db8::1").unwrap_err().contains("same type"));
+        assert!(parse_address_range("192.0.2.1-").is_err());
+    }
+
+    #[test]
+    fn randomised_case_keeps_the_same_name() {
+        let input = "www.example.com";
+        let output = randomise_case(input);
+        assert!(output.eq_ignore_ascii_case(input));
+    }
+
+    #[test]
+    fn lookup_ipv4_returns_addresses() {
+        let (server, _) = start_server(|question| {
+            let answers = vec![
+                Record::new(&question.domain_name, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 300),
+                Record::new(&question.domain_name, RecordData::A(Ipv4Addr::new(192, 0, 2, 2)), 300),
+            ];
+            (ResponseCode::NoError, answers, vec![])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        let output = resolver.lookup_ipv4("www.example.com").unwrap();
+        assert_eq!(
+            vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)],
+            output
+        );
+    }
+
+    fn cname(name: &str, target: &str) -> Record {
+        Record::new(name, RecordData::CNAME(String::from(target)), 300)
+    }
+
+    // Sends each follow-up query of a CNAME chain to the server
+    fn requery_server(server: SocketAddr) -> impl FnMut(DnsQuestion) -> io::Result<Vec<Record>> {
+        move |question| {
+            let response = send_query(server, question, Duration::from_secs(2))?;
+            response.packet.answers.iter().map(|answer| Record::deserialize_from(answer, &response.data)).collect()
+        }
+    }
+
+    #[test]
+    fn cname_chain_is_finished_by_querying_again() {
+        let (server, query_count) = start_server(|question| match question.domain_name.as_str() {
+            "d.example.com" => (ResponseCode::NoError, vec![Record::new("d.example.com", RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 60)], vec![]),
+            _ => (ResponseCode::NameError, vec![], vec![]),
+        });
+        let answers = vec![cname("a.example.com", "b.example.com"), cname("b.example.com", "c.example.com"), cname("c.example.com", "d.example.com")];
+        let output = follow_cname_chain(&question("a.example.com"), answers, requery_server(server)).unwrap();
+        assert_eq!(1, query_count.load(Ordering::SeqCst));
+        assert_eq!(
+            vec!["b.example.com", "c.example.com", "d.example.com"],
+            output.hops.iter().map(|hop| hop.target.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), output.records[0].data);
+    }
+
+    #[test]
+    fn cname_chain_loop_is_rejected() {
+        let (server, query_count) = start_server(|_| (ResponseCode::NoError, vec![], vec![]));
+        let answers = vec![cname("a.example.com", "b.example.com"), cname("b.example.com", "A.example.com")];
+        let error = follow_cname_chain(&question("a.example.com"), answers, requery_server(server)).unwrap_err();
+        assert!(error.contains("loops back to A.example.com"));
+        assert_eq!(0, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn cname_chain_length_is_limited() {
+        let (server, query_count) = start_server(|_| (ResponseCode::NoError, vec![], vec![]));
+        let answers = (0..=MAX_CNAME_CHAIN_LENGTH)
+            .map(|i| cname(&format!("{}.example.com", i), &format!("{}.example.com", i + 1)))
+            .collect();
+        let error = follow_cname_chain(&question("0.example.com"), answers, requery_server(server)).unwrap_err();
+        assert!(error.contains("longer than 16 hops"));
+        assert_eq!(0, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn repeated_lookup_is_answered_from_cache() {
+        let (server, query_count) = start_server(|question| {
+            let answers = vec![Record::new(&question.domain_name, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 300)];
+            (ResponseCode::NoError, answers, vec![])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        let first = resolver.lookup("www.example.com", QueryType::A).unwrap();
+        let second = resolver.lookup("WWW.example.com.", QueryType::A).unwrap();
+        assert_eq!(1, query_count.load(Ordering::SeqCst));
+        assert_eq!(first[0].data, second[0].data);
+        assert!(second[0].ttl <= 300);
+
+        resolver.clear_cache();
+        resolver.lookup("www.example.com", QueryType::A).unwrap();
+        assert_eq!(2, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn expired_records_are_queried_again() {
+        let (server, query_count) = start_server(|question| {
+            let answers = vec![Record::new(&question.domain_name, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 1)];
+            (ResponseCode::NoError, answers, vec![])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        resolver.lookup("www.example.com", QueryType::A).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        resolver.lookup("www.example.com", QueryType::A).unwrap();
+        assert_eq!(2, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn nonexistent_names_are_negatively_cached() {
+        let (server, query_count) = start_server(|_| {
+            (ResponseCode::NameError, vec![], vec![soa(60)])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        let first = resolver.lookup_ipv4("missing.example.com").unwrap_err();
+        let second = resolver.lookup_ipv4("missing.example.com").unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, first.kind());
+        assert_eq!(io::ErrorKind::NotFound, second.kind());
+        assert_eq!(1, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn negative_answers_without_soa_are_not_cached() {
+        let (server, query_count) = start_server(|_| {
+            (ResponseCode::NoError, vec![], vec![])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        assert!(resolver.lookup_ipv6("www.example.com").unwrap().is_empty());
+        assert!(resolver.lookup_ipv6("www.example.com").unwrap().is_empty());
+        assert_eq!(2, query_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn unresponsive_server_falls_back_to_next_server() {
+        let silent_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (server, _) = start_server(|question| {
+            let answers = vec![Record::new(&question.domain_name, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 300)];
+            (ResponseCode::NoError, answers, vec![])
+        });
+        let mut resolver = Resolver::with_socket_addrs(vec![silent_socket.local_addr().unwrap(), server]);
+        resolver.set_timeout(Duration::from_millis(200));
+        let output = resolver.lookup_ipv4("www.example.com").unwrap();
+        assert_eq!(
+            vec![Ipv4Addr::new(192, 0, 2, 1)],
+            output
+        );
+    }
+
+    #[test]
+    fn lookup_mx_sorts_by_preference_and_lookup_txt_joins_strings() {
+        let (server, _) = start_server(|question| {
+            let answers = match question.query_type {
+                QueryType::MX => vec![
+                    Record::new(&question.domain_name, RecordData::MX { preference: 20, exchange: String::from("mx2.example.com") }, 300),
+                    Record::new(&question.domain_name, RecordData::MX { preference: 10, exchange: String::from("mx1.example.com") }, 300),
+                ],
+                _ => vec![Record::new(&question.domain_name, RecordData::txt_from_str(&"a".repeat(300)), 300)],
+            };
+            (ResponseCode::NoError, answers, vec![])
+        });
+        let resolver = Resolver::with_socket_addrs(vec![server]);
+        assert_eq!(
+            vec![(10, String::from("mx1.example.com")), (20, String::from("mx2.example.com"))],
+            resolver.lookup_mx("example.com").unwrap()
+        );
+        assert_eq!(
+            vec!["a".repeat(300)],
+            resolver.lookup_txt("example.com").unwrap()
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostsEntry {
+    pub address: IpAddr,
+    pub names: Vec<String>,
+    pub line: usize,
+}
+
+pub struct HostsFile {
+    pub path: PathBuf,
+    pub entries: Vec<HostsEntry>,
+}
+
+pub fn default_hosts_path() -> PathBuf {
+    if cfg!(windows) {
+        let system_root = env::var("SystemRoot").unwrap_or_else(|_| String::from("C:\\Windows"));
+        [&system_root, "System32", "drivers", "etc", "hosts"].iter().collect()
+    } else {
+        PathBuf::from("/etc/hosts")
+    }
+}
+
+// Each line is an address followed by one or more names, with anything after a # being a comment.
+// Lines that don't start with a valid address are ignored, the same as the system resolver does.
+pub fn parse_hosts(contents: &str) -> Vec<HostsEntry> {
+    let mut result = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        };
+        let mut fields = line.split_whitespace();
+        let address_str = match fields.next() {
+            Some(address_str) => address_str,
+            None => continue,
+        };
+        // Link-local IPv6 addresses may have a zone index (e.g fe80::1%lo0) which we don't need
+        let address = match address_str.split('%').next().unwrap_or("").parse::<IpAddr>() {
+            Ok(address) => address,
+            Err(_) => continue,
+        };
+        let names: Vec<String> = fields.map(String::from).collect();
+        if !names.is_empty() {
+            result.push(HostsEntry {
+                address,
+                names,
+                line: index + 1,
+            });
+        }
+    }
+    result
+}
+
+impl HostsFile {
+    pub fn load(path: &Path) -> io::Result<HostsFile> {
+        let contents = fs::read_to_string(path)?;
+        Ok(HostsFile {
+            path: path.to_path_buf(),
+            entries: parse_hosts(&contents),
+        })
+    }
+
+    pub fn lookup_name(&self, name: &str) -> Vec<&HostsEntry> {
+        let name = name.trim_end_matches('.');
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .names
+                    .iter()
+                    .any(|n| n.trim_end_matches('.').eq_ignore_ascii_case(name))
+            })
+            .collect()
+    }
+
+    pub fn lookup_address(&self, address: IpAddr) -> Vec<&HostsEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.address == address)
+            .collect()
+    }
+}
+
+// Returns the servers from the "nameserver" lines of a resolv.conf file, in the order given
+pub fn parse_resolv_conf(contents: &str) -> Vec<IpAddr> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(address)) => address.split('%').next()?.parse().ok(),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod system_tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn parse_hosts_entries() {
+        let input = "# comment\n127.0.0.1\tlocalhost localhost.localdomain\n\n::1 localhost # IPv6\nnot-an-address foo\n10.0.0.5\n192.0.2.7 Build.Example.com.\n";
+        let output = parse_hosts(input);
+        assert_eq!(3, output.len());
+        assert_eq!(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), output[0].address);
+        assert_eq!(vec![String::from("localhost"), String::from("localhost.localdomain")], output[0].names);
+        assert_eq!(2, output[0].line);
+        assert_eq!(IpAddr::V6(Ipv6Addr::LOCALHOST), output[1].address);
+        assert_eq!(7, output[2].line);
+    }
+
+    #[test]
+    fn lookup_hosts_entries() {
+        let hosts = HostsFile {
+            path: PathBuf::from("hosts"),
+            entries: parse_hosts("127.0.0.1 localhost\n::1 localhost\n192.0.2.7 Build.Example.com.\n"),
+        };
+        assert_eq!(2, hosts.lookup_name("LOCALHOST").len());
+        assert_eq!(1, hosts.lookup_name("build.example.com").len());
+        assert!(hosts.lookup_name("example.com").is_empty());
+        assert_eq!(3, hosts.lookup_address(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7)))[0].line);
+    }
+
+    #[test]
+    fn parse_resolv_conf_nameservers() {
+        let input = "# generated\nsearch example.com\nnameserver 192.0.2.53\nnameserver fe80::1%eth0\noptions ndots:2\n";
+        let output = parse_resolv_conf(input);
+        assert_eq!(
+            vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53)), "fe80::1".parse::<IpAddr>().unwrap()],
+            output
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use super::packet::{
+    deserialize_name_string, serialize_name, DnsHeader, DnsPacket, DnsQuestion, DnsResourceRecord,
+    DomainClass, QueryType,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io;
+
+pub const HMAC_SHA256: &str = "hmac-sha256";
+
+// RFC 8945 section 10: The RECOMMENDED value is 300 seconds
+pub const DEFAULT_FUDGE: u16 = 300;
+
+// RFC 8945 section 3: Errors that are reported in the TSIG record itself rather than the header
+pub fn tsig_error_name(error: u16) -> String {
+    match error {
+        0 => String::from("NOERROR"),
+        16 => String::from("BADSIG"),
+        17 => String::from("BADKEY"),
+        18 => String::from("BADTIME"),
+        22 => String::from("BADTRUNC"),
+        _ => format!("error {}", error),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TsigRecord {
+    pub key_name: String,
+    pub algorithm: String,
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: u16,
+    pub other_data: Vec<u8>,
+}
+
+impl TsigRecord {
+    pub fn deserialize_from(record: &DnsResourceRecord, all_data: &[u8]) -> io::Result<TsigRecord> {
+        let mut cursor = io::Cursor::new(&record.data[..]);
+        let algorithm = deserialize_name_string(&mut cursor, all_data)?;
+        let time_signed = ((cursor.read_u16::<BigEndian>()? as u64) << 32)
+            | (cursor.read_u32::<BigEndian>()? as u64);
+        let fudge = cursor.read_u16::<BigEndian>()?;
+        let mut mac = vec![0; cursor.read_u16::<BigEndian>()? as usize];
+        io::Read::read_exact(&mut cursor, &mut mac)?;
+        let original_id = cursor.read_u16::<BigEndian>()?;
+        let error = cursor.read_u16::<BigEndian>()?;
+        let mut other_data = vec![0; cursor.read_u16::<BigEndian>()? as usize];
+        io::Read::read_exact(&mut cursor, &mut other_data)?;
+        Ok(TsigRecord {
+            key_name: record.domain_name.clone(),
+            algorithm,
+            time_signed,
+            fudge,
+            mac,
+            original_id,
+            error,
+            other_data,
+        })
+    }
+
+    pub fn to_resource_record(&self) -> io::Result<DnsResourceRecord> {
+        let mut data = Vec::new();
+        serialize_name(&self.algorithm, &mut data)?;
+        write_time_signed(self.time_signed, &mut data)?;
+        data.write_u16::<BigEndian>(self.fudge)?;
+        data.write_u16::<BigEndian>(self.mac.len() as u16)?;
+        data.extend_from_slice(&self.mac);
+        data.write_u16::<BigEndian>(self.original_id)?;
+        data.write_u16::<BigEndian>(self.error)?;
+        data.write_u16::<BigEndian>(self.other_data.len() as u16)?;
+        data.extend_from_slice(&self.other_data);
+        Ok(DnsResourceRecord {
+            domain_name: self.key_name.clone(),
+            data_type: QueryType::TSIG,
+            data_class: DomainClass::Any,
+            ttl: 0,
+            data_length: data.len() as u16,
+            data,
+        })
+    }
+
+    // RFC 8945 section 4.3.3: The TSIG variables that get included in the MAC, with names in
+    // canonical (lowercase, uncompressed) form.
+    fn serialize_variables(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        serialize_name(&self.key_name.to_ascii_lowercase(), buffer)?;
+        buffer.write_u16::<BigEndian>(DomainClass::Any as u16)?;
+        buffer.write_u32::<BigEndian>(0)?;
+        serialize_name(&self.algorithm.to_ascii_lowercase(), buffer)?;
+        write_time_signed(self.time_signed, buffer)?;
+        buffer.write_u16::<BigEndian>(self.fudge)?;
+        buffer.write_u16::<BigEndian>(self.error)?;
+        buffer.write_u16::<BigEndian>(self.other_data.len() as u16)?;
+        buffer.extend_from_slice(&self.other_data);
+        Ok(())
+    }
+}
+
+// Time signed is a 48-bit count of seconds since the unix epoch
+fn write_time_signed(time_signed: u64, buffer: &mut Vec<u8>) -> io::Result<()> {
+    buffer.write_u16::<BigEndian>((time_signed >> 32) as u16)?;
+    buffer.write_u32::<BigEndian>(time_signed as u32)?;
+    Ok(())
+}
+
+// Finds the TSIG record, which must be the last record in the additional section, and returns it
+// along with the offset at which it starts in the message.
+pub fn find_tsig(data: &[u8]) -> io::Result<Option<(usize, DnsResourceRecord)>> {
+    let mut cursor = io::Cursor::new(data);
+    let header = DnsHeader::deserialize_from(&mut cursor)?;
+    for _ in 0..header.query_count {
+        DnsQuestion::deserialize_from(&mut cursor)?;
+    }
+    let record_count = header.answer_count as usize
+        + header.nameserver_count as usize
+        + header.additional_count as usize;
+    let mut last_record = None;
+    for _ in 0..record_count {
+        let offset = cursor.position() as usize;
+        last_record = Some((offset, DnsResourceRecord::deserialize_from(&mut cursor, data)?));
+    }
+
+    match last_record {
+        Some((offset, record))
+            if (header.additional_count > 0) && (record.data_type == QueryType::TSIG) =>
+        {
+            Ok(Some((offset, record)))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub struct TsigKey {
+    pub name: String,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    pub fn new(name: &str, secret: &[u8]) -> TsigKey {
+        TsigKey {
+            name: String::from(name.trim_end_matches('.')),
+            secret: secret.to_vec(),
+        }
+    }
+
+    // Accepts a key in the format written by BIND's tsig-keygen, e.g:
+    //   key "ci-key" { algorithm hmac-sha256; secret "c2VjcmV0"; };
+    pub fn from_bind_config(config: &str) -> io::Result<TsigKey> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let tokens: Vec<&str> = config
+            .split(|c: char| c.is_whitespace() || (c == ';') || (c == '{') || (c == '}'))
+            .filter(|token| !token.is_empty())
+            .collect();
+        let value_after = |keyword: &str| {
+            tokens
+                .iter()
+                .position(|token| token.eq_ignore_ascii_case(keyword))
+                .and_then(|index| tokens.get(index + 1))
+                .map(|value| value.trim_matches('"'))
+        };
+
+        let name = value_after("key").ok_or_else(|| invalid("No key name found in key file"))?;
+        let algorithm = value_after("algorithm").ok_or_else(|| invalid("No algorithm found in key file"))?;
+        if !algorithm.eq_ignore_ascii_case(HMAC_SHA256) {
+            return Err(invalid(&format!("Unsupported TSIG algorithm {}, only {} is supported", algorithm, HMAC_SHA256)));
+        }
+        let secret = value_after("secret").ok_or_else(|| invalid("No secret found in key file"))?;
+        Ok(TsigKey::new(name, &decode_secret(secret)?))
+    }
+
+    // Signs the packet by appending a TSIG record to it. The MAC is returned so that it can be
+    // used to verify the response.
+    pub fn sign(&self, packet: &mut DnsPacket, time_signed: u64) -> io::Result<Vec<u8>> {
+        self.sign_with_request_mac(packet, None, time_signed)
+    }
+
+    pub fn sign_response(
+        &self,
+        packet: &mut DnsPacket,
+        request_mac: &[u8],
+        time_signed: u64,
+    ) -> io::Result<Vec<u8>> {
+        self.sign_with_request_mac(packet, Some(request_mac), time_signed)
+    }
+
+    fn sign_with_request_mac(
+        &self,
+        packet: &mut DnsPacket,
+        request_mac: Option<&[u8]>,
+        time_signed: u64,
+    ) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        packet.serialize(&mut message)?;
+
+        let mut tsig = TsigRecord {
+            key_name: self.name.clone(),
+            algorithm: String::from(HMAC_SHA256),
+            time_signed,
+            fudge: DEFAULT_FUDGE,
+            mac: Vec::new(),
+            original_id: packet.header.request_id,
+            error: 0,
+            other_data: Vec::new(),
+        };
+        tsig.mac = self.compute_mac(request_mac, &message, &tsig)?.finalize().into_bytes().to_vec();
+
+        packet.additionals.push(tsig.to_resource_record()?);
+        packet.header.additional_count += 1;
+        Ok(tsig.mac)
+    }
+
+    // Checks the TSIG record on the given message, as described in RFC 8945 section 5.
+    // Responses must be verified against the MAC of the request that they are responding to.
+    pub fn verify(&self, data: &[u8], request_mac: Option<&[u8]>, now: u64) -> io::Result<TsigRecord> {
+        let rejected = |message: String| io::Error::new(io::ErrorKind::PermissionDenied, message);
+
+        let (offset, record) = match find_tsig(data)? {
+            Some(tsig) => tsig,
+            None => return Err(rejected(String::from("Message is not signed"))),
+        };
+        let tsig = TsigRecord::deserialize_from(&record, data)?;
+        if tsig.error != 0 {
+            return Err(rejected(format!("Signature was rejected with {}", tsig_error_name(tsig.error))));
+        }
+        if !tsig.key_name.trim_end_matches('.').eq_ignore_ascii_case(&self.name) {
+            return Err(rejected(format!("Message was signed with unknown key {} (BADKEY)", tsig.key_name)));
+        }
+        if !tsig.algorithm.trim_end_matches('.').eq_ignore_ascii_case(HMAC_SHA256) {
+            return Err(rejected(format!("Message was signed with unsupported algorithm {} (BADKEY)", tsig.algorithm)));
+        }
+
+        // The MAC covers the message as it was before the TSIG record was added
+        let mut message = data[..offset].to_vec();
+        let additional_count = u16::from_be_bytes([message[10], message[11]]) - 1;
+        message[0..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        message[10..12].copy_from_slice(&additional_count.to_be_bytes());
+        if self.compute_mac(request_mac, &message, &tsig)?.verify_slice(&tsig.mac).is_err() {
+            return Err(rejected(String::from("Message signature does not match (BADSIG)")));
+        }
+
+        if now.max(tsig.time_signed) - now.min(tsig.time_signed) > tsig.fudge as u64 {
+            return Err(rejected(format!(
+                "Message was signed {} seconds away from the current time, which is more than the allowed {} (BADTIME)",
+                now.max(tsig.time_signed) - now.min(tsig.time_signed),
+                tsig.fudge
+            )));
+        }
+        Ok(tsig)
+    }
+
+    fn compute_mac(
+        &self,
+        request_mac: Option<&[u8]>,
+        message: &[u8],
+        tsig: &TsigRecord,
+    ) -> io::Result<Hmac<Sha256>> {
+        let mut mac = match Hmac::<Sha256>::new_from_slice(&self.secret) {
+            Ok(mac) => mac,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string())),
+        };
+        if let Some(request_mac) = request_mac {
+            mac.update(&(request_mac.len() as u16).to_be_bytes());
+            mac.update(request_mac);
+        }
+        mac.update(message);
+        let mut variables = Vec::new();
+        tsig.serialize_variables(&mut variables)?;
+        mac.update(&variables);
+        Ok(mac)
+    }
+}
+
+pub fn decode_secret(secret: &str) -> io::Result<Vec<u8>> {
+    match crate::base64::to_bytes(secret) {
+        Ok(bytes) if !bytes.is_empty() => Ok(bytes),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "TSIG secret is empty",
+        )),
+        Err(e) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("TSIG secret is not valid base64: {}", e),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tsig_tests {
+    use super::*;
+
+    fn test_packet() -> DnsPacket {
+        let mut packet = DnsPacket::default();
+        packet.header.request_id = 0x4242;
+        packet.header.query_count = 1;
+        packet.questions.push(DnsQuestion {
+            domain_name: String::from("example.com"),
+            query_type: QueryType::SOA,
+            query_class: DomainClass::Internet,
+        });
+        packet
+    }
+
+    fn serialize(packet: &DnsPacket) -> Vec<u8> {
+        let mut data = Vec::new();
+        packet.serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn signed_message_verifies() {
+        let key = TsigKey::new("ci-key", b"0123456789abcdef");
+        let mut packet = test_packet();
+        let mac = key.sign(&mut packet, 1_700_000_000).unwrap();
+        let output = key.verify(&serialize(&packet), None, 1_700_000_100).unwrap();
+        assert_eq!(mac, output.mac);
+        assert_eq!(32, output.mac.len());
+        assert_eq!(0x4242, output.original_id);
+    }
+
+    #[test]
+    fn modified_message_is_rejected() {
+        let key = TsigKey::new("ci-key", b"0123456789abcdef");
+        let mut packet = test_packet();
+        key.sign(&mut packet, 1_700_000_000).unwrap();
+        packet.questions[0].domain_name = String::from("example.net");
+        assert!(key.verify(&serialize(&packet), None, 1_700_000_000).is_err());
+
+        let other_key = TsigKey::new("ci-key", b"fedcba9876543210");
+        let mut packet = test_packet();
+        key.sign(&mut packet, 1_700_000_000).unwrap();
+        assert!(other_key.verify(&serialize(&packet), None, 1_700_000_000).is_err());
+    }
+
+    #[test]
+    fn message_outside_fudge_is_rejected() {
+        let key = TsigKey::new("ci-key", b"0123456789abcdef");
+        let mut packet = test_packet();
+        key.sign(&mut packet, 1_700_000_000).unwrap();
+        assert!(key.verify(&serialize(&packet), None, 1_700_000_301).is_err());
+    }
+
+    #[test]
+    fn response_is_verified_against_request_mac() {
+        let key = TsigKey::new("ci-key", b"0123456789abcdef");
+        let mut request = test_packet();
+        let request_mac = key.sign(&mut request, 1_700_000_000).unwrap();
+        let mut response = test_packet();
+        response.header.is_response = true;
+        key.sign_response(&mut response, &request_mac, 1_700_000_001).unwrap();
+        assert!(key.verify(&serialize(&response), Some(&request_mac), 1_700_000_001).is_ok());
+        assert!(key.verify(&serialize(&response), None, 1_700_000_001).is_err());
+    }
+
+    #[test]
+    fn key_from_bind_config() {
+        let input = "key \"ci-key\" {\n\talgorithm hmac-sha256;\n\tsecret \"MDEyMzQ1Njc4OWFiY2RlZg==\";\n};\n";
+        let output = TsigKey::from_bind_config(input).unwrap();
+        assert_eq!("ci-key", output.name);
+        assert_eq!(b"0123456789abcdef".to_vec(), output.secret);
+        assert!(TsigKey::from_bind_config("key \"k\" { algorithm hmac-md5; secret \"AAAA\"; };").is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use super::packet::{DnsPacket, DnsQuestion, DnsResourceRecord, DomainClass, OpCode, QueryType};
+use super::record::Record;
+use super::resolver::{send_packet, DnsResponse};
+use super::tsig::TsigKey;
+use rand::Rng;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// RFC 2136 section 2.4
+#[derive(Clone, Debug, PartialEq)]
+pub enum Prerequisite {
+    NameInUse(String),
+    NameNotInUse(String),
+    RecordSetExists(String, QueryType),
+    RecordSetDoesNotExist(String, QueryType),
+    RecordExists(Record),
+}
+
+// RFC 2136 section 2.5
+#[derive(Clone, Debug, PartialEq)]
+pub enum Update {
+    Add(Record),
+    DeleteName(String),
+    DeleteRecordSet(String, QueryType),
+    DeleteRecord(Record),
+}
+
+pub struct UpdateMessage {
+    pub zone: String,
+    pub prerequisites: Vec<Prerequisite>,
+    pub updates: Vec<Update>,
+}
+
+fn empty_record(name: &str, data_type: QueryType, data_class: DomainClass) -> DnsResourceRecord {
+    DnsResourceRecord {
+        domain_name: String::from(name),
+        data_type,
+        data_class,
+        ttl: 0,
+        data_length: 0,
+        data: Vec::new(),
+    }
+}
+
+fn is_in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+    zone.is_empty() || (name == zone) || name.ends_with(&format!(".{}", zone))
+}
+
+impl Prerequisite {
+    fn name(&self) -> &str {
+        match self {
+            Prerequisite::NameInUse(name)
+            | Prerequisite::NameNotInUse(name)
+            | Prerequisite::RecordSetExists(name, _)
+            | Prerequisite::RecordSetDoesNotExist(name, _) => name,
+            Prerequisite::RecordExists(record) => &record.domain_name,
+        }
+    }
+
+    fn to_resource_record(&self) -> io::Result<DnsResourceRecord> {
+        match self {
+            Prerequisite::NameInUse(name) => Ok(empty_record(name, QueryType::ANY, DomainClass::Any)),
+            Prerequisite::NameNotInUse(name) => Ok(empty_record(name, QueryType::ANY, DomainClass::None)),
+            Prerequisite::RecordSetExists(name, data_type) => Ok(empty_record(name, *data_type, DomainClass::Any)),
+            Prerequisite::RecordSetDoesNotExist(name, data_type) => Ok(empty_record(name, *data_type, DomainClass::None)),
+            Prerequisite::RecordExists(record) => {
+                let mut result = record.to_resource_record()?;
+                result.ttl = 0;
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl Update {
+    fn name(&self) -> &str {
+        match self {
+            Update::DeleteName(name) | Update::DeleteRecordSet(name, _) => name,
+            Update::Add(record) | Update::DeleteRecord(record) => &record.domain_name,
+        }
+    }
+
+    fn to_resource_record(&self) -> io::Result<DnsResourceRecord> {
+        match self {
+            Update::Add(record) => record.to_resource_record(),
+            Update::DeleteName(name) => Ok(empty_record(name, QueryType::ANY, DomainClass::Any)),
+            Update::DeleteRecordSet(name, data_type) => Ok(empty_record(name, *data_type, DomainClass::Any)),
+            Update::DeleteRecord(record) => {
+                let mut result = record.to_resource_record()?;
+                result.data_class = DomainClass::None;
+                result.ttl = 0;
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl UpdateMessage {
+    pub fn new(zone: &str) -> UpdateMessage {
+        UpdateMessage {
+            zone: String::from(zone.trim_end_matches('.')),
+            prerequisites: Vec::new(),
+            updates: Vec::new(),
+        }
+    }
+
+    // The zone goes in the question section, prerequisites in the answer section and updates in
+    // the authority section (RFC 2136 section 2).
+    pub fn to_packet(&self, request_id: u16) -> io::Result<DnsPacket> {
+        let names = self
+            .prerequisites
+            .iter()
+            .map(|p| p.name())
+            .chain(self.updates.iter().map(|u| u.name()));
+        for name in names {
+            if !is_in_zone(name, &self.zone) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not in the zone {}", name, self.zone),
+                ));
+            }
+        }
+
+        let mut packet = DnsPacket::default();
+        packet.header.request_id = request_id;
+        packet.header.opcode = OpCode::Update;
+        packet.header.query_count = 1;
+        packet.questions.push(DnsQuestion {
+            domain_name: self.zone.clone(),
+            query_type: QueryType::SOA,
+            query_class: DomainClass::Internet,
+        });
+        for prerequisite in &self.prerequisites {
+            packet.answers.push(prerequisite.to_resource_record()?);
+        }
+        for update in &self.updates {
+            packet.authorities.push(update.to_resource_record()?);
+        }
+        packet.header.answer_count = packet.answers.len() as u16;
+        packet.header.nameserver_count = packet.authorities.len() as u16;
+        Ok(packet)
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Sends the update, signing it with the given key if there is one. Signed updates require a
+// correctly signed response, anything else is returned as an error with kind PermissionDenied.
+pub fn send_update(
+    server: SocketAddr,
+    message: &UpdateMessage,
+    key: Option<&TsigKey>,
+    timeout: Duration,
+) -> io::Result<DnsResponse> {
+    let mut packet = message.to_packet(rand::thread_rng().gen::<u16>())?;
+    let request_mac = match key {
+        Some(key) => Some(key.sign(&mut packet, unix_time())?),
+        None => None,
+    };
+
+    let response = send_packet(server, &packet, timeout)?;
+    if response.packet.header.request_id != response.request_id {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Received a response with a request ID that does not match our request",
+        ));
+    }
+    if let Some(key) = key {
+        key.verify(&response.data, request_mac.as_deref(), unix_time())?;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod update_tests {
+    use super::*;
+    use crate::dns::packet::{DnsHeader, ResponseCode};
+    use crate::dns::record::RecordData;
+    use crate::dns::tsig::TsigRecord;
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::sync::mpsc;
+    use std::thread;
+
+    // A local stand-in for a primary server that only accepts updates signed with the given key,
+    // and passes the updates that it accepts back to the test.
+    fn start_server(key: TsigKey) -> (SocketAddr, mpsc::Receiver<Vec<DnsResourceRecord>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut buffer = [0; 512];
+            let (len, src) = socket.recv_from(&mut buffer).unwrap();
+            let data = &buffer[..len];
+            let mut cursor = io::Cursor::new(data);
+            let header = DnsHeader::deserialize_from(&mut cursor).unwrap();
+            let request = DnsPacket::deserialize_from(header, &mut cursor, data).unwrap();
+
+            let mut response = DnsPacket::default();
+            response.header.request_id = request.header.request_id;
+            response.header.is_response = true;
+            response.header.opcode = OpCode::Update;
+            response.header.query_count = 1;
+            response.questions = request.questions.clone();
+            match key.verify(data, None, unix_time()) {
+                Ok(tsig) => {
+                    response.header.rcode = ResponseCode::NoError;
+                    key.sign_response(&mut response, &tsig.mac, unix_time()).unwrap();
+                    sender.send(request.authorities).unwrap();
+                }
+                Err(_) => {
+                    // RFC 8945 section 5.3.2: Errors are reported in an unsigned TSIG record
+                    response.header.rcode = ResponseCode::NotAuthorized;
+                    let tsig = TsigRecord {
+                        key_name: key.name.clone(),
+                        algorithm: String::from(crate::dns::tsig::HMAC_SHA256),
+                        time_signed: unix_time(),
+                        fudge: 300,
+                        mac: Vec::new(),
+                        original_id: request.header.request_id,
+                        error: 16,
+                        other_data: Vec::new(),
+                    };
+                    response.additionals.push(tsig.to_resource_record().unwrap());
+                    response.header.additional_count = 1;
+                }
+            }
+            let mut response_data = Vec::new();
+            response.serialize(&mut response_data).unwrap();
+            socket.send_to(&response_data, src).unwrap();
+        });
+        (addr, receiver)
+    }
+
+    fn test_message() -> UpdateMessage {
+        let mut message = UpdateMessage::new("example.com");
+        message.prerequisites.push(Prerequisite::NameNotInUse(String::from("new.example.com")));
+        message.updates.push(Update::DeleteRecordSet(String::from("www.example.com"), QueryType::A));
+        message.updates.push(Update::Add(Record::new("www.example.com", RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), 300)));
+        message
+    }
+
+    #[test]
+    fn update_sections_are_encoded() {
+        let output = test_message().to_packet(1).unwrap();
+        assert_eq!(1, output.header.answer_count);
+        assert_eq!(2, output.header.nameserver_count);
+        assert_eq!(QueryType::SOA, output.questions[0].query_type);
+        assert_eq!(DomainClass::None, output.answers[0].data_class);
+        assert_eq!(QueryType::ANY, output.answers[0].data_type);
+        assert_eq!(DomainClass::Any, output.authorities[0].data_class);
+        assert!(output.authorities[0].data.is_empty());
+        assert_eq!(DomainClass::Internet, output.authorities[1].data_class);
+        assert_eq!(vec![192, 0, 2, 1], output.authorities[1].data);
+    }
+
+    #[test]
+    fn names_outside_the_zone_are_rejected() {
+        let mut message = UpdateMessage::new("example.com");
+        message.updates.push(Update::DeleteName(String::from("www.example.net")));
+        assert!(message.to_packet(1).is_err());
+    }
+
+    #[test]
+    fn signed_update_is_accepted() {
+        let (server, updates) = start_server(TsigKey::new("ci-key", b"0123456789abcdef"));
+        let key = TsigKey::new("ci-key", b"0123456789abcdef");
+        let response = send_update(server, &test_message(), Some(&key), Duration::from_secs(5)).unwrap();
+        assert_eq!(ResponseCode::NoError, response.packet.header.rcode);
+        assert_eq!(2, updates.recv().unwrap().len());
+    }
+
+    #[test]
+    fn update_signed_with_wrong_secret_is_rejected() {
+        let (server, updates) = start_server(TsigKey::new("ci-key", b"0123456789abcdef"));
+        let key = TsigKey::new("ci-key", b"not-the-right-secret");
+        let output = send_update(server, &test_message(), Some(&key), Duration::from_secs(5)).unwrap_err();
+        assert_eq!(io::ErrorKind::PermissionDenied, output.kind());
+        assert!(updates.try_recv().is_err());
+    }
+}
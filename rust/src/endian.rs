@@ -0,0 +1,64 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+// The sizes in bits of the words that swap_words can swap
+pub const WORD_SIZES: &[usize] = &[16, 32, 64];
+
+// Reverses the order of the bytes within each word, e.g to read a dump of little-endian 32-bit
+// registers as big-endian. The input must be a whole number of words.
+pub fn swap_words(bytes: &mut [u8], bits: usize) -> Result<(), String> {
+    if !WORD_SIZES.contains(&bits) {
+        return Err(format!("Can't swap the bytes of {}-bit words, only {:?}", bits, WORD_SIZES));
+    }
+    if !bytes.len().is_multiple_of(bits / 8) {
+        return Err(format!("{} bytes isn't a whole number of {}-bit words", bytes.len(), bits));
+    }
+
+    for word in bytes.chunks_exact_mut(bits / 8) {
+        match bits {
+            16 => {
+                let value = LittleEndian::read_u16(word);
+                BigEndian::write_u16(word, value);
+            },
+            32 => {
+                let value = LittleEndian::read_u32(word);
+                BigEndian::write_u32(word, value);
+            },
+            _ => {
+                let value = LittleEndian::read_u64(word);
+                BigEndian::write_u64(word, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod endian_tests {
+    use super::*;
+
+    #[test]
+    fn swap_each_word() {
+        let mut bytes = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        swap_words(&mut bytes, 16).unwrap();
+        assert_eq!(
+            vec![0x02, 0x01, 0x04, 0x03, 0x06, 0x05, 0x08, 0x07],
+            bytes
+        );
+        swap_words(&mut bytes, 32).unwrap();
+        assert_eq!(
+            vec![0x03, 0x04, 0x01, 0x02, 0x07, 0x08, 0x05, 0x06],
+            bytes
+        );
+        swap_words(&mut bytes, 64).unwrap();
+        assert_eq!(
+            vec![0x06, 0x05, 0x08, 0x07, 0x02, 0x01, 0x04, 0x03],
+            bytes
+        );
+    }
+
+    #[test]
+    fn partial_words_are_rejected() {
+        assert!(swap_words(&mut [0x01, 0x02, 0x03], 16).is_err());
+        assert!(swap_words(&mut [0x01, 0x02], 24).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::codec::{BlockCodec, Codec, DecodeError, DecodeErrorKind};
+
 fn hex_char_value(u: u8) -> Option<u8> {
     if (u >= b'0') && (u <= b'9') {
         return Some(u - 0x30);
@@ -12,60 +14,48 @@ fn hex_char_value(u: u8) -> Option<u8> {
     None
 }
 
-pub fn to_bytes(input: &str) -> Option<Vec<u8>> {
-    if input.len() == 0 {
-        return Some(vec![]);
-    }
-
+pub fn to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
     let bytes = input.as_bytes();
     let mut start_index: usize = 0;
     if (bytes.len() > 1) && (bytes[0] == b'0') && (bytes[1] == b'x') {
         start_index = 2;
     }
 
-    let max_result_length = (bytes.len()-start_index)/2 + 1;
-    let mut result = Vec::with_capacity(max_result_length);
-    if (bytes.len()-start_index) % 2 == 1 {
-        let hexval = hex_char_value(bytes[start_index]);
-        let hexval = match hexval {
-            Some(x) => x,
-            None => return None
-        };
-        result.push(hexval);
-        start_index += 1;
-    }
-
-    let mut current_byte = 0;
-    let mut nibble_count = 0;
+    let mut nibbles = Vec::with_capacity(bytes.len()-start_index);
     for i in start_index..bytes.len() {
         if bytes[i] == b'-' {
             continue;
         }
 
-        let hexval = hex_char_value(bytes[i]);
-        let hexval = match hexval {
-            Some(x) => x,
-            None => return None
-        };
-
-        current_byte = (current_byte << 4) | hexval;
-        nibble_count += 1;
-        if nibble_count == 2 {
-            result.push(current_byte);
-            nibble_count = 0;
-            current_byte = 0;
+        match hex_char_value(bytes[i]) {
+            Some(x) => nibbles.push(x),
+            None => return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, i))
         }
     }
 
-    if nibble_count != 0 {
-        result.push(current_byte);
+    // An odd number of digits is treated as though it had a leading zero
+    let mut result = Vec::with_capacity(nibbles.len()/2 + 1);
+    let mut remaining = &nibbles[..];
+    if remaining.len() % 2 == 1 {
+        result.push(remaining[0]);
+        remaining = &remaining[1..];
+    }
+    for pair in remaining.chunks(2) {
+        result.push((pair[0] << 4) | pair[1]);
     }
 
-    Some(result)
+    Ok(result)
 }
 
 pub fn from_bytes(input: &[u8]) -> String {
-    const HEX: &[u8] = b"0123456789ABCDEF";
+    encode(input, true)
+}
+
+fn encode(input: &[u8], uppercase: bool) -> String {
+    let digits: &[u8] = match uppercase {
+        true => b"0123456789ABCDEF",
+        false => b"0123456789abcdef"
+    };
     let mut output = Vec::with_capacity(input.len() * 2);
     output.push(b'0');
     output.push(b'x');
@@ -73,14 +63,87 @@ pub fn from_bytes(input: &[u8]) -> String {
     for b in input {
         let hi_nibble = ((b >> 4) & (0xF as u8)) as usize;
         let lo_nibble = (b & 0xF) as usize;
-        output.push(HEX[hi_nibble]);
-        output.push(HEX[lo_nibble]);
+        output.push(digits[hi_nibble]);
+        output.push(digits[lo_nibble]);
     }
 
     let output = String::from_utf8(output).unwrap();
     output
 }
 
+#[derive(Clone, Default)]
+pub struct Hex {
+    pub lowercase: bool,
+}
+
+impl Codec for Hex {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["16"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("lowercase", "output lowercase hex digits")]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        match option {
+            "lowercase" => self.lowercase = true,
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        encode(input, !self.lowercase)
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        to_bytes(input)
+    }
+
+    fn block_codec(&self) -> Option<Box<dyn BlockCodec>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl BlockCodec for Hex {
+    fn block_sizes(&self) -> (usize, usize) {
+        (1, 2)
+    }
+
+    fn prefix(&self) -> &'static [u8] {
+        b"0x"
+    }
+
+    fn is_ignorable(&self, c: u8) -> bool {
+        (c == b'-') || c.is_ascii_whitespace()
+    }
+
+    fn encode_block(&self, input: &[u8], output: &mut Vec<u8>) {
+        // Skip the "0x" that encode() adds
+        output.extend_from_slice(&encode(input, !self.lowercase).as_bytes()[2..]);
+    }
+
+    fn decode_block(&self, input: &[u8], output: &mut Vec<u8>) -> Result<(), (usize, DecodeErrorKind)> {
+        let mut value = 0;
+        for (i, c) in input.iter().enumerate() {
+            match hex_char_value(*c) {
+                Some(x) => value = (value << 4) | x,
+                None => return Err((i, DecodeErrorKind::InvalidDigit))
+            }
+        }
+        if input.len() != 2 {
+            return Err((input.len(), DecodeErrorKind::BadLength));
+        }
+        output.push(value);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod hex_tests {
     use super::*;
@@ -115,6 +178,16 @@ mod hex_tests {
         );
     }
 
+    #[test]
+    fn hex2bytes_invalid_digit() {
+        let input = "0xDE-AG";
+        let output = to_bytes(input).unwrap_err();
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 6, character: Some('G') },
+            output
+        );
+    }
+
     #[test]
     fn bytes2hex() {
         let input = vec![0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x00];
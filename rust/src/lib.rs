@@ -1,7 +1,17 @@
+pub mod base32;
+pub mod base58;
 pub mod base64;
+pub mod base85;
 pub mod binary;
+pub mod cli;
+pub mod codec;
 pub mod decimal;
+pub mod dns;
+pub mod endian;
 pub mod hex;
+pub mod radix;
+pub mod stream;
+pub mod time;
 
 pub fn is_char_ignorable(c: u8) -> bool {
     return match c {
@@ -0,0 +1,276 @@
+use crate::codec::{Codec, DecodeError, DecodeErrorKind};
+use crate::decimal::{bytes_to_limbs, divide, limbs_to_bytes, multiply_add};
+
+pub const MIN_RADIX: usize = 2;
+pub const MAX_RADIX: usize = 36;
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+// The usual order for base 62, as used by GMP and most URL shorteners
+const BASE62_DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// A number written in any positional base, which is converted to and from its big-endian bytes
+// (without leading zeros) in the same way as decimal.
+#[derive(Clone, Debug)]
+pub struct Radix {
+    name: String,
+    aliases: &'static [&'static str],
+    // The digits in order of value, so the length is the radix
+    alphabet: Vec<u8>,
+    // Whether letters can be given in either case, which is only possible if the alphabet doesn't
+    // have both cases of the same letter
+    case_insensitive: bool,
+    // Written before the output, and skipped if present at the start of the input
+    prefix: &'static str,
+    // Whether the least significant byte comes first
+    little_endian: bool,
+}
+
+impl Radix {
+    pub fn new(radix: usize) -> Option<Radix> {
+        if !(MIN_RADIX..=MAX_RADIX).contains(&radix) {
+            return None;
+        }
+        Some(Radix {
+            name: format!("base{}", radix),
+            aliases: &[],
+            alphabet: DIGITS[..radix].to_vec(),
+            case_insensitive: true,
+            prefix: "",
+            little_endian: false,
+        })
+    }
+
+    pub fn octal() -> Radix {
+        Radix {
+            name: String::from("octal"),
+            aliases: &["8", "oct"],
+            prefix: "0o",
+            ..Radix::new(8).unwrap()
+        }
+    }
+
+    pub fn base62() -> Radix {
+        Radix {
+            aliases: &["62", "b62"],
+            ..Radix::with_alphabet("base62", BASE62_DIGITS)
+        }
+    }
+
+    // For radixes above MAX_RADIX, which need both cases of letters (or other characters) as
+    // digits. The alphabet must be valid as for set_alphabet.
+    pub(crate) fn with_alphabet(name: &str, alphabet: &[u8]) -> Radix {
+        Radix {
+            name: String::from(name),
+            aliases: &[],
+            alphabet: alphabet.to_vec(),
+            case_insensitive: !alphabet.iter().any(u8::is_ascii_lowercase) || !alphabet.iter().any(u8::is_ascii_uppercase),
+            prefix: "",
+            little_endian: false,
+        }
+    }
+
+    pub fn radix(&self) -> usize {
+        self.alphabet.len()
+    }
+
+    pub(crate) fn alphabet(&self) -> &[u8] {
+        &self.alphabet
+    }
+
+    // The alphabet must have one distinct ASCII character for each digit
+    pub fn set_alphabet(&mut self, alphabet: &str) -> Result<(), String> {
+        let bytes = alphabet.as_bytes();
+        if bytes.len() != self.radix() {
+            return Err(format!("The alphabet for {} must have {} characters, not {}", self.name, self.radix(), bytes.len()));
+        }
+        if let Some(c) = bytes.iter().find(|c| !c.is_ascii_graphic()) {
+            return Err(format!("The alphabet for {} can't contain {:?}", self.name, *c as char));
+        }
+
+        let mut case_insensitive = true;
+        for (i, c) in bytes.iter().enumerate() {
+            for other in &bytes[i + 1..] {
+                if c == other {
+                    return Err(format!("The alphabet for {} has {:?} more than once", self.name, *c as char));
+                }
+                if c.eq_ignore_ascii_case(other) {
+                    case_insensitive = false;
+                }
+            }
+        }
+
+        self.alphabet = bytes.to_vec();
+        self.case_insensitive = case_insensitive;
+        Ok(())
+    }
+
+    fn digit_value(&self, c: u8) -> Option<u32> {
+        self.alphabet
+            .iter()
+            .position(|&digit| (digit == c) || (self.case_insensitive && digit.eq_ignore_ascii_case(&c)))
+            .map(|value| value as u32)
+    }
+
+    // The largest number of digits whose value always fits into a single limb, and the radix to
+    // the power of that number
+    fn digits_per_limb(&self) -> (usize, u32) {
+        let radix = self.radix() as u64;
+        let mut count = 1;
+        let mut power = radix;
+        while power * radix <= (u32::MAX as u64) {
+            count += 1;
+            power *= radix;
+        }
+        (count, power as u32)
+    }
+}
+
+impl Codec for Radix {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        self.aliases
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("uppercase", "use uppercase letters for digits above 9"),
+            ("alphabet=DIGITS", "use these characters as the digits, in order of value"),
+            ("little-endian", "read and write the bytes least significant first"),
+        ]
+    }
+
+    fn set_option(&mut self, option: &str) -> Result<bool, String> {
+        if option == "little-endian" {
+            self.little_endian = true;
+            return Ok(true);
+        }
+        if option == "uppercase" {
+            if !self.case_insensitive {
+                return Err(format!("The digits of {} are case sensitive, so can't be made uppercase", self.name));
+            }
+            self.alphabet.make_ascii_uppercase();
+            return Ok(true);
+        }
+        match option.strip_prefix("alphabet=") {
+            Some(alphabet) => self.set_alphabet(alphabet).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn encode(&self, input: &[u8]) -> String {
+        let (chunk_length, chunk_base) = self.digits_per_limb();
+        let mut value = match self.little_endian {
+            true => bytes_to_limbs(&input.iter().rev().cloned().collect::<Vec<_>>()),
+            false => bytes_to_limbs(input),
+        };
+        let mut digits = Vec::new();
+        while !value.is_empty() {
+            let mut chunk = divide(&mut value, chunk_base);
+            for _ in 0..chunk_length {
+                digits.push(self.alphabet[(chunk as usize) % self.radix()]);
+                chunk /= self.radix() as u32;
+            }
+        }
+
+        // Every chunk was padded to its full length, so there may be leading zeros to remove
+        while digits.len() > 1 && (digits.last() == Some(&self.alphabet[0])) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            digits.push(self.alphabet[0]);
+        }
+        digits.reverse();
+
+        let mut output = String::from(self.prefix);
+        output.push_str(&String::from_utf8(digits).unwrap());
+        output
+    }
+
+    fn decode(&self, input: &str) -> Result<Vec<u8>, DecodeError> {
+        let prefix_length = self.prefix.len();
+        let start = match input.get(..prefix_length) {
+            Some(prefix) if !self.prefix.is_empty() && prefix.eq_ignore_ascii_case(self.prefix) => prefix_length,
+            _ => 0,
+        };
+
+        let (chunk_length, _) = self.digits_per_limb();
+        let bytes = &input.as_bytes()[start..];
+        let mut value = Vec::with_capacity(bytes.len() / chunk_length + 1);
+        for (chunk_index, chunk) in bytes.chunks(chunk_length).enumerate() {
+            let mut chunk_value = 0;
+            for (i, c) in chunk.iter().enumerate() {
+                match self.digit_value(*c) {
+                    Some(digit) => chunk_value = (chunk_value * self.radix() as u32) + digit,
+                    None => {
+                        let offset = start + (chunk_index * chunk_length) + i;
+                        return Err(DecodeError::at(DecodeErrorKind::InvalidDigit, input, offset));
+                    }
+                }
+            }
+            multiply_add(&mut value, (self.radix() as u32).pow(chunk.len() as u32), chunk_value);
+        }
+
+        let mut result = limbs_to_bytes(&value);
+        if self.little_endian {
+            result.reverse();
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod radix_tests {
+    use super::*;
+
+    #[test]
+    fn octal_with_and_without_prefix() {
+        let codec = Radix::octal();
+        assert_eq!(vec![0x01, 0xED], codec.decode("755").unwrap());
+        assert_eq!(vec![0x01, 0xED], codec.decode("0o755").unwrap());
+        assert_eq!("0o755", codec.encode(&[0x01, 0xED]));
+        assert_eq!("0o0", codec.encode(&[]));
+        let mut little_endian = Radix::octal();
+        little_endian.set_option("little-endian").unwrap();
+        assert_eq!("0o755", little_endian.encode(&[0xED, 0x01]));
+        assert_eq!(vec![0xED, 0x01], little_endian.decode("755").unwrap());
+        assert_eq!(
+            DecodeError { kind: DecodeErrorKind::InvalidDigit, offset: 4, character: Some('8') },
+            codec.decode("0o7589").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn base36_round_trip() {
+        let codec = Radix::new(36).unwrap();
+        let input = vec![0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x23, 0x45, 0x67, 0x89];
+        let output = codec.encode(&input);
+        assert_eq!(
+            "o2wcse7esfvh55",
+            output
+        );
+        assert_eq!(input, codec.decode(&output.to_uppercase()).unwrap());
+    }
+
+    #[test]
+    fn custom_alphabet() {
+        let mut codec = Radix::new(4).unwrap();
+        assert!(codec.set_option("alphabet=ACGT").unwrap());
+        assert_eq!("GATTACA", codec.encode(&codec.decode("GATTACA").unwrap()));
+        assert_eq!(vec![0x23, 0xC4], codec.decode("gattaca").unwrap());
+        assert!(codec.set_option("alphabet=ACG").is_err());
+        assert!(codec.set_option("alphabet=AACG").is_err());
+        assert!(Radix::new(37).is_none());
+    }
+
+    #[test]
+    fn base62_is_case_sensitive() {
+        let mut codec = Radix::base62();
+        assert_eq!("44pZgF", codec.encode(&[0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(vec![0xDE, 0xAD, 0xBE, 0xEF], codec.decode("44pZgF").unwrap());
+        assert_ne!(vec![0xDE, 0xAD, 0xBE, 0xEF], codec.decode("44PZGF").unwrap());
+        assert!(codec.set_option("uppercase").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::codec::{BlockCodec, DecodeError, DecodeErrorKind};
+use std::io::{self, Read, Write};
+
+// How much of the underlying stream is encoded or decoded at a time
+const CHUNK_SIZE: usize = 8192;
+
+// Encodes everything written to it and writes the result to the inner writer. finish() must be
+// called to write the final (partial) block, otherwise it's written when the Encoder is dropped and
+// any error is lost.
+pub struct Encoder<W: Write> {
+    writer: Option<W>,
+    codec: Box<dyn BlockCodec>,
+    // Bytes which don't yet make up a whole block
+    pending: Vec<u8>,
+    // The number of characters written to the current line, if the output is split into lines
+    column: usize,
+    started: bool,
+    output: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, codec: Box<dyn BlockCodec>) -> Encoder<W> {
+        Encoder {
+            writer: Some(writer),
+            codec,
+            pending: Vec::new(),
+            column: 0,
+            started: false,
+            output: Vec::with_capacity(CHUNK_SIZE * 2),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_final_block()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_final_block(&mut self) -> io::Result<()> {
+        self.start();
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.encode(&pending);
+        }
+        self.write_output()
+    }
+
+    fn start(&mut self) {
+        if !self.started {
+            self.output.extend_from_slice(self.codec.prefix());
+            self.started = true;
+        }
+    }
+
+    // Encodes a single block into the output buffer, splitting it into lines if needed
+    fn encode(&mut self, block: &[u8]) {
+        let line_length = match self.codec.line_length() {
+            Some(length) if length > 0 => length,
+            _ => {
+                self.codec.encode_block(block, &mut self.output);
+                return;
+            }
+        };
+
+        let start = self.output.len();
+        self.codec.encode_block(block, &mut self.output);
+        let encoded = self.output.split_off(start);
+        for c in encoded {
+            if self.column == line_length {
+                self.output.extend_from_slice(b"\r\n");
+                self.column = 0;
+            }
+            self.output.push(c);
+            self.column += 1;
+        }
+    }
+
+    fn write_output(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(&self.output)?;
+        self.output.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.start();
+        let (block_size, _) = self.codec.block_sizes();
+        let mut input = buf;
+
+        // Complete the partial block left over from last time
+        if !self.pending.is_empty() {
+            let needed = (block_size - self.pending.len()).min(input.len());
+            self.pending.extend_from_slice(&input[..needed]);
+            input = &input[needed..];
+            if self.pending.len() < block_size {
+                return Ok(buf.len());
+            }
+            let pending = std::mem::take(&mut self.pending);
+            self.encode(&pending);
+        }
+
+        for chunk in input.chunks(CHUNK_SIZE - (CHUNK_SIZE % block_size)) {
+            let whole_blocks = chunk.len() - (chunk.len() % block_size);
+            for block in chunk[..whole_blocks].chunks(block_size) {
+                self.encode(block);
+            }
+            self.pending.extend_from_slice(&chunk[whole_blocks..]);
+            self.write_output()?;
+        }
+        self.write_output()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_output()?;
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_final_block();
+        }
+    }
+}
+
+// Reads encoded text from the inner reader and returns the decoded bytes. Decoding errors are
+// returned as io::ErrorKind::InvalidData errors that wrap a DecodeError, with offsets counted from
+// the start of the stream.
+pub struct Decoder<R: Read> {
+    reader: R,
+    codec: Box<dyn BlockCodec>,
+    // The characters of the current block which have been read so far, and their offsets
+    pending: Vec<u8>,
+    pending_offsets: Vec<usize>,
+    // The offset in the stream of the next byte to be read
+    offset: usize,
+    prefix_checked: bool,
+    // Set after a padded block, which must be the last one
+    padded: bool,
+    done: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    output_position: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R, codec: Box<dyn BlockCodec>) -> Decoder<R> {
+        let prefix_checked = codec.prefix().is_empty();
+        Decoder {
+            reader,
+            codec,
+            pending: Vec::new(),
+            pending_offsets: Vec::new(),
+            offset: 0,
+            prefix_checked,
+            padded: false,
+            done: false,
+            input: vec![0; CHUNK_SIZE],
+            output: Vec::with_capacity(CHUNK_SIZE),
+            output_position: 0,
+        }
+    }
+
+    fn decode_pending(&mut self) -> io::Result<()> {
+        if let Err((index, kind)) = self.codec.decode_block(&self.pending, &mut self.output) {
+            return Err(match self.pending.get(index) {
+                Some(&c) => decode_error(kind, self.pending_offsets[index], Some(c as char)),
+                None => decode_error(kind, self.offset, None),
+            });
+        }
+        self.padded = self.codec.is_padded(&self.pending);
+        self.pending.clear();
+        self.pending_offsets.clear();
+        Ok(())
+    }
+
+    // Reads and decodes the next chunk of the underlying stream
+    fn fill_output(&mut self) -> io::Result<()> {
+        let (_, block_size) = self.codec.block_sizes();
+        let count = self.reader.read(&mut self.input)?;
+        if count == 0 {
+            self.done = true;
+            if !self.pending.is_empty() {
+                self.decode_pending()?;
+            }
+            return Ok(());
+        }
+
+        for i in 0..count {
+            let c = self.input[i];
+            let offset = self.offset;
+            self.offset += 1;
+            if self.codec.is_ignorable(c) {
+                continue;
+            }
+            if !c.is_ascii() && self.codec.is_ascii_only() {
+                let character = String::from_utf8_lossy(&self.input[i..count]).chars().next();
+                return Err(decode_error(DecodeErrorKind::InvalidDigit, offset, character));
+            }
+            if self.padded {
+                return Err(decode_error(DecodeErrorKind::BadPadding, offset, Some(c as char)));
+            }
+
+            self.pending.push(c);
+            self.pending_offsets.push(offset);
+            if !self.prefix_checked && (self.pending.len() == self.codec.prefix().len()) {
+                self.prefix_checked = true;
+                if self.pending == self.codec.prefix() {
+                    self.pending.clear();
+                    self.pending_offsets.clear();
+                    continue;
+                }
+            }
+            if self.pending.len() == block_size {
+                self.decode_pending()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode_error(kind: DecodeErrorKind, offset: usize, character: Option<char>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        DecodeError { kind, offset, character },
+    )
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_position == self.output.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.output.clear();
+            self.output_position = 0;
+            self.fill_output()?;
+        }
+
+        let count = buf.len().min(self.output.len() - self.output_position);
+        buf[..count].copy_from_slice(&self.output[self.output_position..self.output_position + count]);
+        self.output_position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use super::*;
+    use crate::base64::Base64;
+    use crate::hex::Hex;
+
+    // Reads from the inner reader a few bytes at a time, to test blocks split across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = buf.len().min(3).min(self.0.len());
+            buf[..count].copy_from_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Ok(count)
+        }
+    }
+
+    fn encode(input: &[u8], codec: Box<dyn BlockCodec>) -> String {
+        let mut encoder = Encoder::new(Vec::new(), codec);
+        for chunk in input.chunks(2) {
+            encoder.write_all(chunk).unwrap();
+        }
+        String::from_utf8(encoder.finish().unwrap()).unwrap()
+    }
+
+    fn decode(input: &str, codec: Box<dyn BlockCodec>) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        Decoder::new(Trickle(input.as_bytes()), codec).read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn encode_across_writes() {
+        let input = vec![0xDE, 0xAD, 0xBE, 0xEF, 0x09];
+        assert_eq!(
+            "0xDEADBEEF09",
+            encode(&input, Box::new(Hex::default()))
+        );
+        assert_eq!(
+            "3q2+7wk=",
+            encode(&input, Box::new(Base64::default()))
+        );
+        assert_eq!(
+            format!("{}\r\n{}", "A".repeat(76), "AA=="),
+            encode(&[0; 58], Box::new(Base64::mime()))
+        );
+    }
+
+    #[test]
+    fn decode_across_reads() {
+        assert_eq!(
+            vec![0xDE, 0xAD, 0xBE, 0xEF, 0x09],
+            decode("0xDE AD\nBE-EF09\n", Box::new(Hex::default())).unwrap()
+        );
+        assert_eq!(
+            vec![0xDE, 0xAD, 0xBE, 0xEF, 0x09],
+            decode("3q2+\n7wk=\n", Box::new(Base64::default())).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_errors_have_stream_offsets() {
+        let error = decode("3q2+\n7wk=\nAAAA", Box::new(Base64::default())).unwrap_err();
+        assert_eq!(
+            "bad padding 'A' at offset 10",
+            error.to_string()
+        );
+        let error = decode("0xDEADBEE\n", Box::new(Hex::default())).unwrap_err();
+        assert_eq!(
+            "bad length at end of input (offset 10)",
+            error.to_string()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Duration, Local, SubsecRound, Utc};
+
+const UNITS: [(u64, &str, &str); 4] = [
+    (24 * 60 * 60, "day", "d"),
+    (60 * 60, "hour", "h"),
+    (60, "minute", "m"),
+    (1, "second", "s"),
+];
+
+// Splits a number of seconds into (count, long name, short name) for each non-zero unit, largest first
+fn split_units(total_sec: u64) -> Vec<(u64, &'static str, &'static str)> {
+    let mut result = Vec::new();
+    let mut remaining = total_sec;
+    for &(size, name, short_name) in UNITS.iter() {
+        let count = remaining / size;
+        remaining %= size;
+        if count != 0 {
+            result.push((count, name, short_name));
+        }
+    }
+    result
+}
+
+// e.g "1 day, 3 hours, 1 second"
+pub fn humanize_duration(total_sec: u64) -> String {
+    let parts: Vec<String> = split_units(total_sec).iter().map(|&(count, name, _)| {
+        if count == 1 {
+            format!("1 {}", name)
+        } else {
+            format!("{} {}s", count, name)
+        }
+    }).collect();
+
+    if parts.is_empty() {
+        return String::from("0 seconds");
+    }
+    parts.join(", ")
+}
+
+// e.g "1d3h1s"
+pub fn compact_duration(total_sec: u64) -> String {
+    let parts: Vec<String> = split_units(total_sec).iter()
+        .map(|&(count, _, short_name)| format!("{}{}", count, short_name))
+        .collect();
+
+    if parts.is_empty() {
+        return String::from("0s");
+    }
+    parts.concat()
+}
+
+// Uses the same format as chrono's Display (e.g "2024-01-01 00:00:00 UTC" or "2024-01-01 01:00:00 +01:00")
+pub fn format_timestamp(time: DateTime<Utc>, local: bool) -> String {
+    if local {
+        time.with_timezone(&Local).to_string()
+    } else {
+        time.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtlFormat {
+    Human,
+    Compact,
+    Seconds,
+    // The time at which the TTL runs out, counting from when the record was received
+    ExpiryUtc,
+    ExpiryLocal,
+}
+
+impl TtlFormat {
+    pub const NAMES: [&'static str; 5] = ["human", "compact", "seconds", "utc", "local"];
+
+    pub fn from_name(name: &str) -> Option<TtlFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "human" => Some(TtlFormat::Human),
+            "compact" => Some(TtlFormat::Compact),
+            "seconds" | "raw" => Some(TtlFormat::Seconds),
+            "utc" => Some(TtlFormat::ExpiryUtc),
+            "local" => Some(TtlFormat::ExpiryLocal),
+            _ => None,
+        }
+    }
+
+    pub fn is_expiry(&self) -> bool {
+        matches!(self, TtlFormat::ExpiryUtc | TtlFormat::ExpiryLocal)
+    }
+
+    // For lengths of time that aren't counting down from anything (e.g the SOA refresh interval),
+    // so there's no expiry time to show and the human-readable form is used instead.
+    pub fn format_duration(&self, total_sec: u32) -> String {
+        match self {
+            TtlFormat::Compact => compact_duration(total_sec as u64),
+            TtlFormat::Seconds => total_sec.to_string(),
+            _ => humanize_duration(total_sec as u64),
+        }
+    }
+
+    pub fn format_ttl(&self, ttl: u32, received: DateTime<Utc>) -> String {
+        // TTLs only have a resolution of a second, so anything smaller is just noise
+        let expiry = received.trunc_subsecs(0) + Duration::seconds(ttl as i64);
+        match self {
+            TtlFormat::ExpiryUtc => format_timestamp(expiry, false),
+            TtlFormat::ExpiryLocal => format_timestamp(expiry, true),
+            _ => self.format_duration(ttl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod time_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn humanize_durations() {
+        assert_eq!(
+            "0 seconds",
+            humanize_duration(0)
+        );
+        assert_eq!(
+            "1 hour, 3 minutes",
+            humanize_duration(3780)
+        );
+        assert_eq!(
+            "2 days, 1 second",
+            humanize_duration(2 * 86400 + 1)
+        );
+    }
+
+    #[test]
+    fn compact_durations() {
+        assert_eq!(
+            "0s",
+            compact_duration(0)
+        );
+        assert_eq!(
+            "1h3m",
+            compact_duration(3780)
+        );
+        assert_eq!(
+            "1d1h1m1s",
+            compact_duration(90061)
+        );
+    }
+
+    #[test]
+    fn format_ttl_modes() {
+        let received = Utc.with_ymd_and_hms(2024, 1, 1, 23, 30, 0).unwrap();
+        assert_eq!("3600", TtlFormat::Seconds.format_ttl(3600, received));
+        assert_eq!("1h", TtlFormat::Compact.format_ttl(3600, received));
+        assert_eq!(
+            "2024-01-02 00:30:00 UTC",
+            TtlFormat::ExpiryUtc.format_ttl(3600, received)
+        );
+        assert_eq!("1 hour", TtlFormat::ExpiryUtc.format_duration(3600));
+        assert_eq!(Some(TtlFormat::ExpiryLocal), TtlFormat::from_name("LOCAL"));
+        assert_eq!(None, TtlFormat::from_name("fortnights"));
+    }
+}
@@ -0,0 +1,56 @@
+use std::process::{Command, Output, Stdio};
+
+fn convertbase(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_convertbase"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn little_endian_input_to_big_endian_output() {
+    // 258 is 0x0102, so read least significant byte first it's 0x02 0x01
+    let output = convertbase(&["-f", "dec", "-t", "hex", "--from-option", "little-endian", "258"]);
+    assert!(output.status.success());
+    assert_eq!(
+        "0x0201\n",
+        stdout(&output)
+    );
+
+    let output = convertbase(&["-f", "8", "-t", "dec", "--from-option", "little-endian", "0o1000"]);
+    assert!(output.status.success());
+    assert_eq!(
+        "2\n",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn big_endian_input_to_little_endian_output() {
+    let output = convertbase(&["-f", "hex", "-t", "dec", "--to-option", "little-endian", "0x0102"]);
+    assert!(output.status.success());
+    assert_eq!(
+        "513\n",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn options_both_bases_have_must_say_which() {
+    let output = convertbase(&["-f", "8", "-t", "dec", "-o", "little-endian", "0o1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--from-option"));
+
+    // Only base 4 can have an alphabet of four digits
+    let output = convertbase(&["-f", "4", "-t", "36", "-o", "alphabet=ACGT", "GATTACA"]);
+    assert!(output.status.success());
+    assert_eq!(
+        convertbase(&["-f", "4", "-t", "36", "2033010"]).stdout,
+        output.stdout
+    );
+}